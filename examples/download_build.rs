@@ -21,6 +21,7 @@ async fn main() {
         if let ItemProgress::Downloading {
             bytes_downloaded,
             total_bytes,
+            ..
         } = progress
         {
             if bytes_downloaded == total_bytes {
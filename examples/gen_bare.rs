@@ -8,7 +8,7 @@ async fn main() {
 
     let asset_url = "http://example.url/builds/example_build/";
     let description = Some("example-build");
-    let version = Version::build_barebones(asset_url, description);
+    let version = Version::build_barebones(asset_url, description).unwrap();
 
     let outfile = "manifest.json";
     version.export_manifest(outfile).unwrap();
@@ -18,7 +18,7 @@ async fn main() {
 
     let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
     let test_asset_url = util::file_path_to_uri(good_build_path.to_str().unwrap());
-    version.set_asset_url(&test_asset_url);
+    version.set_asset_url(&test_asset_url).unwrap();
     info!("Overrode asset URL: {}", test_asset_url);
 
     let tmp = TempDir::new();
@@ -0,0 +1,246 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    util::{self, HashAlgorithm},
+    Error, FileInfo, OperationOptions, Version,
+};
+
+const ARCHIVE_MAGIC: &[u8; 4] = b"FFBA";
+const ARCHIVE_FORMAT_VERSION: u32 = 1;
+// the bundles are already LZMA-compressed, so a higher level buys very little
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 3;
+
+/// Lists the files in an archive, in the order their data follows the index.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveIndex {
+    uuid: Uuid,
+    files: Vec<ArchiveEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveEntry {
+    name: String,
+    size: u64,
+}
+
+impl Version {
+    /// Packs the main file and all compressed asset bundles in the local build root into a single
+    /// zstd-compressed archive at `archive_path`, for one-shot full downloads. Each file must pass
+    /// validation first. The archive's size and hash are recorded in the `Version`.
+    pub fn export_archive(&mut self, path: &str, archive_path: &str) -> Result<(), Error> {
+        info!(
+            "[{}] Packing build in {} into {}",
+            self.uuid, path, archive_path
+        );
+        let mut files = self.get_archive_files();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let hash_cache = OperationOptions::default().get_hash_cache();
+
+        let mut entries = Vec::with_capacity(files.len());
+        for (name, expected) in &files {
+            let file_path = PathBuf::from(path).join(name);
+            FileInfo::try_build_file(
+                &file_path,
+                HashAlgorithm::of(&expected.hash),
+                hash_cache.as_deref(),
+                None,
+            )
+            .and_then(|actual| actual.validate(expected))
+            .map_err(|e| format!("{} failed validation: {}", name, e))?;
+            entries.push(ArchiveEntry {
+                name: name.clone(),
+                size: expected.size,
+            });
+        }
+        let index = serde_json::to_vec(&ArchiveIndex {
+            uuid: self.uuid,
+            files: entries,
+        })?;
+
+        let file = BufWriter::new(File::create(archive_path)?);
+        let mut encoder = zstd::Encoder::new(file, ARCHIVE_COMPRESSION_LEVEL)?;
+        encoder.write_all(ARCHIVE_MAGIC)?;
+        encoder.write_all(&ARCHIVE_FORMAT_VERSION.to_le_bytes())?;
+        encoder.write_all(&(index.len() as u32).to_le_bytes())?;
+        encoder.write_all(&index)?;
+        for (name, _) in &files {
+            debug!("[{}] Packing {}", self.uuid, name);
+            let mut reader = BufReader::new(File::open(PathBuf::from(path).join(name))?);
+            std::io::copy(&mut reader, &mut encoder)?;
+        }
+        encoder.finish()?.flush()?;
+
+        let archive_info = FileInfo::try_build_file(
+            Path::new(archive_path),
+            HashAlgorithm::Sha256,
+            hash_cache.as_deref(),
+            None,
+        )
+        .map_err(|e| format!("Couldn't read archive {}: {}", archive_path, e))?;
+        info!(
+            "[{}] Packed {} files into {} bytes",
+            self.uuid,
+            files.len(),
+            archive_info.size
+        );
+        self.archive_info = Some(archive_info);
+        Ok(())
+    }
+
+    /// Unpacks an archive created with `export_archive` into `path`, creating it if needed.
+    /// If the `Version` has archive info, the archive is validated against it first, and every
+    /// unpacked file is validated against the metadata. A file that fails validation is removed.
+    /// Returns the names of the unpacked files.
+    pub fn import_archive(&self, archive_path: &str, path: &str) -> Result<Vec<String>, Error> {
+        self.import_archive_with_options(archive_path, path, &OperationOptions::default())
+    }
+
+    /// Same as `import_archive`, but with settings that override the global ones.
+    pub fn import_archive_with_options(
+        &self,
+        archive_path: &str,
+        path: &str,
+        options: &OperationOptions,
+    ) -> Result<Vec<String>, Error> {
+        info!("[{}] Unpacking {} into {}", self.uuid, archive_path, path);
+        let hash_cache = options.get_hash_cache();
+        let pacer = options.get_validation_pacer();
+        if let Some(expected) = &self.archive_info {
+            FileInfo::try_build_file(
+                Path::new(archive_path),
+                HashAlgorithm::of(&expected.hash),
+                hash_cache.as_deref(),
+                pacer.as_deref(),
+            )
+            .and_then(|actual| actual.validate(expected))
+            .map_err(|e| format!("Archive failed validation: {}", e))?;
+        }
+
+        let expected_files: std::collections::HashMap<String, FileInfo> =
+            self.get_archive_files().into_iter().collect();
+        let archive_file = File::open(archive_path)?;
+        let archive_size = archive_file.metadata()?.len();
+        let mut decoder = zstd::Decoder::new(archive_file)?;
+        let mut magic = [0; 4];
+        decoder.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(format!("{} isn't a build archive", archive_path).into());
+        }
+        let format_version = read_u32(&mut decoder)?;
+        if format_version != ARCHIVE_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported archive format version: {}, expected {}",
+                format_version, ARCHIVE_FORMAT_VERSION
+            )
+            .into());
+        }
+        // the length isn't trusted for an allocation; the index can't outgrow the whole archive
+        let index_len = read_u32(&mut decoder)? as u64;
+        if index_len > archive_size {
+            return Err(format!(
+                "Archive index is {} bytes, more than the {} byte archive",
+                index_len, archive_size
+            )
+            .into());
+        }
+        let mut index = Vec::new();
+        (&mut decoder).take(index_len).read_to_end(&mut index)?;
+        if index.len() as u64 != index_len {
+            return Err("Archive truncated while reading the index".into());
+        }
+        let index: ArchiveIndex = serde_json::from_slice(&index)?;
+        if index.uuid != self.uuid {
+            return Err(format!("Archive is for build {}, not {}", index.uuid, self.uuid).into());
+        }
+
+        std::fs::create_dir_all(path)?;
+        let mut unpacked = Vec::with_capacity(index.files.len());
+        for entry in index.files {
+            let Some(expected) = expected_files.get(&entry.name) else {
+                return Err(format!("{} isn't part of the build", entry.name).into());
+            };
+            let file_path = PathBuf::from(path).join(&entry.name);
+            debug!("[{}] Unpacking {}", self.uuid, entry.name);
+            if let Err(e) = unpack_file(
+                &mut decoder,
+                &file_path,
+                &entry,
+                expected,
+                hash_cache.as_deref(),
+                pacer.as_deref(),
+            ) {
+                // don't leave a partial or corrupted file behind for something to pick up
+                if let Err(remove_err) = std::fs::remove_file(&file_path) {
+                    warn!(
+                        "[{}] Couldn't remove {}: {}",
+                        self.uuid,
+                        file_path.display(),
+                        remove_err
+                    );
+                }
+                return Err(e);
+            }
+            unpacked.push(entry.name);
+        }
+        info!("[{}] Unpacked {} files", self.uuid, unpacked.len());
+        Ok(unpacked)
+    }
+
+    /// Returns the expected info of the archive created with `export_archive`, if there is one.
+    pub fn get_archive_info(&self) -> Option<&FileInfo> {
+        self.archive_info.as_ref()
+    }
+
+    fn get_archive_files(&self) -> Vec<(String, FileInfo)> {
+        let mut files: Vec<(String, FileInfo)> = self
+            .bundles
+            .iter()
+            .map(|(name, bundle_info)| (name.clone(), bundle_info.compressed_info.clone()))
+            .collect();
+        if let Some(main_file_info) = &self.main_file_info {
+            files.push((
+                self.get_main_file_name().to_string(),
+                main_file_info.clone(),
+            ));
+        }
+        files
+    }
+}
+
+fn unpack_file<R: Read>(
+    reader: &mut R,
+    file_path: &Path,
+    entry: &ArchiveEntry,
+    expected: &FileInfo,
+    hash_cache: Option<&util::HashCache>,
+    pacer: Option<&util::IoPacer>,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(file_path)?);
+    let copied = std::io::copy(&mut reader.take(entry.size), &mut writer)?;
+    writer.flush()?;
+    if copied != entry.size {
+        return Err(format!("Archive truncated while unpacking {}", entry.name).into());
+    }
+    FileInfo::try_build_file(
+        file_path,
+        HashAlgorithm::of(&expected.hash),
+        hash_cache,
+        pacer,
+    )
+    .and_then(|actual| actual.validate(expected))
+    .map_err(|e| format!("{} failed validation: {}", entry.name, e).into())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
@@ -1,13 +1,21 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex, OnceLock},
-    time::Duration,
+    convert::Infallible,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use http_body_util::{combinators::BoxBody, BodyExt as _, Full};
+use hyper::body::Bytes;
 
-use ffbuildtool::{ItemProgress, Version};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use ffbuildtool::{ItemProgress, OperationOptions, TotalProgress, Version};
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
 use uuid::Uuid;
 
 #[derive(Parser, Debug)]
@@ -15,6 +23,73 @@ use uuid::Uuid;
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Path to a JSON file used to cache file hashes between runs
+    #[clap(long, global = true)]
+    hash_cache: Option<String>,
+
+    /// Directory to store temporary files in instead of the system temp directory
+    #[clap(long, global = true)]
+    temp_dir: Option<PathBuf>,
+
+    /// Directory of bundles shared between builds, keyed by hash, to check before downloading
+    #[clap(long, global = true)]
+    bundle_store: Option<PathBuf>,
+
+    /// Check the size of each file on the mirror before downloading it, failing fast if it doesn't match the manifest
+    #[clap(long, global = true)]
+    check_mirror_size: bool,
+
+    /// Maximum number of files to process at once. Unlimited if not set
+    #[clap(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>,
+
+    /// Maximum number of files to download at once. Unlimited if not set
+    #[clap(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    download_jobs: Option<u32>,
+
+    /// Download the main file alongside the bundles with its own download slot, instead of first and alone
+    #[clap(long, global = true)]
+    main_file_alongside: bool,
+
+    /// Retry a download if the server doesn't respond or send data for this many seconds
+    #[clap(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    download_timeout: Option<u64>,
+
+    /// Retry a download if it takes longer than this many seconds in total
+    #[clap(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    download_deadline: Option<u64>,
+
+    /// How to treat symlinks when listing and copying build directories
+    #[clap(long, global = true, value_enum, default_value_t = SymlinkArg::Follow)]
+    symlinks: SymlinkArg,
+
+    /// How to report progress. `json` prints one JSON object per line to stderr for each progress event,
+    /// keeping it apart from the regular output on stdout
+    #[clap(long, global = true, value_enum, default_value_t = ProgressFormat::Bars)]
+    progress: ProgressFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ProgressFormat {
+    Bars,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SymlinkArg {
+    Follow,
+    Skip,
+    Error,
+}
+impl From<SymlinkArg> for ffbuildtool::SymlinkPolicy {
+    fn from(arg: SymlinkArg) -> Self {
+        match arg {
+            SymlinkArg::Follow => Self::Follow,
+            SymlinkArg::Skip => Self::Skip,
+            SymlinkArg::Error => Self::Error,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -23,12 +98,23 @@ enum Commands {
     DownloadBuild(DownloadBuildArgs),
     RepairBuild(RepairBuildArgs),
     ValidateBuild(ValidateBuildArgs),
+    ValidateAll(ValidateAllArgs),
+    Stats(StatsArgs),
+    ConvertManifest(ConvertManifestArgs),
+    Install(InstallArgs),
+    Serve(ServeArgs),
+    #[cfg(feature = "s3")]
+    Upload(UploadArgs),
     #[cfg(feature = "lzma")]
     ReadBundle(ReadBundleArgs),
     #[cfg(feature = "lzma")]
     ExtractBundle(ExtractBundleArgs),
     #[cfg(feature = "lzma")]
     PackBundle(PackBundleArgs),
+    #[cfg(feature = "lzma")]
+    DiffBundle(DiffBundleArgs),
+    #[cfg(feature = "lzma")]
+    VerifyBundle(VerifyBundleArgs),
 }
 
 #[derive(Args, Debug)]
@@ -53,6 +139,10 @@ struct GenManifestArgs {
     #[clap(short = 'p', long)]
     parent: Option<String>,
 
+    /// File name of the main file in the build, if it isn't `main.unity3d`
+    #[clap(long)]
+    main_file_name: Option<String>,
+
     /// Path to the output manifest file
     #[clap(short = 'o', long)]
     output_path: String,
@@ -60,6 +150,28 @@ struct GenManifestArgs {
     /// Whether the version should be marked as hidden
     #[clap(long)]
     hidden: bool,
+
+    /// Minimum client version required to run the build
+    #[clap(long)]
+    min_client_version: Option<String>,
+
+    /// Whether to store each bundle's uncompressed file info as a compressed blob to shrink the manifest
+    #[clap(long)]
+    pack_uncompressed_info: bool,
+
+    /// Path or URL of a manifest to validate the build against while generating the new one.
+    /// No manifest is written if any file fails validation.
+    #[clap(long)]
+    validate_against: Option<String>,
+
+    /// Additional file extension to treat as an asset bundle (e.g. `assetbundle`). Can be repeated.
+    #[clap(short = 'e', long = "extension")]
+    extensions: Vec<String>,
+
+    /// Fail without writing the manifest if anything suspicious is found in the build,
+    /// e.g. a bundle whose header doesn't match the file
+    #[clap(long)]
+    deny_warnings: bool,
 }
 
 #[derive(Args, Debug)]
@@ -71,6 +183,10 @@ struct DownloadBuildArgs {
     /// Path to the directory where all the compressed asset bundles in the build, along with the main file, will be downloaded
     #[clap(short = 'o', long)]
     output_path: String,
+
+    /// Keep valid files already in the output directory and only download missing or corrupted ones
+    #[clap(short = 'r', long)]
+    resume: bool,
 }
 
 #[derive(Args, Debug)]
@@ -82,6 +198,18 @@ struct RepairBuildArgs {
     /// Path to the directory containing the compressed asset bundles in the build
     #[clap(short = 'p', long)]
     build_path: String,
+
+    /// List the bundles that would be re-downloaded without downloading anything
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Write the downloads needed to repair the build to a JSON file instead of repairing it
+    #[clap(long, conflicts_with = "dry_run")]
+    script: Option<String>,
+
+    /// Directory of another build to copy matching files from before downloading them. Can be repeated
+    #[clap(short = 'f', long = "fallback")]
+    fallback_dirs: Vec<String>,
 }
 
 #[derive(Args, Debug)]
@@ -97,6 +225,132 @@ struct ValidateBuildArgs {
     /// Flag indicating that the bundles are uncompressed
     #[clap(short = 'u', long)]
     uncompressed: bool,
+
+    /// Flag indicating that uncompressed bundles were extracted to directories named exactly after
+    /// the bundles instead of their url-encoded names
+    #[clap(long, requires = "uncompressed")]
+    raw_dir_names: bool,
+
+    /// Flag indicating that files in the extracted bundle directories that aren't in the manifest
+    /// should be reported as corrupted
+    #[clap(long, requires = "uncompressed")]
+    report_unexpected: bool,
+
+    /// Path to write a JSON validation report to. Only supported for compressed builds.
+    #[clap(short = 'r', long)]
+    report: Option<String>,
+
+    /// Only check these compressed bundles (and the main file, if it's named)
+    #[clap(long, num_args = 1.., conflicts_with_all = ["uncompressed", "report"])]
+    only: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct ValidateAllArgs {
+    /// Path to a directory of manifest files
+    #[clap(short = 'm', long)]
+    manifests_path: String,
+
+    /// Path to the directory containing each build's compressed asset bundles in a subdirectory named after its UUID
+    #[clap(short = 'p', long)]
+    builds_path: String,
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Path or URL of the manifest
+    #[clap(short = 'm', long)]
+    manifest_path: String,
+
+    /// Print the statistics as a JSON object instead
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ManifestKind {
+    Compressed,
+    Uncompressed,
+}
+
+#[derive(Args, Debug)]
+struct ConvertManifestArgs {
+    /// Path to the manifest to convert. For `--to uncompressed`, this may also be a URL
+    #[clap(short = 'i', long = "input")]
+    input_path: String,
+
+    /// Path to write the converted manifest to
+    #[clap(short = 'o', long = "output")]
+    output_path: String,
+
+    /// The representation to convert to. `uncompressed` lists every extracted file by its path
+    /// relative to the extraction directory; `compressed` groups them back into bundles
+    #[clap(long, value_enum)]
+    to: ManifestKind,
+}
+
+#[derive(Args, Debug)]
+struct InstallArgs {
+    /// Path to the manifest file
+    #[clap(short = 'm', long = "manifest")]
+    manifest_path: String,
+
+    /// Path to the directory where the build will be downloaded
+    #[clap(short = 'o', long = "output")]
+    output_path: String,
+
+    /// Path to a directory to extract the downloaded asset bundles into
+    #[cfg(feature = "lzma")]
+    #[clap(short = 'x', long)]
+    extract_path: Option<String>,
+
+    /// Additional file extension to extract as an asset bundle (e.g. `assetbundle`). Can be repeated.
+    #[cfg(feature = "lzma")]
+    #[clap(short = 'e', long = "extension")]
+    extensions: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Path to the manifest file
+    #[clap(short = 'm', long)]
+    manifest_path: String,
+
+    /// Path to the directory containing the compressed asset bundles in the build
+    #[clap(short = 'p', long)]
+    build_path: String,
+
+    /// Port to listen on
+    #[clap(long, default_value = "8080")]
+    port: u16,
+}
+
+#[cfg(feature = "s3")]
+#[derive(Args, Debug)]
+struct UploadArgs {
+    /// Path to the manifest file. It is uploaded alongside the build.
+    #[clap(short = 'm', long)]
+    manifest_path: String,
+
+    /// Path to the directory containing the compressed asset bundles in the build
+    #[clap(short = 'p', long)]
+    build_path: String,
+
+    /// Name of the bucket to upload to
+    #[clap(short = 'b', long)]
+    bucket: String,
+
+    /// Endpoint URL of the S3-compatible service. Defaults to AWS.
+    #[clap(short = 'e', long)]
+    endpoint: Option<String>,
+
+    /// Region of the bucket
+    #[clap(short = 'r', long, default_value = "us-east-1")]
+    region: String,
+
+    /// Key prefix to upload the build under
+    #[clap(long, default_value = "")]
+    prefix: String,
 }
 
 #[cfg(feature = "lzma")]
@@ -121,12 +375,48 @@ struct ExtractBundleArgs {
     /// Path to the output directory. If not specified, will be extracted to a directory named after the bundle.
     #[clap(short = 'o', long)]
     output_dir: Option<String>,
+
+    /// Only extract the files in this level, without a level subdirectory
+    #[clap(short = 'l', long)]
+    level: Option<usize>,
+
+    /// Extract the levels that can be read from a partially corrupt bundle instead of failing
+    #[clap(long)]
+    lenient: bool,
+
+    /// Write files on this many threads at once (0 for one per CPU) instead of one at a time
+    #[clap(long, conflicts_with = "level")]
+    threads: Option<usize>,
+
+    /// Put the files of every level directly in the output directory, prefixed with `levelN__`
+    #[clap(long, conflicts_with_all = ["level", "threads"])]
+    flat: bool,
+}
+
+#[cfg(feature = "lzma")]
+#[derive(Args, Debug)]
+struct DiffBundleArgs {
+    /// Path to the first compressed asset bundle
+    #[clap(short = 'a', long)]
+    first_bundle: String,
+
+    /// Path to the second compressed asset bundle
+    #[clap(short = 'b', long)]
+    second_bundle: String,
+}
+
+#[cfg(feature = "lzma")]
+#[derive(Args, Debug)]
+struct VerifyBundleArgs {
+    /// Path to the compressed asset bundle to extract and repack
+    #[clap(short = 'i', long)]
+    input_bundle: String,
 }
 
 #[cfg(feature = "lzma")]
 #[derive(Args, Debug)]
 struct PackBundleArgs {
-    /// Path to the input directory
+    /// Path to the input directory. If it contains a layout.json, only the files listed in it are packed, in order
     #[clap(short = 'i', long)]
     input_dir: String,
 
@@ -137,6 +427,22 @@ struct PackBundleArgs {
     /// Compression level to use
     #[clap(short = 'l', long, default_value = "4")]
     compression_level: u32,
+
+    /// LZMA dictionary size in bytes. Larger dictionaries may compress better but need more memory to load.
+    #[clap(long, default_value = "8388608")]
+    dict_size: u32,
+
+    /// Fail if any level would contain duplicate file names
+    #[clap(short = 's', long)]
+    strict: bool,
+
+    /// Player version to write to the bundle header. Defaults to the original client's.
+    #[clap(long)]
+    player_version: Option<String>,
+
+    /// Engine version to write to the bundle header. Defaults to the original client's.
+    #[clap(long)]
+    engine_version: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -145,17 +451,209 @@ enum ItemState {
     Validating,
 }
 
+/// How often the progress bars are redrawn. Updates in between are coalesced.
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+/// How many progress events can be waiting to be drawn before intermediate updates are dropped.
+const PROGRESS_QUEUE_SIZE: usize = 1024;
+
+enum ProgressEvent {
+    Item(String, ItemProgress),
+    Total(TotalProgress),
+    /// Draws any pending updates, then signals the sender.
+    Flush(mpsc::Sender<()>),
+    FinishTotal(mpsc::Sender<()>),
+}
+
+/// The receiving end of the progress queue, which counts events out as they're received.
+struct ProgressReceiver {
+    receiver: mpsc::Receiver<ProgressEvent>,
+    queued: Arc<AtomicUsize>,
+}
+impl ProgressReceiver {
+    fn recv_timeout(&self, timeout: Duration) -> Result<ProgressEvent, mpsc::RecvTimeoutError> {
+        let event = self.receiver.recv_timeout(timeout)?;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        Ok(event)
+    }
+}
+
+/// Progress callbacks only queue events, so they don't wait on the terminal.
+/// The bars themselves are owned and drawn by a `ProgressRenderer` on its own thread.
+/// If the renderer falls behind and the queue fills up, intermediate updates are dropped,
+/// since a later one supersedes them anyway. Events that finish an item are never dropped,
+/// and queueing them never blocks, since callbacks run on the async runtime's threads.
 struct ProgressManager {
+    format: ProgressFormat,
+    sender: mpsc::Sender<ProgressEvent>,
+    queued: Arc<AtomicUsize>,
+    queue_size: usize,
+    json_writer: Mutex<Box<dyn Write + Send>>,
+}
+impl ProgressManager {
+    fn new(format: ProgressFormat) -> Self {
+        Self::with_json_writer(format, Box::new(std::io::stderr()))
+    }
+
+    fn with_json_writer(format: ProgressFormat, json_writer: Box<dyn Write + Send>) -> Self {
+        let (manager, receiver) = Self::with_queue(format, json_writer, PROGRESS_QUEUE_SIZE);
+        if format == ProgressFormat::Bars {
+            std::thread::spawn(move || ProgressRenderer::new().run(receiver));
+        }
+        manager
+    }
+
+    fn with_queue(
+        format: ProgressFormat,
+        json_writer: Box<dyn Write + Send>,
+        queue_size: usize,
+    ) -> (Self, ProgressReceiver) {
+        let (sender, receiver) = mpsc::channel();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let manager = Self {
+            format,
+            sender,
+            queued: Arc::clone(&queued),
+            queue_size,
+            json_writer: Mutex::new(json_writer),
+        };
+        (manager, ProgressReceiver { receiver, queued })
+    }
+
+    /// Queues `event` for the renderer. Returns false if it couldn't be sent.
+    fn send(&self, event: ProgressEvent) -> bool {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(event).is_ok()
+    }
+
+    /// Queues `event` unless the renderer is too far behind, in which case it's dropped.
+    fn try_send(&self, event: ProgressEvent) {
+        if self.queued.load(Ordering::Relaxed) < self.queue_size {
+            self.send(event);
+        }
+    }
+
+    fn write_json(&self, event: serde_json::Value) {
+        let mut writer = self.json_writer.lock().unwrap();
+        let _ = writeln!(writer, "{}", event);
+    }
+
+    fn update_total(&self, progress: TotalProgress) {
+        if self.format == ProgressFormat::Json {
+            let event = serde_json::json!({
+                "event": "total",
+                "bytes": progress.bytes_done,
+                "total": progress.total_bytes,
+                "bytes_per_sec": progress.bytes_per_sec,
+                "eta_secs": progress.eta.map(|eta| eta.as_secs()),
+            });
+            self.write_json(event);
+            return;
+        }
+        self.try_send(ProgressEvent::Total(progress));
+    }
+
+    fn finish_total(&self) {
+        self.wait_for(ProgressEvent::FinishTotal);
+    }
+
+    /// Blocks until every update sent so far has been drawn.
+    fn flush(&self) {
+        self.wait_for(ProgressEvent::Flush);
+    }
+
+    fn wait_for(&self, event: fn(mpsc::Sender<()>) -> ProgressEvent) {
+        if self.format == ProgressFormat::Json {
+            return;
+        }
+        let (done_sender, done_receiver) = mpsc::channel();
+        if self.send(event(done_sender)) {
+            let _ = done_receiver.recv();
+        }
+    }
+
+    fn update_item(&self, name: &str, progress: ItemProgress) {
+        if self.format == ProgressFormat::Json {
+            self.write_json(Self::item_event(name, &progress));
+            return;
+        }
+        let must_deliver = matches!(
+            progress,
+            ItemProgress::Passed { .. }
+                | ItemProgress::Failed { .. }
+                | ItemProgress::Retrying { .. }
+        );
+        let event = ProgressEvent::Item(name.to_string(), progress);
+        if must_deliver {
+            self.send(event);
+        } else {
+            // dropped if the queue is full; the next update supersedes it
+            self.try_send(event);
+        }
+    }
+
+    fn item_event(name: &str, progress: &ItemProgress) -> serde_json::Value {
+        let mut event = serde_json::json!({ "event": "item", "name": name });
+        let fields = match progress {
+            ItemProgress::Downloading {
+                bytes_downloaded,
+                total_bytes,
+                attempt,
+                max_attempts,
+            } => serde_json::json!({
+                "phase": "downloading",
+                "bytes": bytes_downloaded,
+                "total": total_bytes,
+                "attempt": attempt,
+                "max_attempts": max_attempts,
+            }),
+            ItemProgress::Validating => serde_json::json!({ "phase": "validating" }),
+            ItemProgress::Retrying { delay } => serde_json::json!({
+                "phase": "retrying",
+                "delay_ms": delay.as_millis() as u64,
+            }),
+            ItemProgress::Passed {
+                item_size,
+                duration,
+                was_downloaded,
+            } => serde_json::json!({
+                "phase": "passed",
+                "bytes": item_size,
+                "total": item_size,
+                "duration_ms": duration.as_millis() as u64,
+                "was_downloaded": was_downloaded,
+            }),
+            ItemProgress::Failed { item_size, reason } => serde_json::json!({
+                "phase": "failed",
+                "bytes": item_size,
+                "total": item_size,
+                "reason": reason.to_string(),
+            }),
+            _ => serde_json::json!({ "phase": "unknown" }),
+        };
+        event
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        event
+    }
+}
+
+struct ProgressRenderer {
     multi: MultiProgress,
-    bars: Mutex<HashMap<String, (ProgressBar, ItemState)>>,
+    bars: HashMap<String, (ProgressBar, ItemState)>,
+    total_bar: Option<ProgressBar>,
     max_bars: usize,
     styles: Vec<ProgressStyle>,
+    // only the latest update for each item is drawn
+    pending_items: HashMap<String, ItemProgress>,
+    pending_total: Option<TotalProgress>,
 }
-impl ProgressManager {
+impl ProgressRenderer {
     fn new() -> Self {
         Self {
             multi: MultiProgress::new(),
-            bars: Mutex::new(HashMap::new()),
+            bars: HashMap::new(),
+            total_bar: None,
             max_bars: 10,
             styles: vec![
                 ProgressStyle::default_bar()
@@ -165,98 +663,222 @@ impl ProgressManager {
                 ProgressStyle::default_spinner()
                     .template("{spinner:.green} Validating {wide_msg:>}")
                     .unwrap(),
+                ProgressStyle::default_bar()
+                    .template("[{bar:40}] {bytes} / {total_bytes} total {wide_msg:>}")
+                    .unwrap()
+                    .progress_chars("=> "),
             ],
+            pending_items: HashMap::new(),
+            pending_total: None,
         }
     }
 
-    fn update_item(&self, name: &str, progress: ItemProgress) {
+    fn run(mut self, receiver: ProgressReceiver) {
+        let mut next_frame = Instant::now() + FRAME_INTERVAL;
+        loop {
+            match receiver.recv_timeout(next_frame.saturating_duration_since(Instant::now())) {
+                Ok(ProgressEvent::Item(name, progress)) => self.queue_item(name, progress),
+                Ok(ProgressEvent::Total(progress)) => self.pending_total = Some(progress),
+                Ok(ProgressEvent::Flush(done)) => {
+                    self.draw();
+                    let _ = done.send(());
+                }
+                Ok(ProgressEvent::FinishTotal(done)) => {
+                    self.draw();
+                    if let Some(pb) = self.total_bar.take() {
+                        pb.finish_and_clear();
+                    }
+                    let _ = done.send(());
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if Instant::now() >= next_frame {
+                self.draw();
+                next_frame = Instant::now() + FRAME_INTERVAL;
+            }
+        }
+    }
+
+    fn queue_item(&mut self, name: String, progress: ItemProgress) {
+        if let ItemProgress::Retrying { delay } = progress {
+            let _ = self.multi.println(format!(
+                "{}: rate limited; retrying in {}s",
+                name,
+                delay.as_secs_f64().ceil()
+            ));
+            return;
+        }
+        // don't let a new attempt at an item swallow the end of the previous one
+        if let Some(finished @ (ItemProgress::Passed { .. } | ItemProgress::Failed { .. })) =
+            self.pending_items.remove(&name)
+        {
+            self.update_item(&name, finished);
+        }
+        self.pending_items.insert(name, progress);
+    }
+
+    fn draw(&mut self) {
+        for (name, progress) in std::mem::take(&mut self.pending_items) {
+            self.update_item(&name, progress);
+        }
+        if let Some(progress) = self.pending_total.take() {
+            self.update_total(progress);
+        }
+    }
+
+    fn update_total(&mut self, progress: TotalProgress) {
+        let pb = self.total_bar.get_or_insert_with(|| {
+            let pb = self.multi.insert(0, ProgressBar::new(progress.total_bytes));
+            pb.set_style(self.styles[2].clone());
+            pb
+        });
+        pb.set_position(progress.bytes_done);
+        match progress.eta {
+            Some(eta) => pb.set_message(format!(
+                "{}/s, {} left",
+                ffbuildtool::util::bytes_to_human_readable(progress.bytes_per_sec),
+                HumanDuration(eta)
+            )),
+            None => pb.set_message(""),
+        }
+    }
+
+    fn update_item(&mut self, name: &str, progress: ItemProgress) {
         match progress {
             ItemProgress::Downloading {
                 bytes_downloaded,
                 total_bytes,
+                attempt,
+                max_attempts,
             } => {
-                self.update_item_downloading(name, bytes_downloaded, total_bytes);
+                let label = match attempt {
+                    1 => name.to_string(),
+                    _ => format!("{} (retry {}/{})", name, attempt, max_attempts),
+                };
+                self.update_item_downloading(name, &label, bytes_downloaded, total_bytes);
             }
             ItemProgress::Validating => {
                 self.update_item_validating(name);
             }
+            // printed as soon as it's received
+            ItemProgress::Retrying { .. } => {}
             ItemProgress::Passed { .. } | ItemProgress::Failed { .. } => {
                 self.finish_item(name);
             }
+            _ => {}
         }
     }
 
-    fn finish_item(&self, name: &str) {
-        let mut bars = self.bars.lock().unwrap();
-        if let Some((pb, _)) = bars.remove(name) {
+    fn finish_item(&mut self, name: &str) {
+        if let Some((pb, _)) = self.bars.remove(name) {
             pb.finish_and_clear();
         }
     }
 
-    fn update_item_validating(&self, name: &str) {
-        let mut bars = self.bars.lock().unwrap();
-        if let Some((pb, st)) = bars.get_mut(name) {
+    fn update_item_validating(&mut self, name: &str) {
+        if let Some((pb, st)) = self.bars.get_mut(name) {
             if *st != ItemState::Validating {
                 pb.set_style(self.styles[1].clone());
                 *st = ItemState::Validating;
             }
-        } else if bars.len() < self.max_bars {
+        } else if self.bars.len() < self.max_bars {
             let pb = self.multi.add(ProgressBar::new(0));
             pb.set_style(self.styles[1].clone());
             pb.set_message(name.to_string());
             pb.enable_steady_tick(Duration::from_millis(100));
-            bars.insert(name.to_string(), (pb, ItemState::Validating));
+            self.bars
+                .insert(name.to_string(), (pb, ItemState::Validating));
         };
     }
 
-    fn update_item_downloading(&self, name: &str, current: u64, total: u64) {
-        let mut bars = self.bars.lock().unwrap();
-        if let Some((pb, st)) = bars.get_mut(name) {
+    fn update_item_downloading(&mut self, name: &str, label: &str, current: u64, total: u64) {
+        if let Some((pb, st)) = self.bars.get_mut(name) {
             if *st != ItemState::Downloading {
                 pb.disable_steady_tick();
                 pb.set_style(self.styles[0].clone());
                 pb.set_length(total);
+                pb.set_message(label.to_string());
                 *st = ItemState::Downloading;
             }
             if pb.length().unwrap_or(0) != total {
                 pb.set_length(total);
             }
             pb.set_position(current);
-        } else if bars.len() < self.max_bars {
+        } else if self.bars.len() < self.max_bars {
             let pb = self.multi.add(ProgressBar::new(total));
             pb.set_style(self.styles[0].clone());
-            pb.set_message(name.to_string());
+            pb.set_message(label.to_string());
             pb.set_position(current);
-            bars.insert(name.to_string(), (pb, ItemState::Downloading));
+            self.bars
+                .insert(name.to_string(), (pb, ItemState::Downloading));
         };
     }
 }
 
 async fn parse_manifest(path: &str) -> Result<Version, String> {
-    Version::from_manifest(path)
+    let version = Version::from_manifest(path)
         .await
-        .map_err(|e| format!("Couldn't parse manifest: {}", e))
+        .map_err(|e| format!("Couldn't parse manifest: {}", e))?;
+    for problem in version.validate_self() {
+        println!("Warning: {}", problem);
+    }
+    Ok(version)
 }
 
 static PROGRESS: OnceLock<ProgressManager> = OnceLock::new();
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
+    let args = Cli::parse();
     PROGRESS
-        .set(ProgressManager::new())
+        .set(ProgressManager::new(args.progress))
         .unwrap_or_else(|_| panic!());
-    let args = Cli::parse();
+    if let Some(temp_dir) = args.temp_dir {
+        ffbuildtool::set_temp_dir(temp_dir)?;
+    }
+    ffbuildtool::set_check_mirror_size(args.check_mirror_size);
+    if let Some(jobs) = args.jobs {
+        ffbuildtool::set_max_concurrent_items(jobs as usize)?;
+    }
+    if let Some(download_jobs) = args.download_jobs {
+        ffbuildtool::set_max_concurrent_downloads(download_jobs as usize)?;
+    }
+    ffbuildtool::set_main_file_alongside_bundles(args.main_file_alongside);
+    ffbuildtool::set_symlink_policy(args.symlinks.into());
+    ffbuildtool::set_download_timeout(
+        args.download_timeout.map(Duration::from_secs),
+        args.download_deadline.map(Duration::from_secs),
+    );
+    if let Some(bundle_store) = args.bundle_store {
+        ffbuildtool::set_shared_bundle_store(bundle_store)?;
+    }
+    if let Some(hash_cache) = &args.hash_cache {
+        ffbuildtool::set_hash_cache_path(hash_cache)
+            .map_err(|e| format!("Couldn't load hash cache: {}", e))?;
+    }
     match args.command {
         Commands::GenManifest(args) => generate_manifest(args).await,
         Commands::DownloadBuild(args) => download_build(args).await,
         Commands::RepairBuild(args) => repair_build(args).await,
         Commands::ValidateBuild(args) => validate_build(args).await,
+        Commands::ValidateAll(args) => validate_all(args).await,
+        Commands::Stats(args) => stats(args).await,
+        Commands::ConvertManifest(args) => convert_manifest(args).await,
+        Commands::Install(args) => install(args).await,
+        Commands::Serve(args) => serve(args).await,
+        #[cfg(feature = "s3")]
+        Commands::Upload(args) => upload(args).await,
         #[cfg(feature = "lzma")]
         Commands::ReadBundle(args) => read_bundle(args).await,
         #[cfg(feature = "lzma")]
         Commands::ExtractBundle(args) => extract_bundle(args).await,
         #[cfg(feature = "lzma")]
         Commands::PackBundle(args) => pack_bundle(args).await,
+        #[cfg(feature = "lzma")]
+        Commands::DiffBundle(args) => diff_bundle(args).await,
+        #[cfg(feature = "lzma")]
+        Commands::VerifyBundle(args) => verify_bundle(args).await,
     }
 }
 
@@ -271,20 +893,108 @@ async fn generate_manifest(args: GenManifestArgs) -> Result<(), String> {
         None
     };
 
-    let mut version = Version::build(
-        &args.build_path,
-        &args.asset_url,
-        args.name.as_deref(),
-        args.description.as_deref(),
-        parent_uuid,
-    )
-    .await
-    .map_err(|e| format!("Couldn't generate bundle info: {}", e))?;
+    if !args.extensions.is_empty() {
+        let extensions: Vec<&str> = args
+            .extensions
+            .iter()
+            .map(|ext| ext.trim_start_matches('.'))
+            .collect();
+        ffbuildtool::set_extra_bundle_extensions(&extensions)?;
+    }
+
+    let pb = ProgressBar::new(0);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos} / {len} {msg}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    let cb = {
+        let pb = pb.clone();
+        move |bundle_name: &str, processed: usize, total: usize, _: u64, uncompressed: u64| {
+            pb.set_length(total as u64);
+            pb.set_position(processed as u64);
+            pb.set_message(format!(
+                "{} ({} uncompressed)",
+                bundle_name,
+                ffbuildtool::util::bytes_to_human_readable(uncompressed)
+            ));
+        }
+    };
+
+    let mut builder = Version::builder()
+        .asset_url(&args.asset_url)
+        .callback(Arc::new(cb));
+    if let Some(name) = &args.name {
+        builder = builder.name(name);
+    }
+    if let Some(description) = &args.description {
+        builder = builder.description(description);
+    }
+    if let Some(parent_uuid) = parent_uuid {
+        builder = builder.parent(parent_uuid);
+    }
+    if let Some(main_file_name) = &args.main_file_name {
+        builder = builder.main_file_name(main_file_name);
+    }
+
+    let (mut version, corrupted, warnings) = if let Some(reference_path) = &args.validate_against {
+        let reference = parse_manifest(reference_path).await?;
+        let (version, report, warnings) = builder
+            .build_validated(&args.build_path, &reference)
+            .await
+            .map_err(|e| format!("Couldn't generate bundle info: {}", e))?;
+        (version, Some(report.get_corrupted()), warnings)
+    } else {
+        let (version, warnings) = builder
+            .build_with_warnings(&args.build_path)
+            .await
+            .map_err(|e| format!("Couldn't generate bundle info: {}", e))?;
+        (version, None, warnings)
+    };
+    pb.finish_and_clear();
+
+    if !warnings.is_empty() {
+        println!("{} warnings:", warnings.len());
+        for warning in &warnings {
+            println!("\t{}", warning);
+        }
+    }
+
+    // a failed build doesn't get a manifest, so it can't be mistaken for a good one
+    if let Some(corrupted) = &corrupted {
+        if corrupted.is_empty() {
+            println!("No corrupted files found");
+        } else {
+            println!("{} corrupted files found:", corrupted.len());
+            for file in corrupted {
+                println!("\t{}", file);
+            }
+            return Err(format!(
+                "{} files failed validation; no manifest was written",
+                corrupted.len()
+            ));
+        }
+    }
+    if args.deny_warnings && !warnings.is_empty() {
+        return Err(format!(
+            "{} warnings found; no manifest was written",
+            warnings.len()
+        ));
+    }
 
     if args.hidden {
         version.set_hidden(true);
     }
 
+    if let Some(min_client_version) = &args.min_client_version {
+        version.set_min_client_version(min_client_version);
+    }
+
+    if args.pack_uncompressed_info {
+        version.set_pack_uncompressed_info(true);
+    }
+
     println!("Build UUID: {}", version.get_uuid());
 
     version
@@ -305,11 +1015,22 @@ async fn download_build(args: DownloadBuildArgs) -> Result<(), String> {
     let cb = |_uuid: &Uuid, name: &str, progress: ItemProgress| {
         PROGRESS.get().unwrap().update_item(name, progress);
     };
+    let total_cb = |_uuid: &Uuid, progress: TotalProgress| {
+        PROGRESS.get().unwrap().update_total(progress);
+    };
+    let cb = version.track_total_progress(Some(Arc::new(cb)), Arc::new(total_cb));
 
-    version
-        .download_compressed(&args.output_path, Some(Arc::new(cb)))
-        .await
-        .map_err(|e| format!("Couldn't download build: {}", e))?;
+    if args.resume {
+        version
+            .download_compressed_resume(&args.output_path, Some(cb))
+            .await
+    } else {
+        version
+            .download_compressed(&args.output_path, Some(cb))
+            .await
+    }
+    .map_err(|e| format!("Couldn't download build: {}", e))?;
+    PROGRESS.get().unwrap().finish_total();
     println!("Download complete");
     Ok(())
 }
@@ -317,19 +1038,67 @@ async fn download_build(args: DownloadBuildArgs) -> Result<(), String> {
 async fn repair_build(args: RepairBuildArgs) -> Result<(), String> {
     let version = parse_manifest(&args.manifest_path).await?;
     println!(
-        "Repairing build {} at {}",
+        "Repairing build {} at {}{}",
         version.get_uuid(),
-        args.build_path
+        args.build_path,
+        if args.dry_run { " (dry run)" } else { "" }
     );
 
+    if args.dry_run {
+        let to_download = version
+            .repair_dry_run(&args.build_path)
+            .await
+            .map_err(|e| format!("Couldn't validate build: {}", e))?;
+        if to_download.is_empty() {
+            println!("No corrupted files found");
+        } else {
+            let total_bytes: u64 = to_download.iter().map(|(_, _, size)| size).sum();
+            println!(
+                "{} files would be downloaded ({}):",
+                to_download.len(),
+                ffbuildtool::util::bytes_to_human_readable(total_bytes)
+            );
+            for (file, reason, size) in to_download {
+                println!(
+                    "\t{} ({}): {}",
+                    file,
+                    ffbuildtool::util::bytes_to_human_readable(size),
+                    reason
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(script_path) = args.script {
+        let script = version
+            .generate_repair_script(&args.build_path)
+            .await
+            .map_err(|e| format!("Couldn't validate build: {}", e))?;
+        script
+            .export(&script_path)
+            .map_err(|e| format!("Couldn't export repair script: {}", e))?;
+        println!(
+            "Repair script with {} files exported to {}",
+            script.get_entries().len(),
+            script_path
+        );
+        return Ok(());
+    }
+
     let cb = |_uuid: &Uuid, name: &str, progress: ItemProgress| {
         PROGRESS.get().unwrap().update_item(name, progress);
     };
+    let total_cb = |_uuid: &Uuid, progress: TotalProgress| {
+        PROGRESS.get().unwrap().update_total(progress);
+    };
+    let cb = version.track_total_progress(Some(Arc::new(cb)), Arc::new(total_cb));
 
     let corrupted = version
-        .repair(&args.build_path, Some(Arc::new(cb)))
+        .repair_with_fallback(&args.build_path, &args.fallback_dirs, Some(cb))
         .await
         .map_err(|e| format!("Couldn't repair build: {}", e))?;
+    PROGRESS.get().unwrap().finish_total();
     if corrupted.is_empty() {
         println!("No corrupted files found");
     } else {
@@ -353,17 +1122,53 @@ async fn validate_build(args: ValidateBuildArgs) -> Result<(), String> {
         PROGRESS.get().unwrap().update_item(name, progress);
     };
 
-    let corrupted = if args.uncompressed {
+    let corrupted = if let Some(report_path) = args.report {
+        if args.uncompressed {
+            return Err("Reports are only supported for compressed builds".to_string());
+        }
+        let report = version
+            .validate_compressed_report(&args.build_path, Some(Arc::new(cb)))
+            .await
+            .map_err(|e| format!("Couldn't validate compressed files: {}", e))?;
+        report
+            .export(&report_path)
+            .map_err(|e| format!("Couldn't export report: {}", e))?;
+        println!("Report exported to {}", report_path);
+        report.get_corrupted()
+    } else if args.uncompressed && (args.raw_dir_names || args.report_unexpected) {
+        let build_path = PathBuf::from(&args.build_path);
+        let raw_dir_names = args.raw_dir_names;
+        let resolver = move |bundle_name: &str| match raw_dir_names {
+            true => build_path.join(bundle_name),
+            false => build_path.join(ffbuildtool::util::url_encode(bundle_name)),
+        };
+        let result = if args.report_unexpected {
+            version
+                .validate_uncompressed_reporting_unexpected(Arc::new(resolver), None)
+                .await
+        } else {
+            version
+                .validate_uncompressed_with_resolver(Arc::new(resolver), None)
+                .await
+        };
+        result.map_err(|e| format!("Couldn't validate uncompressed files: {}", e))?
+    } else if args.uncompressed {
         version
             .validate_uncompressed(&args.build_path, None)
             .await
             .map_err(|e| format!("Couldn't validate uncompressed files: {}", e))?
+    } else if !args.only.is_empty() {
+        version
+            .validate_compressed_subset(&args.build_path, &args.only, Some(Arc::new(cb)))
+            .await
+            .map_err(|e| format!("Couldn't validate compressed files: {}", e))?
     } else {
         version
             .validate_compressed(&args.build_path, Some(Arc::new(cb)))
             .await
             .map_err(|e| format!("Couldn't validate compressed files: {}", e))?
     };
+    PROGRESS.get().unwrap().flush();
 
     if corrupted.is_empty() {
         println!("No corrupted files found");
@@ -376,6 +1181,666 @@ async fn validate_build(args: ValidateBuildArgs) -> Result<(), String> {
     Ok(())
 }
 
+async fn validate_all(args: ValidateAllArgs) -> Result<(), String> {
+    let manifest_names = ffbuildtool::util::list_filenames_in_directory(&args.manifests_path)
+        .map_err(|e| format!("Couldn't list manifests: {}", e))?;
+    let mut builds = Vec::new();
+    for manifest_name in manifest_names {
+        if !manifest_name.ends_with(".json") {
+            continue;
+        }
+        let manifest_path = PathBuf::from(&args.manifests_path).join(&manifest_name);
+        let version = parse_manifest(&manifest_path.to_string_lossy()).await?;
+        let build_path = PathBuf::from(&args.builds_path).join(version.get_uuid().to_string());
+        builds.push((version, build_path.to_string_lossy().to_string()));
+    }
+    println!("Validating {} builds in {}", builds.len(), args.builds_path);
+
+    // item names repeat across builds, so key the progress bars by build as well
+    let cb = |uuid: &Uuid, name: &str, progress: ItemProgress| {
+        PROGRESS
+            .get()
+            .unwrap()
+            .update_item(&format!("{}/{}", uuid, name), progress);
+    };
+
+    let results =
+        Version::validate_many(&builds, Some(Arc::new(cb)), &OperationOptions::default()).await;
+    PROGRESS.get().unwrap().flush();
+
+    let mut total_corrupted = 0;
+    let mut failed_builds = 0;
+    for ((version, path), result) in builds.iter().zip(&results) {
+        let report = match result {
+            Ok(report) => report,
+            Err(e) => {
+                failed_builds += 1;
+                println!(
+                    "{} ({}): couldn't validate {}: {}",
+                    version.get_uuid(),
+                    version.get_name().unwrap_or("unnamed"),
+                    path,
+                    e
+                );
+                continue;
+            }
+        };
+        let corrupted = report.get_corrupted();
+        total_corrupted += corrupted.len();
+        println!(
+            "{} ({}): {} corrupted files",
+            report.get_uuid(),
+            version.get_name().unwrap_or("unnamed"),
+            corrupted.len()
+        );
+        for file in corrupted {
+            println!("\t{}/{}", report.get_uuid(), file);
+        }
+    }
+    if failed_builds > 0 {
+        return Err(format!(
+            "{} of {} builds couldn't be validated; {} corrupted files found in the rest",
+            failed_builds,
+            results.len(),
+            total_corrupted
+        ));
+    }
+    if total_corrupted == 0 {
+        println!("No corrupted files found across {} builds", results.len());
+        return Ok(());
+    }
+    Err(format!(
+        "{} corrupted files found across {} builds",
+        total_corrupted,
+        results.len()
+    ))
+}
+
+async fn stats(args: StatsArgs) -> Result<(), String> {
+    use ffbuildtool::util::bytes_to_human_readable;
+
+    let version = parse_manifest(&args.manifest_path).await?;
+    let mut bundles: Vec<(&str, u64)> = version
+        .get_bundles()
+        .map(|(name, bundle_info)| (name, bundle_info.get_compressed_size()))
+        .collect();
+    // largest first, then by name so ties are stable
+    bundles.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let num_bundles = bundles.len() as u64;
+    let compressed_size = version.get_compressed_assets_size();
+    let uncompressed_size = version.get_uncompressed_assets_size();
+    let average_size = compressed_size.checked_div(num_bundles).unwrap_or(0);
+    let largest = bundles.first().copied();
+    let smallest = bundles.last().copied();
+    let main_file_size = version.get_main_file_info().map(|info| info.get_size());
+
+    if args.json {
+        let bundle_json = |bundle: Option<(&str, u64)>| {
+            bundle.map(|(name, size)| serde_json::json!({ "name": name, "size": size }))
+        };
+        let stats = serde_json::json!({
+            "uuid": version.get_uuid(),
+            "name": version.get_name(),
+            "bundles": num_bundles,
+            "compressed_size": compressed_size,
+            "uncompressed_size": uncompressed_size,
+            "compression_ratio": version.compression_ratio(),
+            "average_bundle_size": average_size,
+            "largest_bundle": bundle_json(largest),
+            "smallest_bundle": bundle_json(smallest),
+            "main_file_size": main_file_size,
+            "total_size": version.get_total_compressed_size(),
+        });
+        println!("{}", stats);
+        return Ok(());
+    }
+
+    println!(
+        "Build {} ({})",
+        version.get_uuid(),
+        version.get_name().unwrap_or("unnamed")
+    );
+    println!("Bundles: {}", num_bundles);
+    println!(
+        "Compressed size: {}",
+        bytes_to_human_readable(compressed_size)
+    );
+    println!(
+        "Uncompressed size: {}",
+        bytes_to_human_readable(uncompressed_size)
+    );
+    if let Some(ratio) = version.compression_ratio() {
+        println!("Compression ratio: {:.2}", ratio);
+    }
+    println!(
+        "Average bundle size: {}",
+        bytes_to_human_readable(average_size)
+    );
+    for (label, bundle) in [("Largest", largest), ("Smallest", smallest)] {
+        if let Some((name, size)) = bundle {
+            println!(
+                "{} bundle: {} ({})",
+                label,
+                name,
+                bytes_to_human_readable(size)
+            );
+        }
+    }
+    match main_file_size {
+        Some(size) => println!(
+            "Main file ({}): {}",
+            version.get_main_file_name(),
+            bytes_to_human_readable(size)
+        ),
+        None => println!("Main file: none"),
+    }
+    println!(
+        "Total download size: {}",
+        bytes_to_human_readable(version.get_total_compressed_size())
+    );
+    Ok(())
+}
+
+async fn convert_manifest(args: ConvertManifestArgs) -> Result<(), String> {
+    use ffbuildtool::UncompressedManifest;
+
+    match args.to {
+        ManifestKind::Uncompressed => {
+            let version = parse_manifest(&args.input_path).await?;
+            let manifest = version.to_uncompressed_manifest();
+            manifest
+                .export(&args.output_path)
+                .map_err(|e| format!("Couldn't write manifest: {}", e))?;
+            println!(
+                "Wrote {} files to {}",
+                manifest.get_files().len(),
+                args.output_path
+            );
+        }
+        ManifestKind::Compressed => {
+            let manifest = UncompressedManifest::from_file(&args.input_path)
+                .map_err(|e| format!("Couldn't parse manifest: {}", e))?;
+            let version = Version::from_uncompressed_manifest(&manifest)
+                .map_err(|e| format!("Couldn't convert manifest: {}", e))?;
+            version
+                .export_manifest(&args.output_path)
+                .map_err(|e| format!("Couldn't write manifest: {}", e))?;
+            println!(
+                "Wrote {} bundles to {}",
+                version.get_bundles().count(),
+                args.output_path
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn install(args: InstallArgs) -> Result<(), String> {
+    use std::sync::atomic::AtomicU64;
+
+    use ffbuildtool::util;
+
+    let version = parse_manifest(&args.manifest_path).await?;
+    println!(
+        "Installing build {} to {}",
+        version.get_uuid(),
+        args.output_path
+    );
+
+    let start = Instant::now();
+    let bytes_downloaded = Arc::new(AtomicU64::new(0));
+    let files_validated = Arc::new(AtomicU64::new(0));
+    let failures: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let last_positions: Arc<Mutex<HashMap<String, u64>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    let cb = {
+        let bytes_downloaded = Arc::clone(&bytes_downloaded);
+        let files_validated = Arc::clone(&files_validated);
+        let failures = Arc::clone(&failures);
+        move |_uuid: &Uuid, name: &str, progress: ItemProgress| {
+            match &progress {
+                ItemProgress::Downloading {
+                    bytes_downloaded: current,
+                    ..
+                } => {
+                    // a retried download starts over from zero
+                    let mut last_positions = last_positions.lock().unwrap();
+                    let last = last_positions.entry(name.to_string()).or_insert(0);
+                    let delta = current.checked_sub(*last).unwrap_or(*current);
+                    *last = *current;
+                    bytes_downloaded.fetch_add(delta, Ordering::Relaxed);
+                }
+                ItemProgress::Passed { .. } => {
+                    files_validated.fetch_add(1, Ordering::Relaxed);
+                }
+                // only sent once a file has run out of download attempts
+                ItemProgress::Failed { .. } => {
+                    files_validated.fetch_add(1, Ordering::Relaxed);
+                    failures.lock().unwrap().push(name.to_string());
+                }
+                _ => {}
+            }
+            PROGRESS.get().unwrap().update_item(name, progress);
+        }
+    };
+    let cb: ffbuildtool::ProgressCallback = Arc::new(cb);
+
+    let total_cb = |_uuid: &Uuid, progress: TotalProgress| {
+        PROGRESS.get().unwrap().update_total(progress);
+    };
+    let download_cb = version.track_total_progress(Some(cb.clone()), Arc::new(total_cb));
+
+    util::create_dir_if_needed(&args.output_path)
+        .map_err(|e| format!("Couldn't create output directory: {}", e))?;
+    version
+        .download_compressed(&args.output_path, Some(download_cb))
+        .await
+        .map_err(|e| format!("Couldn't download build: {}", e))?;
+    PROGRESS.get().unwrap().finish_total();
+
+    // every file was validated as it was downloaded
+    let failures = std::mem::take(&mut *failures.lock().unwrap());
+
+    #[cfg(feature = "lzma")]
+    let (failures, extracted) = match &args.extract_path {
+        Some(extract_path) if failures.is_empty() => {
+            let count = extract_build(
+                &args.output_path,
+                extract_path,
+                version.get_main_file_name(),
+                &args.extensions,
+            )?;
+            let failures = version
+                .validate_uncompressed(extract_path, Some(cb.clone()))
+                .await
+                .map_err(|e| format!("Couldn't validate uncompressed files: {}", e))?;
+            (failures, Some(count))
+        }
+        _ => (failures, None),
+    };
+
+    let bytes_downloaded = bytes_downloaded.load(Ordering::Relaxed);
+    println!("------------------------");
+    println!(
+        "Build: {} ({})",
+        version.get_uuid(),
+        version.get_name().unwrap_or("unnamed")
+    );
+    println!(
+        "Downloaded: {} ({} bytes)",
+        util::bytes_to_human_readable(bytes_downloaded),
+        bytes_downloaded
+    );
+    println!(
+        "Files validated: {}",
+        files_validated.load(Ordering::Relaxed)
+    );
+    #[cfg(feature = "lzma")]
+    if let Some(count) = extracted {
+        println!("Bundles extracted: {}", count);
+    }
+    println!("Time: {:.2}s", start.elapsed().as_secs_f64());
+
+    if failures.is_empty() {
+        println!("Failures: none");
+        Ok(())
+    } else {
+        println!("Failures:");
+        for file in &failures {
+            println!("\t{}", file);
+        }
+        Err(format!("{} corrupted files found", failures.len()))
+    }
+}
+
+/// Extracts every compressed asset bundle in the build directory, including files with one of the
+/// extra `extensions`, into a subdirectory of `extract_path`. Returns the number of extracted bundles.
+#[cfg(feature = "lzma")]
+fn extract_build(
+    build_path: &str,
+    extract_path: &str,
+    main_file_name: &str,
+    extensions: &[String],
+) -> Result<usize, String> {
+    use ffbuildtool::{bundle::AssetBundle, util};
+
+    let extensions: Vec<String> = extensions
+        .iter()
+        .map(|ext| ext.trim_start_matches('.').to_string())
+        .collect();
+    let bundle_names = ffbuildtool::get_bundle_names_from_asset_root(
+        build_path,
+        main_file_name,
+        Some(&extensions),
+    )
+    .map_err(|e| format!("Couldn't list files in {}: {}", build_path, e))?;
+
+    for bundle_name in &bundle_names {
+        println!("Extracting {}", bundle_name);
+        let bundle_path = PathBuf::from(build_path).join(bundle_name);
+        let (_, bundle) = AssetBundle::from_file(&bundle_path.to_string_lossy())?;
+        let output_dir = PathBuf::from(extract_path).join(util::url_encode(bundle_name));
+        bundle.extract_files(&output_dir.to_string_lossy())?;
+    }
+    Ok(bundle_names.len())
+}
+
+struct ServeState {
+    manifest: Vec<u8>,
+    asset_path: String,
+    build_path: PathBuf,
+}
+
+type ServeResponse = hyper::Response<BoxBody<Bytes, std::io::Error>>;
+
+/// Files are streamed to the client in chunks of this size instead of being read into memory.
+const SERVE_CHUNK_SIZE: usize = 64 * 1024;
+
+async fn serve(args: ServeArgs) -> Result<(), String> {
+    use tokio::net::TcpListener;
+
+    let mut version = parse_manifest(&args.manifest_path).await?;
+
+    // Serve the bundles at the same path as the real asset URL, but on this host
+    let asset_url = reqwest::Url::parse(&version.get_asset_url())
+        .map_err(|e| format!("Invalid asset URL: {}", e))?;
+    let asset_path = asset_url.path().trim_end_matches('/').to_string();
+    let local_asset_url = format!("http://localhost:{}{}", args.port, asset_path);
+    version
+        .set_asset_url(&local_asset_url)
+        .map_err(|e| e.to_string())?;
+    let main_file_url = format!("{}/{}", local_asset_url, version.get_main_file_name());
+    version.set_main_file_url(&main_file_url);
+
+    let manifest = serde_json::to_vec_pretty(&version)
+        .map_err(|e| format!("Couldn't serialize manifest: {}", e))?;
+    // canonical, so resolved file paths can be checked against it
+    let build_path = std::fs::canonicalize(&args.build_path)
+        .map_err(|e| format!("Couldn't open {}: {}", args.build_path, e))?;
+    let state = Arc::new(ServeState {
+        manifest,
+        asset_path,
+        build_path,
+    });
+
+    let listener = TcpListener::bind(("127.0.0.1", args.port))
+        .await
+        .map_err(|e| format!("Couldn't bind to port {}: {}", args.port, e))?;
+    println!(
+        "Serving build {} at http://localhost:{}/manifest.json",
+        version.get_uuid(),
+        args.port
+    );
+    println!(
+        "Assets served from {} at {}",
+        args.build_path, local_asset_url
+    );
+    serve_connections(listener, state).await
+}
+
+async fn serve_connections(
+    listener: tokio::net::TcpListener,
+    state: Arc<ServeState>,
+) -> Result<(), String> {
+    use hyper::{server::conn::http1, service::service_fn};
+    use hyper_util::rt::TokioIo;
+
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Couldn't accept connection: {}", e))?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            let service = service_fn(move |req| serve_request(Arc::clone(&state), req));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+    }
+}
+
+async fn serve_request(
+    state: Arc<ServeState>,
+    req: hyper::Request<hyper::body::Incoming>,
+) -> Result<ServeResponse, Infallible> {
+    use hyper::{header, Method, StatusCode};
+
+    let is_head = req.method() == Method::HEAD;
+    if req.method() != Method::GET && !is_head {
+        return Ok(serve_status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let path = req.uri().path();
+    if path == "/manifest.json" {
+        let len = state.manifest.len();
+        let body = if is_head {
+            Bytes::new()
+        } else {
+            Bytes::from(state.manifest.clone())
+        };
+        let response = hyper::Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, len)
+            .body(full_body(body))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let Some(file_path) = path
+        .strip_prefix(&state.asset_path)
+        .and_then(|p| p.strip_prefix('/'))
+        .and_then(percent_decode)
+        .and_then(|file_name| resolve_served_file(&state.build_path, &file_name))
+    else {
+        return Ok(serve_status(StatusCode::NOT_FOUND));
+    };
+
+    match serve_file(&file_path, req.headers(), is_head).await {
+        Ok(response) => Ok(response),
+        Err(e) => {
+            eprintln!("Couldn't serve {}: {}", file_path.display(), e);
+            Ok(serve_status(StatusCode::NOT_FOUND))
+        }
+    }
+}
+
+/// Decodes `%XX` escapes in a request path. Returns `None` if an escape is malformed
+/// or the result isn't valid UTF-8.
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Maps a requested file name to a file inside `build_path`, which must be canonical.
+/// Returns `None` for names that could escape it, e.g. absolute paths or `..` components,
+/// and for files that resolve outside of it through symlinks.
+fn resolve_served_file(build_path: &Path, file_name: &str) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let name = Path::new(file_name);
+    let unsafe_name = file_name.is_empty()
+        || name.has_root()
+        || file_name.split(['/', '\\']).any(|part| part == "..")
+        || name
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)));
+    if unsafe_name {
+        return None;
+    }
+
+    let file_path = std::fs::canonicalize(build_path.join(name)).ok()?;
+    file_path.starts_with(build_path).then_some(file_path)
+}
+
+async fn serve_file(
+    file_path: &Path,
+    headers: &hyper::HeaderMap,
+    is_head: bool,
+) -> Result<ServeResponse, std::io::Error> {
+    use hyper::{header, StatusCode};
+    use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _};
+
+    let mut file = tokio::fs::File::open(file_path).await?;
+    let metadata = file.metadata().await?;
+    let size = metadata.len();
+    let last_modified = metadata.modified()?;
+
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok());
+    if let Some(since) = if_modified_since {
+        // HTTP dates have second precision
+        let modified_secs = last_modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let since_secs = since
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if modified_secs <= since_secs {
+            return Ok(serve_status(StatusCode::NOT_MODIFIED));
+        }
+    }
+
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    let (status, start, end) = match range {
+        None => (StatusCode::OK, 0, size),
+        Some(range) => match parse_range(range, size) {
+            Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end),
+            None => {
+                let response = hyper::Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                    .body(full_body(Bytes::new()))
+                    .unwrap();
+                return Ok(response);
+            }
+        },
+    };
+
+    let len = end - start;
+    let body = if is_head {
+        full_body(Bytes::new())
+    } else {
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        file_body(file.take(len))
+    };
+
+    let mut response = hyper::Response::builder()
+        .status(status)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, len)
+        .header(
+            header::LAST_MODIFIED,
+            httpdate::fmt_http_date(last_modified),
+        );
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end - 1, size),
+        );
+    }
+    Ok(response.body(body).unwrap())
+}
+
+fn full_body(data: Bytes) -> BoxBody<Bytes, std::io::Error> {
+    Full::new(data).map_err(|never| match never {}).boxed()
+}
+
+/// Streams the rest of `reader` in `SERVE_CHUNK_SIZE` chunks, stopping after the first error.
+fn file_body(reader: tokio::io::Take<tokio::fs::File>) -> BoxBody<Bytes, std::io::Error> {
+    use hyper::body::Frame;
+    use tokio::io::AsyncReadExt as _;
+
+    let stream = futures_util::stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        let mut buf = vec![0; SERVE_CHUNK_SIZE];
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(Frame::data(Bytes::from(buf))), Some(reader)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    http_body_util::StreamBody::new(stream).boxed()
+}
+
+/// Parses a single-range `Range` header value into a half-open byte range.
+/// Returns `None` if the range is malformed or unsatisfiable.
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.saturating_sub(suffix), size)
+        }
+        (start, "") => (start.parse().ok()?, size),
+        (start, end) => {
+            let end: u64 = end.parse().ok()?;
+            // the end is inclusive and may be past the end of the file
+            let end = end.checked_add(1).map_or(size, |end| end.min(size));
+            (start.parse().ok()?, end)
+        }
+    };
+    if start >= end {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn serve_status(status: hyper::StatusCode) -> ServeResponse {
+    hyper::Response::builder()
+        .status(status)
+        .body(full_body(Bytes::new()))
+        .unwrap()
+}
+
+#[cfg(feature = "s3")]
+async fn upload(args: UploadArgs) -> Result<(), String> {
+    use ffbuildtool::s3::S3Target;
+
+    let version = parse_manifest(&args.manifest_path).await?;
+    println!(
+        "Uploading build {} at {} to bucket {}",
+        version.get_uuid(),
+        args.build_path,
+        args.bucket
+    );
+
+    let target = S3Target {
+        bucket: args.bucket,
+        endpoint: args.endpoint,
+        region: args.region,
+        prefix: args.prefix,
+    };
+    let summary = version
+        .upload_to_s3(&args.build_path, &args.manifest_path, &target)
+        .await
+        .map_err(|e| format!("Couldn't upload build: {}", e))?;
+    println!(
+        "Upload complete; {} files uploaded, {} already up to date",
+        summary.uploaded.len(),
+        summary.skipped.len()
+    );
+    Ok(())
+}
+
 #[cfg(feature = "lzma")]
 async fn read_bundle(args: ReadBundleArgs) -> Result<(), String> {
     use std::time::Instant;
@@ -399,14 +1864,57 @@ async fn read_bundle(args: ReadBundleArgs) -> Result<(), String> {
     Ok(())
 }
 
+// one progress bar per bundle level, shared by packing and extraction
+#[cfg(feature = "lzma")]
+fn bundle_level_progress(
+    level_idx: usize,
+    file: usize,
+    total_files: usize,
+    current_file_name: String,
+) {
+    use std::sync::LazyLock;
+
+    static PBS: OnceLock<Mutex<HashMap<usize, ProgressBar>>> = OnceLock::new();
+    static PB_TEMPLATE: LazyLock<ProgressStyle> = LazyLock::new(|| {
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos} / {len} {msg}")
+            .unwrap()
+            .progress_chars("=> ")
+    });
+
+    let mut pbs = PBS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    let pb = pbs.entry(level_idx).or_insert_with(|| {
+        let pb = ProgressBar::new(total_files as u64);
+        pb.set_style(PB_TEMPLATE.clone());
+        pb
+    });
+
+    pb.set_position(file as u64);
+    pb.set_message(current_file_name);
+}
+
 #[cfg(feature = "lzma")]
 async fn extract_bundle(args: ExtractBundleArgs) -> Result<(), String> {
-    use std::{path::PathBuf, time::Instant};
+    use std::time::Instant;
 
-    use ffbuildtool::{bundle::AssetBundle, util};
+    use ffbuildtool::{
+        bundle::{AssetBundle, ExtractLayout},
+        util,
+    };
 
     let start = Instant::now();
-    let (header, bundle) = AssetBundle::from_file(&args.input_bundle)?;
+    let (header, bundle) = if args.lenient {
+        let (header, bundle, failed_levels) = AssetBundle::from_file_lenient(&args.input_bundle)?;
+        for (level, e) in failed_levels {
+            println!("Couldn't read level {}: {}", level, e);
+        }
+        (header, bundle)
+    } else {
+        AssetBundle::from_file(&args.input_bundle)?
+    };
     println!("Bundle read in {}ms", start.elapsed().as_millis());
     println!(
         "------------------------\n{}\n------------------------\n{}",
@@ -427,7 +1935,15 @@ async fn extract_bundle(args: ExtractBundleArgs) -> Result<(), String> {
     println!("Extracting bundle {} to {}", args.input_bundle, output_dir);
 
     let start = Instant::now();
-    bundle.extract_files(&output_dir)?;
+    if let Some(level) = args.level {
+        bundle.extract_level(level, &output_dir)?;
+    } else if let Some(threads) = args.threads {
+        bundle.extract_files_parallel(&output_dir, threads)?;
+    } else if args.flat {
+        bundle.extract_files_with_layout(&output_dir, ExtractLayout::Flat)?;
+    } else {
+        bundle.extract_files_with_callback(&output_dir, Some(bundle_level_progress))?;
+    }
     println!("Bundle extracted in {}ms", start.elapsed().as_millis());
 
     Ok(())
@@ -435,40 +1951,396 @@ async fn extract_bundle(args: ExtractBundleArgs) -> Result<(), String> {
 
 #[cfg(feature = "lzma")]
 async fn pack_bundle(args: PackBundleArgs) -> Result<(), String> {
-    use std::{sync::LazyLock, time::Instant};
+    use std::time::Instant;
+
+    use ffbuildtool::bundle::{AssetBundle, HeaderVersions, LzmaParams};
 
+    let start = Instant::now();
+    let bundle = if args.strict {
+        AssetBundle::from_directory_strict(&args.input_dir)?
+    } else {
+        AssetBundle::from_directory(&args.input_dir)?
+    };
+    println!("Files read in {}ms", start.elapsed().as_millis());
+
+    let start = Instant::now();
+    let params = LzmaParams {
+        level: args.compression_level,
+        dict_size: args.dict_size,
+        ..Default::default()
+    };
+    let mut versions = HeaderVersions::default();
+    if let Some(player_version) = args.player_version {
+        versions.player_version = player_version;
+    }
+    if let Some(engine_version) = args.engine_version {
+        versions.engine_version = engine_version;
+    }
+    bundle.to_file(
+        &args.output_bundle,
+        &params,
+        &versions,
+        Some(bundle_level_progress),
+    )?;
+    println!("Bundle created in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+#[cfg(feature = "lzma")]
+async fn diff_bundle(args: DiffBundleArgs) -> Result<(), String> {
     use ffbuildtool::bundle::AssetBundle;
 
-    fn cb(level_idx: usize, file: usize, total_files: usize, current_file_name: String) {
-        static PBS: OnceLock<Mutex<HashMap<usize, ProgressBar>>> = OnceLock::new();
-        static PB_TEMPLATE: LazyLock<ProgressStyle> = LazyLock::new(|| {
-            ProgressStyle::default_bar()
-                .template("[{bar:40}] {pos} / {len} {msg}")
-                .unwrap()
-                .progress_chars("=> ")
-        });
+    let (_, first) = AssetBundle::from_file(&args.first_bundle)?;
+    let (_, second) = AssetBundle::from_file(&args.second_bundle)?;
+    let diffs = first.diff(&second);
+    if diffs.is_empty() {
+        println!("Bundles are identical");
+        return Ok(());
+    }
+
+    for diff in &diffs {
+        println!("{}", diff);
+    }
+    Err(format!("{} differences found", diffs.len()))
+}
+
+#[cfg(feature = "lzma")]
+async fn verify_bundle(args: VerifyBundleArgs) -> Result<(), String> {
+    use ffbuildtool::bundle::AssetBundle;
+
+    let (_, bundle) = AssetBundle::from_file(&args.input_bundle)?;
+    let diffs = bundle.verify_round_trip()?;
+    if diffs.is_empty() {
+        println!("Bundle survives extraction and repacking unchanged");
+        return Ok(());
+    }
+
+    for diff in &diffs {
+        println!("{}", diff);
+    }
+    Err(format!(
+        "{} files changed through the round trip",
+        diffs.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(addr: std::net::SocketAddr, path: &str, headers: &str) -> (u16, Vec<u8>) {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+            path, headers
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+
+        let header_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status_line = String::from_utf8_lossy(&response[..header_end]).to_string();
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, response[header_end + 4..].to_vec())
+    }
 
-        let mut pbs = PBS
-            .get_or_init(|| Mutex::new(HashMap::new()))
-            .lock()
+    #[cfg(feature = "lzma")]
+    #[test]
+    fn test_extract_build() {
+        use ffbuildtool::util;
+
+        let root = util::TempDir::new();
+        let build_dir = format!("{}/build", root.path());
+        let extract_dir = format!("{}/extracted", root.path());
+        std::fs::create_dir(&build_dir).unwrap();
+        for name in util::list_filenames_in_directory("example_builds/compressed/good").unwrap() {
+            std::fs::copy(
+                format!("example_builds/compressed/good/{}", name),
+                format!("{}/{}", build_dir, name),
+            )
             .unwrap();
-        let pb = pbs.entry(level_idx).or_insert_with(|| {
-            let pb = ProgressBar::new(total_files as u64);
-            pb.set_style(PB_TEMPLATE.clone());
-            pb
+        }
+        std::fs::copy(
+            "example_builds/compressed/good/Map_00_00.unity3d",
+            format!("{}/Extra.assetbundle", build_dir),
+        )
+        .unwrap();
+
+        let count = extract_build(
+            &build_dir,
+            &extract_dir,
+            "main.unity3d",
+            &[".assetbundle".to_string()],
+        )
+        .unwrap();
+        assert_eq!(count, 5);
+        let mut extracted = std::fs::read_dir(&extract_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        extracted.sort();
+        let mut expected = [
+            "DongResources_00_09.resourceFile",
+            "DongResources_03_01.resourceFile",
+            "Extra.assetbundle",
+            "Map_00_00.unity3d",
+            "Map_01_03.unity3d",
+        ]
+        .map(util::url_encode);
+        expected.sort();
+        assert_eq!(extracted, expected);
+    }
+
+    #[tokio::test]
+    async fn test_generate_manifest_failures() {
+        use std::io::Write as _;
+
+        use ffbuildtool::util;
+
+        let root = util::TempDir::new();
+        let output_path = format!("{}/manifest.json", root.path());
+        let gen_manifest = |build_path: &str, extra_args: &[&str]| {
+            let mut args = vec![
+                "ffbuildtool",
+                "gen-manifest",
+                "-b",
+                build_path,
+                "-u",
+                "http://example.url/builds/example_build/",
+                "-o",
+                &output_path,
+            ];
+            args.extend_from_slice(extra_args);
+            match Cli::try_parse_from(args).unwrap().command {
+                Commands::GenManifest(args) => args,
+                _ => unreachable!(),
+            }
+        };
+
+        let args = gen_manifest(
+            "example_builds/compressed/bad",
+            &["--validate-against", "example_manifest.json"],
+        );
+        assert!(generate_manifest(args).await.is_err());
+        assert!(!std::path::Path::new(&output_path).exists());
+
+        // trailing bytes after the bundle are only a warning
+        let build_dir = format!("{}/build", root.path());
+        std::fs::create_dir(&build_dir).unwrap();
+        for name in util::list_filenames_in_directory("example_builds/compressed/good").unwrap() {
+            std::fs::copy(
+                format!("example_builds/compressed/good/{}", name),
+                format!("{}/{}", build_dir, name),
+            )
+            .unwrap();
+        }
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(format!("{}/Map_00_00.unity3d", build_dir))
+            .unwrap()
+            .write_all(b"trailing garbage")
+            .unwrap();
+
+        let args = gen_manifest(&build_dir, &["--deny-warnings"]);
+        assert!(generate_manifest(args).await.is_err());
+        assert!(!std::path::Path::new(&output_path).exists());
+
+        let args = gen_manifest(&build_dir, &[]);
+        generate_manifest(args).await.unwrap();
+        assert!(std::path::Path::new(&output_path).exists());
+    }
+
+    #[test]
+    fn test_json_progress() {
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = SharedBuffer::default();
+        let progress =
+            ProgressManager::with_json_writer(ProgressFormat::Json, Box::new(buffer.clone()));
+        progress.update_item("Map_00_00.unity3d", ItemProgress::Validating);
+        progress.update_total(TotalProgress {
+            bytes_done: 10,
+            total_bytes: 20,
+            bytes_per_sec: 5,
+            eta: Some(Duration::from_secs(2)),
         });
+        progress.flush();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                serde_json::json!({
+                    "event": "item",
+                    "name": "Map_00_00.unity3d",
+                    "phase": "validating",
+                }),
+                serde_json::json!({
+                    "event": "total",
+                    "bytes": 10,
+                    "total": 20,
+                    "bytes_per_sec": 5,
+                    "eta_secs": 2,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_progress_queue_full() {
+        let (progress, receiver) =
+            ProgressManager::with_queue(ProgressFormat::Bars, Box::new(std::io::sink()), 2);
+        let downloading = |bytes_downloaded| ItemProgress::Downloading {
+            bytes_downloaded,
+            total_bytes: 100,
+            attempt: 1,
+            max_attempts: 5,
+        };
+        let recv_all = || {
+            let mut events = Vec::new();
+            while let Ok(event) = receiver.recv_timeout(Duration::ZERO) {
+                events.push(event);
+            }
+            events
+        };
 
-        pb.set_position(file as u64);
-        pb.set_message(current_file_name);
+        // nothing is drawing, so these would pile up if they weren't dropped
+        for bytes_downloaded in 0..100 {
+            progress.update_item("Map_00_00.unity3d", downloading(bytes_downloaded));
+        }
+        // but the end of the item is queued anyway, without waiting for room
+        progress.update_item(
+            "Map_00_00.unity3d",
+            ItemProgress::Passed {
+                item_size: 100,
+                duration: Duration::from_secs(1),
+                was_downloaded: true,
+            },
+        );
+        assert!(matches!(
+            recv_all()[..],
+            [
+                ProgressEvent::Item(
+                    _,
+                    ItemProgress::Downloading {
+                        bytes_downloaded: 0,
+                        ..
+                    }
+                ),
+                ProgressEvent::Item(
+                    _,
+                    ItemProgress::Downloading {
+                        bytes_downloaded: 1,
+                        ..
+                    }
+                ),
+                ProgressEvent::Item(_, ItemProgress::Passed { .. }),
+            ]
+        ));
+
+        // once the renderer catches up, there's room again
+        progress.update_item("Map_00_00.unity3d", downloading(100));
+        assert_eq!(recv_all().len(), 1);
     }
 
-    let start = Instant::now();
-    let bundle = AssetBundle::from_directory(&args.input_dir)?;
-    println!("Files read in {}ms", start.elapsed().as_millis());
+    #[tokio::test]
+    async fn test_serve() {
+        let root = ffbuildtool::util::TempDir::new();
+        let build_dir = format!("{}/build", root.path());
+        std::fs::create_dir(&build_dir).unwrap();
+        std::fs::write(format!("{}/bundle.unity3d", build_dir), b"bundle data").unwrap();
+        std::fs::write(format!("{}/secret", root.path()), b"secret").unwrap();
+
+        let state = Arc::new(ServeState {
+            manifest: b"{}".to_vec(),
+            asset_path: "/builds/example".to_string(),
+            build_path: std::fs::canonicalize(&build_dir).unwrap(),
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_connections(listener, state));
+
+        assert_eq!(
+            get(addr, "/builds/example/bundle.unity3d", "").await,
+            (200, b"bundle data".to_vec())
+        );
+        assert_eq!(
+            get(addr, "/builds/example/bundle%2eunity3d", "").await,
+            (200, b"bundle data".to_vec())
+        );
+        assert_eq!(get(addr, "/manifest.json", "").await, (200, b"{}".to_vec()));
+
+        let secret = std::fs::canonicalize(format!("{}/secret", root.path())).unwrap();
+        let traversals = [
+            format!("/builds/example/{}", secret.display()),
+            format!("/builds/example/%2f{}", secret.display()),
+            "/builds/example/../secret".to_string(),
+            "/builds/example/%2e%2e/secret".to_string(),
+            "/builds/example/..%2fsecret".to_string(),
+            "/builds/example/".to_string(),
+        ];
+        for path in traversals {
+            assert_eq!(get(addr, &path, "").await.0, 404, "{}", path);
+        }
 
-    let start = Instant::now();
-    bundle.to_file(&args.output_bundle, args.compression_level, Some(cb))?;
-    println!("Bundle created in {}ms", start.elapsed().as_millis());
+        assert_eq!(
+            get(
+                addr,
+                "/builds/example/bundle.unity3d",
+                "Range: bytes=7-\r\n"
+            )
+            .await,
+            (206, b"data".to_vec())
+        );
+        assert_eq!(
+            get(
+                addr,
+                "/builds/example/bundle.unity3d",
+                "Range: bytes=0-18446744073709551615\r\n"
+            )
+            .await,
+            (206, b"bundle data".to_vec())
+        );
+        assert_eq!(
+            get(
+                addr,
+                "/builds/example/bundle.unity3d",
+                "Range: bytes=20-\r\n"
+            )
+            .await
+            .0,
+            416
+        );
+    }
 
-    Ok(())
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-4", 10), Some((0, 5)));
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 10)));
+        assert_eq!(parse_range("bytes=5-", 10), Some((5, 10)));
+        assert_eq!(
+            parse_range("bytes=0-18446744073709551615", 10),
+            Some((0, 10))
+        );
+        assert_eq!(parse_range("bytes=10-", 10), None);
+        assert_eq!(parse_range("items=0-4", 10), None);
+    }
 }
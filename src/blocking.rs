@@ -0,0 +1,41 @@
+//! Synchronous wrappers around the async `Version` operations, for tools that don't run a tokio runtime.
+//! Each call runs the async version to completion on a new current-thread runtime,
+//! so these must not be called from within an async context.
+
+use std::future::Future;
+
+use crate::{Error, ProgressCallback, Version};
+
+fn block_on<T>(future: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(future)
+}
+
+/// Blocking version of `Version::validate_compressed`.
+pub fn validate_compressed(
+    version: &Version,
+    path: &str,
+    callback: Option<ProgressCallback>,
+) -> Result<Vec<String>, Error> {
+    block_on(version.validate_compressed(path, callback))
+}
+
+/// Blocking version of `Version::repair`.
+pub fn repair(
+    version: &Version,
+    path: &str,
+    callback: Option<ProgressCallback>,
+) -> Result<Vec<String>, Error> {
+    block_on(version.repair(path, callback))
+}
+
+/// Blocking version of `Version::download_compressed`.
+pub fn download_compressed(
+    version: &Version,
+    path: &str,
+    callback: Option<ProgressCallback>,
+) -> Result<(), Error> {
+    block_on(version.download_compressed(path, callback))
+}
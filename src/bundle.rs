@@ -1,8 +1,9 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
     io::{BufRead, BufReader, BufWriter, Read, Write},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 use countio::Counter;
@@ -12,19 +13,76 @@ use liblzma::{
     write::XzEncoder,
 };
 use log::*;
+use serde::Deserialize;
 
 use crate::{util, Error, FileInfo};
 
+/// Name of the optional file in a bundle's input directory that lists the files in each level.
+pub const BUNDLE_LAYOUT_FILE: &str = "layout.json";
+
+#[derive(Deserialize)]
+struct BundleLayout {
+    levels: Vec<Vec<String>>,
+}
+
 // level index, file index, total files, file name
 pub type CompressionCallback = fn(usize, usize, usize, String);
 
-fn get_lzma_encoder<W: Write>(writer: &mut W, level: u32) -> Result<XzEncoder<&mut W>, Error> {
-    let mut options = LzmaOptions::new_preset(level)?;
+// level index, file index, total files, file name
+pub type ExtractionCallback = fn(usize, usize, usize, String);
+
+// level index and error for each level that couldn't be read
+pub type FailedLevels = Vec<(usize, String)>;
+
+// level index and either the compressed level with its uncompressed size, or why it couldn't be written
+type CompressedLevel = (usize, Result<(Vec<u8>, usize), String>);
+
+/// Parameters for the LZMA encoder used when writing bundles.
+/// The defaults match the parameters used by the original FusionFall bundles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LzmaParams {
+    /// Compression preset, from 0 (fastest) to 9 (smallest).
+    pub level: u32,
+    /// Dictionary size in bytes. Larger dictionaries can find matches further apart,
+    /// which helps bundles with large repetitive assets, but raise the memory needed
+    /// to both compress and decompress. Must be at least 4 KiB.
+    pub dict_size: u32,
+    pub literal_context_bits: u32,
+    pub literal_position_bits: u32,
+    pub position_bits: u32,
+}
+impl Default for LzmaParams {
+    fn default() -> Self {
+        Self {
+            level: 4,
+            dict_size: 1 << 23,
+            literal_context_bits: 3,
+            literal_position_bits: 0,
+            position_bits: 2,
+        }
+    }
+}
+
+/// How the levels of a multi-level bundle are laid out when extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtractLayout {
+    /// Each level goes in its own `levelN` subdirectory.
+    #[default]
+    Nested,
+    /// Every file goes directly in the output directory, named `levelN__name`.
+    Flat,
+}
+
+fn get_lzma_encoder<'a, W: Write>(
+    writer: &'a mut W,
+    params: &LzmaParams,
+) -> Result<XzEncoder<&'a mut W>, Error> {
+    let mut options = LzmaOptions::new_preset(params.level)?;
     options
-        .literal_context_bits(3)
-        .literal_position_bits(0)
-        .position_bits(2)
-        .dict_size(1 << 23);
+        .literal_context_bits(params.literal_context_bits)
+        .literal_position_bits(params.literal_position_bits)
+        .position_bits(params.position_bits)
+        .dict_size(params.dict_size);
 
     let stream = Stream::new_lzma_encoder(&options)?;
     Ok(XzEncoder::new_stream(writer, stream))
@@ -47,16 +105,53 @@ fn write_u32<T: Write>(writer: &mut T, value: u32) -> Result<(), Error> {
     Ok(())
 }
 
-fn read_stringz<T: BufRead>(reader: &mut T) -> Result<String, Error> {
+/// Checks that a file name from a bundle can't point outside the directory it's extracted to,
+/// e.g. `../../etc/foo` or `/etc/foo`.
+fn check_file_name(name: &str) -> Result<(), String> {
+    let path = Path::new(name);
+    // backslashes are only separators on Windows, but a bundle may have been made there
+    let unsafe_name = name.is_empty()
+        || path.has_root()
+        || name.split(['/', '\\']).any(|part| part == "..")
+        || path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if unsafe_name {
+        return Err(format!("Unsafe file name in bundle: {:?}", name));
+    }
+    Ok(())
+}
+
+pub(crate) fn read_stringz<T: BufRead>(reader: &mut T) -> Result<String, Error> {
+    Ok(decode_string(read_raw_stringz(reader)?))
+}
+
+fn read_raw_stringz<T: BufRead>(reader: &mut T) -> Result<Vec<u8>, Error> {
     let mut buf = Vec::new();
     reader.read_until(0, &mut buf)?;
     buf.pop(); // Remove the null terminator
-    let string = String::from_utf8(buf)?;
-    Ok(string)
+    Ok(buf)
+}
+
+/// Strings that aren't valid UTF-8 are decoded as Latin-1, which some legacy asset names use,
+/// so one odd file name doesn't make the whole bundle unreadable.
+fn decode_string(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(string) => string,
+        Err(e) => {
+            let string: String = e.into_bytes().into_iter().map(char::from).collect();
+            warn!("String {} isn't valid UTF-8; decoded as Latin-1", string);
+            string
+        }
+    }
 }
 
 fn write_stringz<T: Write>(writer: &mut T, string: &str) -> Result<(), Error> {
-    writer.write_all(&[string.as_bytes(), &[0]].concat())?;
+    write_raw_stringz(writer, string.as_bytes())
+}
+
+fn write_raw_stringz<T: Write>(writer: &mut T, bytes: &[u8]) -> Result<(), Error> {
+    writer.write_all(&[bytes, &[0]].concat())?;
     Ok(())
 }
 
@@ -66,6 +161,11 @@ fn skip_exact<T: Read>(reader: &mut T, count: usize) -> Result<(), Error> {
     Ok(())
 }
 
+fn is_eof(e: &Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
 fn align<T: Into<usize> + From<usize>>(value: T, alignment: T) -> T {
     let value = value.into();
     let alignment = alignment.into();
@@ -73,17 +173,63 @@ fn align<T: Into<usize> + From<usize>>(value: T, alignment: T) -> T {
     aligned.into()
 }
 
+/// Where a level ends in the bundle, relative to the end of the header (compressed)
+/// and the start of the first level's data (uncompressed).
 #[derive(Debug, PartialEq, Eq)]
-struct LevelEnds {
+pub struct LevelEnds {
     compressed_end: u32,
     uncompressed_end: u32,
 }
+impl LevelEnds {
+    pub fn get_compressed_end(&self) -> u32 {
+        self.compressed_end
+    }
+
+    pub fn get_uncompressed_end(&self) -> u32 {
+        self.uncompressed_end
+    }
+}
 
 const EXPECTED_SIGNATURE: &str = "UnityWeb";
 const EXPECTED_STREAM_VERSION: u32 = 2;
+// version 3 only adds the size of the first level's file table after the bundle size
+const COMPATIBLE_STREAM_VERSIONS: &[u32] = &[EXPECTED_STREAM_VERSION, 3];
 const EXPECTED_PLAYER_VERSION: &str = "fusion-2.x.x";
 const EXPECTED_ENGINE_VERSION_BASE: &str = "2";
 const DEFAULT_ENGINE_VERSION: &str = "2.5.4b5";
+// signature, stream version, empty player version, shortest engine version, size fields, bundle size
+const MIN_HEADER_SIZE: usize = 9 + 4 + 1 + 2 + 16 + 4;
+
+/// Version strings written to the header of packed bundles.
+/// The defaults match the original FusionFall client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderVersions {
+    pub player_version: String,
+    /// Must start with the major engine version the client expects (`2`).
+    pub engine_version: String,
+}
+impl HeaderVersions {
+    fn check(&self) -> Result<(), String> {
+        if !self
+            .engine_version
+            .starts_with(EXPECTED_ENGINE_VERSION_BASE)
+        {
+            return Err(format!(
+                "Invalid engine version: {}, must start with {}",
+                self.engine_version, EXPECTED_ENGINE_VERSION_BASE
+            ));
+        }
+        Ok(())
+    }
+}
+impl Default for HeaderVersions {
+    fn default() -> Self {
+        Self {
+            player_version: EXPECTED_PLAYER_VERSION.to_string(),
+            engine_version: DEFAULT_ENGINE_VERSION.to_string(),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct AssetBundleHeader {
@@ -97,6 +243,7 @@ pub struct AssetBundleHeader {
     min_levels_for_load: u32,
     level_ends: Vec<LevelEnds>,
     bundle_size: u32,
+    data_header_size: Option<u32>,
 }
 impl std::fmt::Display for AssetBundleHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -121,21 +268,72 @@ impl std::fmt::Display for AssetBundleHeader {
             "Bundle size: {} ({} bytes)",
             util::bytes_to_human_readable(self.bundle_size),
             self.bundle_size
-        )
+        )?;
+        if let Some(data_header_size) = self.data_header_size {
+            write!(f, "\nData header size: {}", data_header_size)?;
+        }
+        Ok(())
     }
 }
 impl AssetBundleHeader {
-    fn new(level_ends: Vec<LevelEnds>) -> Self {
+    pub fn get_signature(&self) -> &str {
+        &self.signature
+    }
+
+    pub fn get_stream_version(&self) -> u32 {
+        self.stream_version
+    }
+
+    pub fn get_player_version(&self) -> &str {
+        &self.player_version
+    }
+
+    pub fn get_engine_version(&self) -> &str {
+        &self.engine_version
+    }
+
+    pub fn get_min_streamed_bytes(&self) -> u32 {
+        self.min_streamed_bytes
+    }
+
+    pub fn get_header_size(&self) -> u32 {
+        self.header_size
+    }
+
+    pub fn get_num_levels(&self) -> u32 {
+        self.num_levels
+    }
+
+    pub fn get_min_levels_for_load(&self) -> u32 {
+        self.min_levels_for_load
+    }
+
+    pub fn get_level_ends(&self) -> &[LevelEnds] {
+        &self.level_ends
+    }
+
+    /// Returns the total size of the bundle in bytes, as recorded in the header.
+    pub fn get_bundle_size(&self) -> u32 {
+        self.bundle_size
+    }
+
+    /// Returns the size of the first level's file table, which is only in stream version 3 headers.
+    pub fn get_data_header_size(&self) -> Option<u32> {
+        self.data_header_size
+    }
+
+    fn new(level_ends: Vec<LevelEnds>, versions: &HeaderVersions) -> Self {
         let num_levels = level_ends.len() as u32;
         let mut header = Self {
             signature: EXPECTED_SIGNATURE.to_string(),
             stream_version: EXPECTED_STREAM_VERSION,
-            player_version: EXPECTED_PLAYER_VERSION.to_string(),
-            engine_version: DEFAULT_ENGINE_VERSION.to_string(),
+            player_version: versions.player_version.clone(),
+            engine_version: versions.engine_version.clone(),
             num_levels,
             min_levels_for_load: 1,
             level_ends,
             bundle_size: 0,
+            data_header_size: None,
             min_streamed_bytes: 0,
             header_size: 0,
         };
@@ -143,7 +341,9 @@ impl AssetBundleHeader {
         header
     }
 
-    fn read<R: Read + BufRead>(reader: &mut R) -> Result<Self, Error> {
+    /// Reads the header of a bundle file that's `expected_size` bytes long.
+    /// Nothing is allocated from the declared counts, since they can't be trusted.
+    fn read<R: Read + BufRead>(reader: &mut R, expected_size: u32) -> Result<Self, Error> {
         let signature = read_stringz(reader)?;
         if signature != EXPECTED_SIGNATURE {
             return Err(format!(
@@ -154,21 +354,21 @@ impl AssetBundleHeader {
         }
 
         let stream_version = read_u32(reader)?;
-        if stream_version != EXPECTED_STREAM_VERSION {
+        if !COMPATIBLE_STREAM_VERSIONS.contains(&stream_version) {
             return Err(format!(
-                "Unexpected stream version: {}, expected {}",
-                stream_version, EXPECTED_STREAM_VERSION
+                "Unsupported stream version: {}, expected one of {:?}",
+                stream_version, COMPATIBLE_STREAM_VERSIONS
             )
             .into());
         }
 
         let player_version = read_stringz(reader)?;
         if player_version != EXPECTED_PLAYER_VERSION {
-            return Err(format!(
+            // bundles may be repacked for other clients; the engine version is what matters
+            warn!(
                 "Unexpected player version: {}, expected {}",
                 player_version, EXPECTED_PLAYER_VERSION
-            )
-            .into());
+            );
         }
 
         let engine_version = read_stringz(reader)?;
@@ -182,8 +382,22 @@ impl AssetBundleHeader {
 
         let min_streamed_bytes = read_u32(reader)?;
         let header_size = read_u32(reader)?;
+        if header_size > expected_size {
+            return Err(format!(
+                "Bundle truncated: header is {} bytes, but the file is only {} bytes",
+                header_size, expected_size
+            )
+            .into());
+        }
         let min_levels_for_load = read_u32(reader)?;
         let num_levels = read_u32(reader)?;
+        if num_levels as usize * 8 > header_size as usize {
+            return Err(format!(
+                "Header declares {} levels, which can't fit in {} bytes",
+                num_levels, header_size
+            )
+            .into());
+        }
         if num_levels < min_levels_for_load {
             return Err(format!(
                 "Number of levels ({}) is less than the minimum for load ({})",
@@ -192,7 +406,7 @@ impl AssetBundleHeader {
             .into());
         }
 
-        let mut level_ends = Vec::with_capacity(num_levels as usize);
+        let mut level_ends = Vec::new();
         for _ in 0..num_levels {
             let compressed_end = read_u32(reader)?;
             let uncompressed_end = read_u32(reader)?;
@@ -203,6 +417,10 @@ impl AssetBundleHeader {
         }
 
         let bundle_size = read_u32(reader)?;
+        let data_header_size = match stream_version {
+            EXPECTED_STREAM_VERSION => None,
+            _ => Some(read_u32(reader)?),
+        };
 
         Ok(Self {
             signature,
@@ -215,6 +433,7 @@ impl AssetBundleHeader {
             min_levels_for_load,
             level_ends,
             bundle_size,
+            data_header_size,
         })
     }
 
@@ -234,6 +453,9 @@ impl AssetBundleHeader {
             write_u32(&mut writer, level.uncompressed_end)?;
         }
         write_u32(&mut writer, self.bundle_size)?;
+        if let Some(data_header_size) = self.data_header_size {
+            write_u32(&mut writer, data_header_size)?;
+        }
 
         // padding
         let padding_size = self.header_size as usize - writer.writer_bytes();
@@ -252,7 +474,8 @@ impl AssetBundleHeader {
             + 4 // min_levels_for_load
             + 4 // num_levels
             + self.level_ends.len() * 8 // level_ends
-            + 4; // bundle_size
+            + 4 // bundle_size
+            + self.data_header_size.map_or(0, |_| 4); // data_header_size
         align(size, 4)
     }
 
@@ -267,6 +490,8 @@ impl AssetBundleHeader {
 #[derive(Debug)]
 struct LevelFileMetadata {
     name: String,
+    /// The name as it's stored in the level.
+    raw_name: Vec<u8>,
     offset: u32,
     size: u32,
 }
@@ -277,22 +502,61 @@ struct LevelHeader {
     files: Vec<LevelFileMetadata>,
 }
 impl LevelHeader {
-    fn read<R: Read + BufRead>(reader: &mut R) -> Result<Self, Error> {
+    fn read<R: Read + BufRead>(reader: &mut R, level_size: usize) -> Result<Self, Error> {
         let num_files = read_u32(reader)?;
-        let mut files = Vec::with_capacity(num_files as usize);
+        // each entry is at least a null terminator plus offset and size
+        if num_files as usize * 9 > level_size {
+            return Err(format!(
+                "Level declares {} files, which can't fit in {} bytes",
+                num_files, level_size
+            )
+            .into());
+        }
+
+        let mut files = Vec::new();
         for _ in 0..num_files {
-            let name = read_stringz(reader)?;
+            let raw_name = read_raw_stringz(reader)?;
+            let name = decode_string(raw_name.clone());
             let offset = read_u32(reader)?;
             let size = read_u32(reader)?;
-            files.push(LevelFileMetadata { name, offset, size });
+            files.push(LevelFileMetadata {
+                name,
+                raw_name,
+                offset,
+                size,
+            });
         }
         Ok(Self { num_files, files })
     }
 
+    /// Checks that the files are laid out in order after the file table, which ends at `header_end`,
+    /// and that none of them overlap or extend past the end of the level.
+    fn check_layout(&self, header_end: usize, level_size: usize) -> Result<(), Error> {
+        let mut offset = header_end;
+        for file in &self.files {
+            if (file.offset as usize) < offset {
+                return Err(format!(
+                    "File {} starts at {}, which overlaps the previous data ending at {}",
+                    file.name, file.offset, offset
+                )
+                .into());
+            }
+            offset = file.offset as usize + file.size as usize;
+            if offset > level_size {
+                return Err(format!(
+                    "File {} ({} bytes at {}) extends past the end of the level ({} bytes)",
+                    file.name, file.size, file.offset, level_size
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
     fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
         write_u32(writer, self.num_files)?;
         for file in &self.files {
-            write_stringz(writer, &file.name)?;
+            write_raw_stringz(writer, &file.raw_name)?;
             write_u32(writer, file.offset)?;
             write_u32(writer, file.size)?;
         }
@@ -302,6 +566,8 @@ impl LevelHeader {
 
 struct LevelFile {
     name: String,
+    /// The name as it was stored in the bundle, if it isn't valid UTF-8 and so doesn't match `name`.
+    raw_name: Option<Vec<u8>>,
     data: Vec<u8>,
     hash: Option<String>,
 }
@@ -345,10 +611,16 @@ impl LevelFile {
     fn new(name: String, data: Vec<u8>) -> Self {
         Self {
             name,
+            raw_name: None,
             data,
             hash: None,
         }
     }
+
+    /// Returns the name as it's written to the bundle.
+    fn get_raw_name(&self) -> &[u8] {
+        self.raw_name.as_deref().unwrap_or(self.name.as_bytes())
+    }
 }
 
 #[derive(Debug)]
@@ -369,29 +641,54 @@ impl PartialEq for Level {
 }
 impl Eq for Level {}
 impl Level {
-    fn read<R: Read + BufRead>(reader: &mut R) -> Result<Self, Error> {
+    fn read<R: Read + BufRead>(reader: &mut R, level_size: usize) -> Result<Self, Error> {
         let mut reader = Counter::new(BufReader::new(get_lzma_decoder(reader)?));
-        let header = LevelHeader::read(&mut reader)?;
+        let header = LevelHeader::read(&mut reader, level_size)?;
+        header.check_layout(reader.reader_bytes(), level_size)?;
 
         let mut files = Vec::with_capacity(header.num_files as usize);
         for file in header.files {
-            let offset = reader.reader_bytes();
-            skip_exact(&mut reader, file.offset as usize - offset)?;
-            let mut data = vec![0; file.size as usize];
-            reader.read_exact(&mut data)?;
-            files.push(LevelFile::new(file.name, data));
+            let padding = file.offset as usize - reader.reader_bytes();
+            skip_exact(&mut reader, padding)?;
+            // the size comes from the level header, so let the data itself bound the allocation
+            let mut data = Vec::new();
+            (&mut reader)
+                .take(file.size as u64)
+                .read_to_end(&mut data)?;
+            if data.len() != file.size as usize {
+                return Err(format!(
+                    "Level data ends {} bytes into {}, which should be {} bytes",
+                    data.len(),
+                    file.name,
+                    file.size
+                )
+                .into());
+            }
+            let mut level_file = LevelFile::new(file.name, data);
+            // names that had to be decoded as Latin-1 are written back byte for byte
+            if file.raw_name != level_file.name.as_bytes() {
+                level_file.raw_name = Some(file.raw_name);
+            }
+            files.push(level_file);
         }
         Ok(Self { files })
     }
 
+    /// Decompresses only the file table of the level and checks its layout, without reading any file data.
+    fn verify<R: Read + BufRead>(reader: &mut R, level_size: usize) -> Result<(), Error> {
+        let mut reader = Counter::new(BufReader::new(get_lzma_decoder(reader)?));
+        let header = LevelHeader::read(&mut reader, level_size)?;
+        header.check_layout(reader.reader_bytes(), level_size)
+    }
+
     fn write<W: Write>(
         &self,
         writer: &mut W,
-        compression: u32,
+        params: &LzmaParams,
         level_idx: usize,
         callback: Option<CompressionCallback>,
     ) -> Result<usize, Error> {
-        let mut writer = Counter::new(get_lzma_encoder(writer, compression)?);
+        let mut writer = Counter::new(get_lzma_encoder(writer, params)?);
         let header = self.gen_header();
         header.write(&mut writer)?;
 
@@ -429,6 +726,7 @@ impl Level {
             let size = file.data.len();
             files.push(LevelFileMetadata {
                 name: file.name.clone(),
+                raw_name: file.get_raw_name().to_vec(),
                 offset: offset as u32,
                 size: size as u32,
             });
@@ -446,7 +744,7 @@ impl Level {
     fn get_header_size(&self) -> usize {
         4 // num_files
             + self.files.iter().map(|file| {
-                file.name.len() + 1 // name (+ null byte)
+                file.get_raw_name().len() + 1 // name (+ null byte)
                     + 4 // offset
                     + 4 // size
             })
@@ -475,9 +773,45 @@ impl AssetBundle {
         reader: &mut R,
         expected_size: u32,
     ) -> Result<(AssetBundleHeader, Self), Error> {
-        let mut reader = Counter::new(reader);
+        let (header, bundle, _) = Self::read_internal(reader, expected_size, false)?;
+        Ok((header, bundle))
+    }
+
+    /// Like `read`, but a level that can't be read is left empty and reported instead of failing the bundle.
+    fn read_lenient<R: Read + BufRead>(
+        reader: &mut R,
+        expected_size: u32,
+    ) -> Result<(AssetBundleHeader, Self, FailedLevels), Error> {
+        Self::read_internal(reader, expected_size, true)
+    }
 
-        let header = AssetBundleHeader::read(&mut reader)?;
+    /// Reads and sanity-checks the bundle header, leaving the reader at the start of the first level.
+    fn read_header<R: Read + BufRead>(
+        reader: &mut Counter<R>,
+        expected_size: u32,
+    ) -> Result<AssetBundleHeader, Error> {
+        let truncated_header_err = format!(
+            "Bundle truncated: expected header of at least {} bytes, but the file is only {} bytes",
+            MIN_HEADER_SIZE, expected_size
+        );
+        if (expected_size as usize) < MIN_HEADER_SIZE {
+            return Err(truncated_header_err.into());
+        }
+        let header = AssetBundleHeader::read(reader, expected_size).map_err(|e| -> Error {
+            if is_eof(&e) {
+                truncated_header_err.into()
+            } else {
+                e
+            }
+        })?;
+        let declared_size = header.header_size.max(header.bundle_size);
+        if declared_size > expected_size {
+            return Err(format!(
+                "Bundle truncated: expected {} bytes, but the file is only {} bytes",
+                declared_size, expected_size
+            )
+            .into());
+        }
         if header.bundle_size != expected_size {
             warn!(
                 "Bundle size mismatch: {} != {}",
@@ -487,71 +821,176 @@ impl AssetBundle {
 
         // seek to first level
         let offset = reader.reader_bytes();
-        skip_exact(&mut reader, header.header_size as usize - offset)?;
+        let Some(padding) = (header.header_size as usize).checked_sub(offset) else {
+            return Err(format!(
+                "Header size {} is smaller than the header itself ({} bytes)",
+                header.header_size, offset
+            )
+            .into());
+        };
+        skip_exact(reader, padding)?;
+        Ok(header)
+    }
+
+    /// Returns the compressed and uncompressed size of each level, as declared in the header.
+    fn get_level_sizes(
+        header: &AssetBundleHeader,
+        expected_size: u32,
+    ) -> Vec<Result<(u32, u32), String>> {
+        let mut prev_compressed_end = 0;
+        let mut prev_uncompressed_end = 0;
+        let mut sizes = Vec::with_capacity(header.level_ends.len());
+        for (i, ends) in header.level_ends.iter().enumerate() {
+            let compressed_size = ends.compressed_end.checked_sub(prev_compressed_end);
+            let uncompressed_size = ends.uncompressed_end.checked_sub(prev_uncompressed_end);
+            let level_end = header.header_size as u64 + ends.compressed_end as u64;
+            sizes.push(match (compressed_size, uncompressed_size) {
+                (Some(_), Some(_)) if level_end > expected_size as u64 => Err(format!(
+                    "Bundle truncated: level {} ends at byte {}, but the file is only {} bytes",
+                    i, level_end, expected_size
+                )),
+                (Some(compressed_size), Some(uncompressed_size)) => {
+                    Ok((compressed_size, uncompressed_size))
+                }
+                _ => Err(format!("Level {} ends before the previous level", i)),
+            });
+            prev_compressed_end = ends.compressed_end;
+            prev_uncompressed_end = ends.uncompressed_end;
+        }
+        sizes
+    }
+
+    fn read_internal<R: Read + BufRead>(
+        reader: &mut R,
+        expected_size: u32,
+        lenient: bool,
+    ) -> Result<(AssetBundleHeader, Self, FailedLevels), Error> {
+        let mut reader = Counter::new(reader);
+        let header = Self::read_header(&mut reader, expected_size)?;
+
+        let level_sizes = Self::get_level_sizes(&header, expected_size);
+        let num_levels = level_sizes.len();
+        let mut levels = Vec::with_capacity(num_levels);
+        let mut failed_levels = Vec::new();
+        for (i, sizes) in level_sizes.into_iter().enumerate() {
+            let (compressed_size, uncompressed_size) = match sizes {
+                Ok(sizes) => sizes,
+                Err(e) if lenient => {
+                    // we can't tell where the following levels start, so none of them can be read
+                    warn!("{}", e);
+                    for j in i..num_levels {
+                        failed_levels.push((j, e.clone()));
+                        levels.push(Level { files: Vec::new() });
+                    }
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
-        let mut levels = Vec::with_capacity(header.num_levels as usize);
-        for i in 0..header.num_levels {
-            let level = Level::read(&mut reader)?;
+            // Limit the decoder to this level's bytes so it can't read ahead into the next one
+            let mut level_reader = (&mut reader).take(compressed_size as u64);
+            let level = match Level::read(&mut level_reader, uncompressed_size as usize) {
+                Ok(level) => level,
+                Err(e) if lenient => {
+                    warn!("Couldn't read level {}: {}", i, e);
+                    failed_levels.push((i, e.to_string()));
+                    Level { files: Vec::new() }
+                }
+                Err(e) => return Err(format!("Couldn't read level {}: {}", i, e).into()),
+            };
+            std::io::copy(&mut level_reader, &mut std::io::sink())?;
             levels.push(level);
-            if i + 1 < header.num_levels {
-                let offset = reader.reader_bytes();
-                skip_exact(
-                    &mut reader,
-                    header.level_ends[i as usize + 1].compressed_end as usize - offset,
-                )?;
-            }
         }
 
-        Ok((header, Self { levels }))
+        Ok((header, Self { levels }, failed_levels))
+    }
+
+    fn verify<R: Read + BufRead>(reader: &mut R, expected_size: u32) -> Result<(), Error> {
+        let mut reader = Counter::new(reader);
+        let header = Self::read_header(&mut reader, expected_size)?;
+        for (i, sizes) in Self::get_level_sizes(&header, expected_size)
+            .into_iter()
+            .enumerate()
+        {
+            let (compressed_size, uncompressed_size) = sizes?;
+            let mut level_reader = (&mut reader).take(compressed_size as u64);
+            Level::verify(&mut level_reader, uncompressed_size as usize)
+                .map_err(|e| format!("Couldn't read level {}: {}", i, e))?;
+            // skip the rest of the level without decompressing it
+            std::io::copy(&mut level_reader, &mut std::io::sink())?;
+        }
+        Ok(())
     }
 
     fn write<W: Write>(
         &self,
         writer: &mut W,
-        compression: u32,
+        params: &LzmaParams,
+        versions: &HeaderVersions,
         callback: Option<CompressionCallback>,
+        max_threads: usize,
     ) -> Result<(), Error> {
+        versions.check()?;
+
+        // Each level is a separate LZMA stream, so they can be compressed in parallel
+        let max_threads = match max_threads {
+            0 => num_cpus::get(),
+            n => n,
+        };
+        let num_threads = max_threads.min(self.levels.len()).max(1);
+        let next_level = AtomicUsize::new(0);
+        let mut compressed_levels: Vec<CompressedLevel> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..num_threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut compressed = Vec::new();
+                        loop {
+                            let idx = next_level.fetch_add(1, Ordering::Relaxed);
+                            let Some(level) = self.levels.get(idx) else {
+                                break;
+                            };
+                            let mut level_buf = Vec::new();
+                            let result = level
+                                .write(&mut level_buf, params, idx, callback)
+                                .map(|level_size_uncompressed| (level_buf, level_size_uncompressed))
+                                .map_err(|e| format!("Couldn't write level {}: {}", idx, e));
+                            compressed.push((idx, result));
+                        }
+                        compressed
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        compressed_levels.sort_by_key(|(idx, _)| *idx);
+
         let mut buf = Vec::new();
-        let mut buf_writer = Counter::new(&mut buf);
         let mut uncompressed_bytes_written = 0;
-
-        let mut level_sizes_uncompressed = Vec::with_capacity(self.levels.len());
         let mut level_ends = Vec::with_capacity(self.levels.len());
-        for (idx, level) in self.levels.iter().enumerate() {
-            let level_size_uncompressed =
-                level.write(&mut buf_writer, compression, idx, callback)? as u64;
-            uncompressed_bytes_written += level_size_uncompressed;
-            level_sizes_uncompressed.push(level_size_uncompressed);
+        for (_, compressed_level) in compressed_levels {
+            let (mut level_buf, level_size_uncompressed) = compressed_level?;
+
+            // The LZMA_alone encoder does not write the correct buffer size
+            // to the header (it writes all 0xFFs), so sub it in.
+            let level_size_uncompressed_start = 1 // properties byte
+                + 4; // dict size
+            let slice =
+                &mut level_buf[level_size_uncompressed_start..level_size_uncompressed_start + 8];
+            assert!(slice == [0xFF; 8]);
+            slice.copy_from_slice(&(level_size_uncompressed as u64).to_le_bytes());
 
-            let uncompressed_end = uncompressed_bytes_written as u32;
-            let compressed_end = buf_writer.writer_bytes() as u32;
+            uncompressed_bytes_written += level_size_uncompressed;
+            buf.extend_from_slice(&level_buf);
             level_ends.push(LevelEnds {
-                uncompressed_end,
-                compressed_end,
+                uncompressed_end: uncompressed_bytes_written as u32,
+                compressed_end: buf.len() as u32,
             });
         }
 
-        // The LZMA_alone encoder does not write the correct buffer sizes
-        // to the headers (it writes all 0xFFs), so sub them in.
-        for i in 0..self.levels.len() {
-            let level_start = if i == 0 {
-                0
-            } else {
-                level_ends[i - 1].compressed_end
-            };
-
-            let level_size_uncompressed = level_sizes_uncompressed[i];
-            let level_size_uncompressed_start = (level_start
-                + 1 // properties byte
-                + 4) // dict size
-                as usize;
-
-            let slice = &mut buf[level_size_uncompressed_start..level_size_uncompressed_start + 8];
-            assert!(slice == [0xFF; 8]);
-            slice.copy_from_slice(&level_size_uncompressed.to_le_bytes());
-        }
-
-        let header = AssetBundleHeader::new(level_ends);
+        let header = AssetBundleHeader::new(level_ends, versions);
         header.write(writer)?;
         writer.write_all(&buf)?;
         Ok(())
@@ -574,26 +1013,145 @@ impl AssetBundle {
                 files.push(LevelFile::new(name.to_string(), data));
             }
         }
+        // `read_dir` order is platform-dependent; sort so the same inputs always pack the same way
+        files.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(files)
     }
 
     pub fn from_file(path: &str) -> Result<(AssetBundleHeader, Self), String> {
         let file = File::open(path).map_err(|e| format!("Couldn't open file {}: {}", path, e))?;
         let metadata = file.metadata().unwrap();
+        if metadata.len() == 0 {
+            return Err(format!("Bundle {} is empty; the file is incomplete", path));
+        }
         let mut reader = BufReader::new(file);
         Self::read(&mut reader, metadata.len() as u32)
             .map_err(|e| format!("Couldn't read bundle: {}", e))
     }
 
+    /// Checks that the bundle at `path` is structurally sound: that its header and the file table
+    /// of each level are readable and that the declared offsets and sizes are consistent.
+    /// Only the file tables are decompressed, so this is much faster than `from_file`.
+    pub fn verify_structure(path: &str) -> Result<(), String> {
+        let file = File::open(path).map_err(|e| format!("Couldn't open file {}: {}", path, e))?;
+        let metadata = file.metadata().unwrap();
+        if metadata.len() == 0 {
+            return Err(format!("Bundle {} is empty; the file is incomplete", path));
+        }
+        let mut reader = BufReader::new(file);
+        Self::verify(&mut reader, metadata.len() as u32)
+            .map_err(|e| format!("Bad bundle structure: {}", e))
+    }
+
+    /// Reads a bundle from a file, salvaging what it can from a partially corrupt one.
+    /// Levels that can't be read are left empty so the other levels keep their indices,
+    /// and are returned with their errors. The header must still be readable.
+    pub fn from_file_lenient(
+        path: &str,
+    ) -> Result<(AssetBundleHeader, Self, FailedLevels), String> {
+        let file = File::open(path).map_err(|e| format!("Couldn't open file {}: {}", path, e))?;
+        let metadata = file.metadata().unwrap();
+        if metadata.len() == 0 {
+            return Err(format!("Bundle {} is empty; the file is incomplete", path));
+        }
+        let mut reader = BufReader::new(file);
+        Self::read_lenient(&mut reader, metadata.len() as u32)
+            .map_err(|e| format!("Couldn't read bundle: {}", e))
+    }
+
+    /// Builds a bundle from a directory. Duplicate file names within a level are logged as warnings.
+    /// If the directory contains a `layout.json` (see `BUNDLE_LAYOUT_FILE`), the levels are built from it
+    /// instead, e.g. `{"levels": [["level0/a", "level0/b"], ["level1/c"]]}` with paths relative to the directory.
     pub fn from_directory(path: &str) -> Result<Self, String> {
-        // each subdirectory with the name `levelX` contains the files for that level.
+        let bundle = Self::from_directory_internal(path)?;
+        for (level_idx, name) in bundle.find_duplicate_files() {
+            warn!("Duplicate file {} in level {}", name, level_idx);
+        }
+        Ok(bundle)
+    }
+
+    /// Builds a bundle from a directory, failing if any level would contain duplicate file names,
+    /// e.g. when a loose file has the same name as a file in `level0`.
+    pub fn from_directory_strict(path: &str) -> Result<Self, String> {
+        let bundle = Self::from_directory_internal(path)?;
+        let duplicates = bundle.find_duplicate_files();
+        if !duplicates.is_empty() {
+            let list = duplicates
+                .iter()
+                .map(|(level_idx, name)| format!("{} (level {})", name, level_idx))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(format!("Duplicate files in bundle: {}", list));
+        }
+        Ok(bundle)
+    }
+
+    /// Builds a bundle from in-memory files, given as name and data pairs for each level.
+    /// Duplicate file names within a level are logged as warnings.
+    pub fn from_files(levels: Vec<Vec<(String, Vec<u8>)>>) -> Self {
+        let mut levels: Vec<Level> = levels
+            .into_iter()
+            .map(|files| Level {
+                files: files
+                    .into_iter()
+                    .map(|(name, data)| LevelFile::new(name, data))
+                    .collect(),
+            })
+            .collect();
+        if levels.is_empty() {
+            levels.push(Level { files: Vec::new() });
+        }
+
+        let bundle = Self { levels };
+        for (level_idx, name) in bundle.find_duplicate_files() {
+            warn!("Duplicate file {} in level {}", name, level_idx);
+        }
+        bundle
+    }
+
+    /// Builds the levels from the `layout.json` in the root directory, which lists
+    /// the paths of the files in each level, in order, relative to the root.
+    fn from_layout_file(root_path: &Path, layout_path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(layout_path)
+            .map_err(|e| format!("Couldn't read layout {}: {}", layout_path.display(), e))?;
+        let layout: BundleLayout = serde_json::from_str(&json)
+            .map_err(|e| format!("Couldn't parse layout {}: {}", layout_path.display(), e))?;
+
+        let mut levels = Vec::with_capacity(layout.levels.len());
+        for file_paths in layout.levels {
+            let mut files = Vec::with_capacity(file_paths.len());
+            for file_path in file_paths {
+                let full_path = root_path.join(&file_path);
+                let Some(name) = full_path.file_name().and_then(|name| name.to_str()) else {
+                    return Err(format!("Invalid file path in layout: {}", file_path));
+                };
+                let data = std::fs::read(&full_path)
+                    .map_err(|e| format!("Couldn't read file {}: {}", full_path.display(), e))?;
+                files.push(LevelFile::new(name.to_string(), data));
+            }
+            levels.push(Level { files });
+        }
+        if levels.is_empty() {
+            levels.push(Level { files: Vec::new() });
+        }
+        Ok(Self { levels })
+    }
+
+    fn from_directory_internal(path: &str) -> Result<Self, String> {
+        // if there's a layout file, it decides exactly which files go in each level and in what order.
+        // otherwise, each subdirectory with the name `levelX` contains the files for that level.
         // they must be in order-- starting from level0-- for their files to be included.
-        // all loose files get put at the end of level0.
+        // all loose files get put at the end of level0. files are sorted by name within each directory.
         let root_path = PathBuf::from(path);
         if !root_path.is_dir() {
             return Err(format!("Invalid root directory: {}", path));
         }
 
+        let layout_path = root_path.join(BUNDLE_LAYOUT_FILE);
+        if layout_path.is_file() {
+            return Self::from_layout_file(&root_path, &layout_path);
+        }
+
         let mut levels = Vec::new();
         for i in 0.. {
             let level_dir = root_path.join(format!("level{}", i));
@@ -626,16 +1184,54 @@ impl AssetBundle {
         Ok(Self { levels })
     }
 
+    /// Returns the level index and name of every file that shares its name with an earlier file in the same level.
+    fn find_duplicate_files(&self) -> Vec<(usize, String)> {
+        let mut duplicates = Vec::new();
+        for (level_idx, level) in self.levels.iter().enumerate() {
+            let mut seen = HashSet::new();
+            for file in &level.files {
+                if !seen.insert(file.name.as_str()) {
+                    duplicates.push((level_idx, file.name.clone()));
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Writes the bundle to a file, compressing each level with the given LZMA parameters.
+    /// The dictionary size is recorded in each level's LZMA header, so bundles written
+    /// with any dictionary size can be read back; however, the Unity player has to
+    /// allocate the whole dictionary to decompress a level, so very large dictionaries
+    /// may cause the game to run out of memory when loading the bundle.
+    /// The player and engine versions in the bundle header are taken from `versions`.
+    /// Levels are compressed in parallel, one thread per CPU, so `callback` is called from
+    /// several threads at once and the calls for different levels are interleaved.
     pub fn to_file(
         &self,
         path: &str,
-        compression_level: u32,
+        params: &LzmaParams,
+        versions: &HeaderVersions,
+        callback: Option<CompressionCallback>,
+    ) -> Result<(), String> {
+        self.to_file_parallel(path, params, versions, callback, 0)
+    }
+
+    /// Like `to_file`, but compresses up to `max_threads` levels at once.
+    /// A `max_threads` of 0 uses one thread per CPU; with 1, `callback` is called in order.
+    pub fn to_file_parallel(
+        &self,
+        path: &str,
+        params: &LzmaParams,
+        versions: &HeaderVersions,
         callback: Option<CompressionCallback>,
+        max_threads: usize,
     ) -> Result<(), String> {
+        // checked up front so a bad version doesn't leave an empty file behind
+        versions.check()?;
         let file =
             File::create(path).map_err(|e| format!("Couldn't create file {}: {}", path, e))?;
         let mut writer = BufWriter::new(file);
-        self.write(&mut writer, compression_level, callback)
+        self.write(&mut writer, params, versions, callback, max_threads)
             .map_err(|e| format!("Couldn't write bundle: {}", e))?;
         writer
             .flush()
@@ -643,9 +1239,88 @@ impl AssetBundle {
         Ok(())
     }
 
+    /// Same as `to_file`, but returns the bundle bytes instead of writing them to disk.
+    pub fn to_buffer(
+        &self,
+        params: &LzmaParams,
+        versions: &HeaderVersions,
+        callback: Option<CompressionCallback>,
+    ) -> Result<Vec<u8>, String> {
+        self.to_buffer_parallel(params, versions, callback, 0)
+    }
+
+    /// Same as `to_file_parallel`, but returns the bundle bytes instead of writing them to disk.
+    pub fn to_buffer_parallel(
+        &self,
+        params: &LzmaParams,
+        versions: &HeaderVersions,
+        callback: Option<CompressionCallback>,
+        max_threads: usize,
+    ) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+        self.write(&mut buffer, params, versions, callback, max_threads)
+            .map_err(|e| format!("Couldn't write bundle: {}", e))?;
+        Ok(buffer)
+    }
+
     pub fn extract_files(&self, output_dir: &str) -> Result<(), String> {
+        self.extract_files_with_callback(output_dir, None)
+    }
+
+    /// Like `extract_files`, but reports per-level progress through `callback`.
+    pub fn extract_files_with_callback(
+        &self,
+        output_dir: &str,
+        callback: Option<ExtractionCallback>,
+    ) -> Result<(), String> {
+        self.extract_files_internal(output_dir, ExtractLayout::Nested, callback)
+    }
+
+    /// Like `extract_files`, but with the levels of a multi-level bundle laid out as `layout` says.
+    /// Single-level bundles are always extracted directly into `output_dir` without a prefix.
+    pub fn extract_files_with_layout(
+        &self,
+        output_dir: &str,
+        layout: ExtractLayout,
+    ) -> Result<(), String> {
+        self.extract_files_internal(output_dir, layout, None)
+    }
+
+    fn extract_files_internal(
+        &self,
+        output_dir: &str,
+        layout: ExtractLayout,
+        callback: Option<ExtractionCallback>,
+    ) -> Result<(), String> {
+        let multi_level = self.levels.len() > 1;
+        for i in 0..self.levels.len() {
+            let (level_dir, prefix) = match layout {
+                ExtractLayout::Nested if multi_level => {
+                    (format!("{}/level{}", output_dir, i), String::new())
+                }
+                ExtractLayout::Flat if multi_level => {
+                    (output_dir.to_string(), format!("level{}__", i))
+                }
+                _ => (output_dir.to_string(), String::new()),
+            };
+            self.extract_level_internal(i, &level_dir, &prefix, callback)?;
+        }
+        Ok(())
+    }
+
+    /// Like `extract_files`, but writes up to `max_threads` files at once, which is much faster
+    /// for bundles with many small files. A `max_threads` of 0 uses one thread per CPU.
+    pub fn extract_files_parallel(
+        &self,
+        output_dir: &str,
+        max_threads: usize,
+    ) -> Result<(), String> {
         let make_subdirs = self.levels.len() > 1;
+        let mut jobs = Vec::new();
         for (i, level) in self.levels.iter().enumerate() {
+            for file in &level.files {
+                check_file_name(&file.name)?;
+            }
             let level_dir = if make_subdirs {
                 format!("{}/level{}", output_dir, i)
             } else {
@@ -653,14 +1328,120 @@ impl AssetBundle {
             };
             util::create_dir_if_needed(&level_dir)
                 .map_err(|e| format!("Couldn't create dir {}: {}", level_dir, e))?;
+            let level_path = PathBuf::from(level_dir);
+            jobs.extend(
+                level
+                    .files
+                    .iter()
+                    .map(|file| (level_path.join(&file.name), file)),
+            );
+        }
 
-            let dir_path = Path::new(&level_dir);
-            for file in &level.files {
-                let file_path = dir_path.join(&file.name);
-                std::fs::write(&file_path, &file.data).map_err(|e| {
-                    format!("Couldn't write file {}/{}: {}", level_dir, file.name, e)
-                })?;
+        let max_threads = match max_threads {
+            0 => num_cpus::get(),
+            n => n,
+        };
+        let num_threads = max_threads.min(jobs.len()).max(1);
+        let next_job = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..num_threads)
+                .map(|_| {
+                    scope.spawn(|| -> Result<(), String> {
+                        while !failed.load(Ordering::Relaxed) {
+                            let Some((path, file)) =
+                                jobs.get(next_job.fetch_add(1, Ordering::Relaxed))
+                            else {
+                                break;
+                            };
+                            if let Err(e) = std::fs::write(path, &file.data) {
+                                failed.store(true, Ordering::Relaxed);
+                                return Err(format!(
+                                    "Couldn't write file {}: {}",
+                                    path.display(),
+                                    e
+                                ));
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            // scoped threads are all joined before returning, even if this stops early
+            handles
+                .into_iter()
+                .try_for_each(|handle| handle.join().unwrap())
+        })
+    }
+
+    /// Extracts only the files in the specified level, directly into `output_dir`.
+    pub fn extract_level(&self, level: usize, output_dir: &str) -> Result<(), String> {
+        self.extract_level_internal(level, output_dir, "", None)
+    }
+
+    fn extract_level_internal(
+        &self,
+        level_idx: usize,
+        output_dir: &str,
+        prefix: &str,
+        callback: Option<ExtractionCallback>,
+    ) -> Result<(), String> {
+        let Some(level) = self.levels.get(level_idx) else {
+            return Err(format!("Level {} does not exist", level_idx));
+        };
+        // names come from the bundle, so make sure none of them escape the output dir before writing anything
+        for file in &level.files {
+            check_file_name(&file.name)?;
+        }
+        util::create_dir_if_needed(output_dir)
+            .map_err(|e| format!("Couldn't create dir {}: {}", output_dir, e))?;
+
+        let dir_path = Path::new(output_dir);
+        let num_files = level.files.len();
+        for (idx, file) in level.files.iter().enumerate() {
+            if let Some(callback) = callback {
+                callback(level_idx, idx, num_files, file.name.clone());
+            }
+            let file_name = format!("{}{}", prefix, file.name);
+            let file_path = dir_path.join(&file_name);
+            std::fs::write(&file_path, &file.data)
+                .map_err(|e| format!("Couldn't write file {}/{}: {}", output_dir, file_name, e))?;
+        }
+
+        if let Some(callback) = callback {
+            callback(level_idx, num_files, num_files, "Done".to_string());
+        }
+        Ok(())
+    }
+
+    /// Swaps the data of every file named `name` for `data`, clearing its cached hash.
+    /// If no file has that name, it's added to the last level when `add_if_missing` is set
+    /// and an error is returned otherwise. File offsets are recomputed when the bundle is written.
+    pub fn replace_file(
+        &mut self,
+        name: &str,
+        data: Vec<u8>,
+        add_if_missing: bool,
+    ) -> Result<(), String> {
+        let mut found = false;
+        for level in &mut self.levels {
+            for file in level.files.iter_mut().filter(|f| f.name == name) {
+                file.data = data.clone();
+                file.hash = None;
+                found = true;
+            }
+        }
+
+        if !found {
+            if !add_if_missing {
+                return Err(format!("File {} not found in bundle", name));
+            }
+            check_file_name(name)?;
+            if self.levels.is_empty() {
+                self.levels.push(Level { files: Vec::new() });
             }
+            let level = self.levels.last_mut().unwrap();
+            level.files.push(LevelFile::new(name.to_string(), data));
         }
         Ok(())
     }
@@ -693,10 +1474,244 @@ impl AssetBundle {
         Ok(result)
     }
 
+    /// Maps each file name to the index of the level it's in.
+    /// If a name appears more than once, the first occurrence wins, unless `strict` is set,
+    /// in which case an error listing the duplicates is returned.
+    pub fn file_index(&self, strict: bool) -> Result<HashMap<String, usize>, Error> {
+        let mut index = HashMap::new();
+        let mut duplicates = Vec::new();
+        for (level_idx, level) in self.levels.iter().enumerate() {
+            for file in &level.files {
+                if index.contains_key(&file.name) {
+                    duplicates.push(format!("{} (level {})", file.name, level_idx));
+                } else {
+                    index.insert(file.name.clone(), level_idx);
+                }
+            }
+        }
+
+        if strict && !duplicates.is_empty() {
+            return Err(format!("Duplicate files in bundle: {}", duplicates.join(", ")).into());
+        }
+        Ok(index)
+    }
+
     pub fn get_num_files(&self, level: usize) -> Result<usize, Error> {
         if level >= self.levels.len() {
             return Err(format!("Level {} does not exist", level).into());
         }
         Ok(self.levels[level].files.len())
     }
+
+    /// Compares the files in each level against `other`, matching them by name.
+    /// Files only in `other` are reported as added and files only in `self` as removed.
+    /// If a name appears more than once in a level, only the first occurrence is compared.
+    pub fn diff(&self, other: &AssetBundle) -> Vec<BundleFileDiff> {
+        let mut diffs = Vec::new();
+        let num_levels = self.levels.len().max(other.levels.len());
+        for level in 0..num_levels {
+            let files = self.levels.get(level).map_or(&[][..], |l| &l.files);
+            let other_files = other.levels.get(level).map_or(&[][..], |l| &l.files);
+            let mut other_by_name: HashMap<&str, &LevelFile> = HashMap::new();
+            for file in other_files {
+                other_by_name.entry(&file.name).or_insert(file);
+            }
+
+            let mut seen = HashSet::new();
+            for file in files {
+                if !seen.insert(file.name.as_str()) {
+                    continue;
+                }
+                let name = file.name.clone();
+                let Some(other_file) = other_by_name.get(file.name.as_str()) else {
+                    diffs.push(BundleFileDiff::Removed { level, name });
+                    continue;
+                };
+                if file.data.len() != other_file.data.len() {
+                    diffs.push(BundleFileDiff::SizeMismatch {
+                        level,
+                        name,
+                        size: file.data.len() as u64,
+                        other_size: other_file.data.len() as u64,
+                    });
+                } else if file.data != other_file.data {
+                    diffs.push(BundleFileDiff::HashMismatch {
+                        level,
+                        name,
+                        hash: util::get_buffer_hash(&file.data),
+                        other_hash: util::get_buffer_hash(&other_file.data),
+                    });
+                }
+            }
+
+            for file in other_files {
+                if seen.insert(file.name.as_str()) {
+                    let name = file.name.clone();
+                    diffs.push(BundleFileDiff::Added { level, name });
+                }
+            }
+        }
+        diffs
+    }
+
+    /// Extracts the bundle to a temporary directory, repacks it and reads the result back, returning
+    /// each file that didn't survive the round trip, including files that ended up in a different
+    /// order within their level and files whose names aren't valid UTF-8, which are written back as UTF-8. If the list is empty, the bundle can be edited through
+    /// `extract_files` and `from_directory` without losing anything.
+    pub fn verify_round_trip(&self) -> Result<Vec<BundleFileDiff>, String> {
+        let extract_dir = util::TempDir::new();
+        self.extract_files(extract_dir.path())?;
+        let repacked = Self::from_directory(extract_dir.path())?;
+
+        // write it out as well, so the compression and header are covered too
+        let output_dir = util::TempDir::new();
+        let output_path = format!("{}/repacked.unity3d", output_dir.path());
+        repacked.to_file(
+            &output_path,
+            &LzmaParams::default(),
+            &HeaderVersions::default(),
+            None,
+        )?;
+        let (_, repacked) = Self::from_file(&output_path)?;
+        let mut diffs = self.diff(&repacked);
+        diffs.extend(self.diff_order(&repacked));
+        diffs.extend(self.diff_raw_names(&repacked));
+        Ok(diffs)
+    }
+
+    /// Lists the files in both bundles whose names are stored as different bytes,
+    /// comparing each file with the first one of the same name in the other bundle's level.
+    fn diff_raw_names(&self, other: &AssetBundle) -> Vec<BundleFileDiff> {
+        let mut diffs = Vec::new();
+        for (level, (ours, theirs)) in self.levels.iter().zip(&other.levels).enumerate() {
+            for file in &ours.files {
+                let Some(other_file) = theirs.files.iter().find(|f| f.name == file.name) else {
+                    continue;
+                };
+                let diff = BundleFileDiff::NameMismatch {
+                    level,
+                    name: file.name.clone(),
+                };
+                if file.get_raw_name() != other_file.get_raw_name() && !diffs.contains(&diff) {
+                    diffs.push(diff);
+                }
+            }
+        }
+        diffs
+    }
+
+    /// Lists the files in both bundles that are in a different order within their level.
+    /// Files are compared by their first occurrence, ignoring any that are only in one of the bundles.
+    fn diff_order(&self, other: &AssetBundle) -> Vec<BundleFileDiff> {
+        fn first_positions(files: &[LevelFile]) -> HashMap<&str, usize> {
+            let mut positions = HashMap::new();
+            for (idx, file) in files.iter().enumerate() {
+                positions.entry(file.name.as_str()).or_insert(idx);
+            }
+            positions
+        }
+
+        let mut diffs = Vec::new();
+        for (level, (ours, theirs)) in self.levels.iter().zip(&other.levels).enumerate() {
+            let positions = first_positions(&ours.files);
+            let other_positions = first_positions(&theirs.files);
+            let mut common: Vec<(&str, usize, usize)> = positions
+                .iter()
+                .filter_map(|(name, idx)| Some((*name, *idx, *other_positions.get(name)?)))
+                .collect();
+            common.sort_by_key(|(_, idx, _)| *idx);
+            let mut other_order: Vec<&str> = common.iter().map(|(name, _, _)| *name).collect();
+            other_order.sort_by_key(|name| other_positions[name]);
+
+            for ((name, index, other_index), other_name) in common.into_iter().zip(other_order) {
+                if name != other_name {
+                    diffs.push(BundleFileDiff::Moved {
+                        level,
+                        name: name.to_string(),
+                        index,
+                        other_index,
+                    });
+                }
+            }
+        }
+        diffs
+    }
+}
+
+/// A difference between two bundles, as reported by `AssetBundle::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleFileDiff {
+    Added {
+        level: usize,
+        name: String,
+    },
+    Removed {
+        level: usize,
+        name: String,
+    },
+    SizeMismatch {
+        level: usize,
+        name: String,
+        size: u64,
+        other_size: u64,
+    },
+    HashMismatch {
+        level: usize,
+        name: String,
+        hash: String,
+        other_hash: String,
+    },
+    /// The file is at `index` in its level, but at `other_index` in the other bundle's.
+    Moved {
+        level: usize,
+        name: String,
+        index: usize,
+        other_index: usize,
+    },
+    /// The file's name decodes the same in both bundles, but is stored as different bytes.
+    NameMismatch {
+        level: usize,
+        name: String,
+    },
+}
+impl std::fmt::Display for BundleFileDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BundleFileDiff::Added { level, name } => write!(f, "+ level {}: {}", level, name),
+            BundleFileDiff::Removed { level, name } => write!(f, "- level {}: {}", level, name),
+            BundleFileDiff::SizeMismatch {
+                level,
+                name,
+                size,
+                other_size,
+            } => write!(
+                f,
+                "~ level {}: {} size {} vs {}",
+                level, name, size, other_size
+            ),
+            BundleFileDiff::HashMismatch {
+                level,
+                name,
+                hash,
+                other_hash,
+            } => write!(
+                f,
+                "~ level {}: {} hash {} vs {}",
+                level, name, hash, other_hash
+            ),
+            BundleFileDiff::Moved {
+                level,
+                name,
+                index,
+                other_index,
+            } => write!(
+                f,
+                "> level {}: {} position {} vs {}",
+                level, name, index, other_index
+            ),
+            BundleFileDiff::NameMismatch { level, name } => {
+                write!(f, "~ level {}: {} name bytes differ", level, name)
+            }
+        }
+    }
 }
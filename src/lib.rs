@@ -1,15 +1,20 @@
 use std::{
-    collections::HashMap,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap, VecDeque},
+    ops::Range,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex, OnceLock,
     },
+    time::{Duration, Instant},
 };
 
 use serde::{Deserialize, Serialize};
-use tokio::{sync::Semaphore, task::JoinHandle};
-use util::TempFile;
+use tokio::{
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
+use util::{HashAlgorithm, TempFile};
 use uuid::Uuid;
 
 use log::*;
@@ -18,17 +23,94 @@ pub type Error = Box<dyn std::error::Error>;
 
 pub mod util;
 
+mod archive;
+
 #[cfg(feature = "lzma")]
 pub mod bundle;
 
+#[cfg(feature = "s3")]
+pub mod s3;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
 #[cfg(test)]
 mod tests;
 
+/// File name of the main file, which isn't an asset bundle, unless the manifest says otherwise.
+pub const DEFAULT_MAIN_FILE_NAME: &str = "main.unity3d";
+
+/// A suspicious but non-fatal finding while generating a `Version`.
+/// These are also logged as they're found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildWarning {
+    /// The bundle size recorded in the bundle's header doesn't match the size of the file.
+    BundleSizeMismatch {
+        bundle: String,
+        header_size: u64,
+        file_size: u64,
+    },
+}
+impl BuildWarning {
+    /// Returns the name of the bundle the warning is about.
+    pub fn get_bundle(&self) -> &str {
+        match self {
+            BuildWarning::BundleSizeMismatch { bundle, .. } => bundle,
+        }
+    }
+}
+impl std::fmt::Display for BuildWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BuildWarning::BundleSizeMismatch {
+                bundle,
+                header_size,
+                file_size,
+            } => write!(
+                f,
+                "{}: bundle size mismatch: {} (header) vs {} (file)",
+                bundle, header_size, file_size
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum FailReason {
-    BadSize { expected: u64, actual: u64 },
-    BadHash { expected: String, actual: String },
+    BadSize {
+        expected: u64,
+        actual: u64,
+    },
+    BadHash {
+        expected: String,
+        actual: String,
+        /// Offset of the first byte that differs from a known-good copy, if one was compared.
+        /// See `FileInfo::validate_verbose`.
+        first_mismatch: Option<u64>,
+    },
     Missing,
+    /// The file exists but is empty, and the manifest says it shouldn't be.
+    Empty,
+    MirrorMismatch {
+        url: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// The manifest has no uncompressed file info to validate against,
+    /// e.g. because it was generated without the `lzma` feature.
+    NoUncompressedInfo,
+    /// The file is on disk but isn't listed in the manifest.
+    Unexpected,
+    /// The file exists but couldn't be read, e.g. because of a permissions error
+    /// or a path that isn't valid UTF-8.
+    Unreadable {
+        error: String,
+    },
+    /// The file couldn't be fetched from the server for a reason other than it not being there,
+    /// e.g. a connection error or a server error.
+    Unreachable {
+        error: String,
+    },
 }
 impl std::fmt::Display for FailReason {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -36,51 +118,983 @@ impl std::fmt::Display for FailReason {
             FailReason::BadSize { expected, actual } => {
                 write!(f, "Bad size: {} (disk) vs {} (manifest)", actual, expected)
             }
-            FailReason::BadHash { expected, actual } => {
-                write!(f, "Bad hash: {} (disk) vs {} (manifest)", actual, expected)
+            FailReason::BadHash {
+                expected,
+                actual,
+                first_mismatch,
+            } => {
+                write!(f, "Bad hash: {} (disk) vs {} (manifest)", actual, expected)?;
+                if let Some(offset) = first_mismatch {
+                    write!(f, "; first differs at byte {}", offset)?;
+                }
+                Ok(())
             }
             FailReason::Missing => write!(f, "File missing"),
+            FailReason::Empty => write!(f, "File empty"),
+            FailReason::NoUncompressedInfo => write!(f, "No uncompressed file info in manifest"),
+            FailReason::Unexpected => write!(f, "File not in manifest"),
+            FailReason::Unreadable { error } => write!(f, "Couldn't read file: {}", error),
+            FailReason::Unreachable { error } => write!(f, "Couldn't fetch file: {}", error),
+            FailReason::MirrorMismatch {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Mirror mismatch: {} (remote) vs {} (manifest) for {}",
+                actual, expected, url
+            ),
+        }
+    }
+}
+impl std::error::Error for FailReason {}
+
+/// A `FailReason` along with the path of the file that failed.
+#[derive(Debug, Clone)]
+pub struct FileFailure {
+    path: String,
+    reason: FailReason,
+}
+impl FileFailure {
+    pub fn new(path: &str, reason: FailReason) -> Self {
+        Self {
+            path: path.to_string(),
+            reason,
+        }
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_reason(&self) -> &FailReason {
+        &self.reason
+    }
+}
+impl std::fmt::Display for FileFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+impl std::error::Error for FileFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.reason)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ItemProgress {
+    Downloading {
+        bytes_downloaded: u64,
+        total_bytes: u64,
+        /// Which download attempt this is, starting from 1.
+        attempt: usize,
+        /// How many attempts will be made before giving up.
+        max_attempts: usize,
+    },
+    Validating,
+    /// The mirror is rate limiting downloads; the next attempt starts after `delay`.
+    Retrying {
+        delay: Duration,
+    },
+    Passed {
+        item_size: u64,
+        /// Time spent on the item, including any downloads.
+        duration: Duration,
+        /// Whether the item had to be (re-)downloaded before it passed.
+        was_downloaded: bool,
+    },
+    Failed {
+        item_size: u64,
+        reason: FailReason,
+    },
+}
+
+// uuid, item name, progress
+pub type ProgressCallback = Arc<dyn Fn(&Uuid, &str, ItemProgress) + Send + Sync>;
+
+// bundle name -> path to the directory the bundle was extracted to
+pub type BundlePathResolver = Arc<dyn Fn(&str) -> PathBuf + Send + Sync>;
+
+// bundle name, bundles processed, total bundles, compressed bytes so far, uncompressed bytes so far
+pub type BuildCallback = Arc<dyn Fn(&str, usize, usize, u64, u64) + Send + Sync>;
+
+/// Aggregate progress over every item in a build, as reported by `Version::track_total_progress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotalProgress {
+    /// Bytes downloaded or validated so far.
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+    /// Download throughput averaged over the last few seconds, in bytes per second.
+    pub bytes_per_sec: u64,
+    /// Estimated time left, if there's been any download throughput to go by.
+    pub eta: Option<Duration>,
+}
+
+// uuid, aggregate progress
+pub type TotalProgressCallback = Arc<dyn Fn(&Uuid, TotalProgress) + Send + Sync>;
+
+struct TotalProgressTracker {
+    total_bytes: u64,
+    bytes_done: u64,
+    item_bytes: HashMap<String, u64>,
+    bytes_downloaded: u64,
+    samples: VecDeque<(Instant, u64)>,
+}
+impl TotalProgressTracker {
+    const THROUGHPUT_WINDOW: Duration = Duration::from_secs(10);
+
+    fn new(total_bytes: u64) -> Self {
+        Self {
+            total_bytes,
+            bytes_done: 0,
+            item_bytes: HashMap::new(),
+            bytes_downloaded: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    fn update(&mut self, name: &str, progress: &ItemProgress) -> TotalProgress {
+        let item_done = match progress {
+            ItemProgress::Downloading {
+                bytes_downloaded, ..
+            } => {
+                let last = self.item_bytes.get(name).copied().unwrap_or(0);
+                // a retried download starts over from zero
+                self.bytes_downloaded += bytes_downloaded.saturating_sub(last);
+                Some(*bytes_downloaded)
+            }
+            ItemProgress::Passed { item_size, .. } | ItemProgress::Failed { item_size, .. } => {
+                Some(*item_size)
+            }
+            ItemProgress::Validating | ItemProgress::Retrying { .. } => None,
+        };
+        if let Some(item_done) = item_done {
+            let last = self.item_bytes.insert(name.to_string(), item_done);
+            self.bytes_done = self.bytes_done - last.unwrap_or(0) + item_done;
+        }
+
+        let now = Instant::now();
+        self.samples.push_back((now, self.bytes_downloaded));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(t, _)| now - *t > Self::THROUGHPUT_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        let (oldest_time, oldest_bytes) = self.samples.front().copied().unwrap();
+        let elapsed = (now - oldest_time).as_secs_f64();
+        let bytes_per_sec = if elapsed > 0.0 {
+            ((self.bytes_downloaded - oldest_bytes) as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let eta = match bytes_per_sec {
+            0 => None,
+            rate => Some(Duration::from_secs(
+                self.total_bytes.saturating_sub(self.bytes_done) / rate,
+            )),
+        };
+
+        TotalProgress {
+            bytes_done: self.bytes_done,
+            total_bytes: self.total_bytes,
+            bytes_per_sec,
+            eta,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    Passed,
+    Failed,
+}
+
+/// Validation result for a single item, as recorded in a `ValidationReport`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ItemReport {
+    name: String,
+    status: ItemStatus,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+
+    expected: FileInfo,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<FileInfo>,
+}
+impl ItemReport {
+    fn new(name: &str, expected: FileInfo, actual: FileInfo, reason: Option<FailReason>) -> Self {
+        let status = match reason {
+            None => ItemStatus::Passed,
+            Some(_) => ItemStatus::Failed,
+        };
+        Self {
+            name: name.to_string(),
+            status,
+            reason: reason.map(|r| r.to_string()),
+            expected,
+            // only files that couldn't be read have no hash; an empty file still has one
+            actual: (!actual.hash.is_empty()).then_some(actual),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_status(&self) -> ItemStatus {
+        self.status
+    }
+
+    pub fn get_reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+}
+
+/// The top-level metadata of a build, without the per-bundle info, for listing builds cheaply.
+/// See `Version::peek_metadata`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct BuildSummary {
+    uuid: Uuid,
+    name: Option<String>,
+    description: Option<String>,
+    hidden: Option<bool>,
+    main_file_info: Option<FileInfo>,
+    total_compressed_size: Option<u64>,
+    total_uncompressed_size: Option<u64>,
+}
+impl BuildSummary {
+    pub fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden.unwrap_or(false)
+    }
+
+    /// Returns the total size of the build in bytes, including the main file.
+    pub fn get_total_compressed_size(&self) -> u64 {
+        self.main_file_info.as_ref().map_or(0, |info| info.size) + self.get_compressed_assets_size()
+    }
+
+    /// Returns the total size of the compressed asset bundles in bytes.
+    pub fn get_compressed_assets_size(&self) -> u64 {
+        self.total_compressed_size.unwrap_or(0)
+    }
+
+    /// Returns the total size of the uncompressed asset bundles in bytes.
+    pub fn get_uncompressed_assets_size(&self) -> u64 {
+        self.total_uncompressed_size.unwrap_or(0)
+    }
+}
+
+/// The files of a build's extracted asset bundles as a flat list, keyed by their path relative to
+/// the directory the bundles were extracted to, i.e. `<url-encoded bundle name>/<file name>`.
+/// See `Version::to_uncompressed_manifest`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UncompressedManifest {
+    uuid: Uuid,
+    asset_url: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+
+    files: BTreeMap<String, FileInfo>,
+}
+impl UncompressedManifest {
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn export(&self, path: &str) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn get_files(&self) -> &BTreeMap<String, FileInfo> {
+        &self.files
+    }
+}
+
+/// Machine-readable results of a validation pass over a build.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    uuid: Uuid,
+    path: String,
+    /// Seconds since the Unix epoch at which the validation finished.
+    timestamp: u64,
+    items: Vec<ItemReport>,
+}
+impl ValidationReport {
+    fn new(uuid: Uuid, path: &str, mut items: Vec<ItemReport>) -> Self {
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            uuid,
+            path: path.to_string(),
+            timestamp,
+            items,
+        }
+    }
+
+    pub fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_items(&self) -> &[ItemReport] {
+        &self.items
+    }
+
+    /// Returns the names of all items that failed validation.
+    pub fn get_corrupted(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .filter(|item| item.status == ItemStatus::Failed)
+            .map(|item| item.name.clone())
+            .collect()
+    }
+
+    /// Serializes the report into a JSON value.
+    pub fn to_json(&self) -> Result<serde_json::Value, Error> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// Exports the report to a JSON file.
+    pub fn export(&self, path: &str) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+/// A file that has to be downloaded to repair a build, as listed in a `RepairScript`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RepairEntry {
+    bundle: String,
+    url: String,
+    local_path: String,
+    expected_hash: String,
+    expected_size: u64,
+    reason: String,
+}
+impl RepairEntry {
+    pub fn get_bundle(&self) -> &str {
+        &self.bundle
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_local_path(&self) -> &str {
+        &self.local_path
+    }
+
+    pub fn get_expected_hash(&self) -> &str {
+        &self.expected_hash
+    }
+
+    pub fn get_expected_size(&self) -> u64 {
+        self.expected_size
+    }
+
+    pub fn get_reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// Every download needed to repair a build, for consumption by an external downloader.
+/// See `Version::generate_repair_script`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RepairScript {
+    uuid: Uuid,
+    path: String,
+    entries: Vec<RepairEntry>,
+}
+impl RepairScript {
+    pub fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_entries(&self) -> &[RepairEntry] {
+        &self.entries
+    }
+
+    /// Serializes the script into a JSON value.
+    pub fn to_json(&self) -> Result<serde_json::Value, Error> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// Exports the script to a JSON file.
+    pub fn export(&self, path: &str) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+static ITEM_PERMITS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+static DOWNLOAD_PERMITS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+static EXTRA_BUNDLE_EXTENSIONS: OnceLock<Vec<String>> = OnceLock::new();
+static CHECK_MIRROR_SIZE: AtomicBool = AtomicBool::new(false);
+static MAIN_FILE_ALONGSIDE_BUNDLES: AtomicBool = AtomicBool::new(false);
+
+/// Sets the maximum number of concurrent items that can be processed at once for all operations.
+/// Returns an error if the value has already been set.
+pub fn set_max_concurrent_items(max: usize) -> Result<(), String> {
+    ITEM_PERMITS
+        .set(Arc::new(Semaphore::new(max)))
+        .map_err(|_| "Limit already set".to_string())
+}
+
+/// Sets the maximum number of concurrent downloads that can run at once for download/repair operations.
+/// Returns an error if the value has already been set.
+pub fn set_max_concurrent_downloads(max: usize) -> Result<(), String> {
+    DOWNLOAD_PERMITS
+        .set(Arc::new(Semaphore::new(max)))
+        .map_err(|_| "Limit already set".to_string())
+}
+
+/// Concurrency limits for one or more operations. Limits that aren't set fall back to the global ones
+/// from `set_max_concurrent_items` and `set_max_concurrent_downloads`. Clones share the same limits,
+/// so one config can be passed to several operations to limit them together.
+#[derive(Debug, Clone, Default)]
+pub struct ConcurrencyConfig {
+    item_permits: Option<Arc<Semaphore>>,
+    download_permits: Option<Arc<Semaphore>>,
+}
+impl ConcurrencyConfig {
+    /// Limits the number of items processed at once.
+    pub fn with_max_items(mut self, max: usize) -> Self {
+        self.item_permits = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Limits the number of downloads running at once.
+    pub fn with_max_downloads(mut self, max: usize) -> Self {
+        self.download_permits = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    pub(crate) async fn acquire_item(&self) -> Option<OwnedSemaphorePermit> {
+        Self::acquire(self.item_permits.as_ref().or(ITEM_PERMITS.get())).await
+    }
+
+    pub(crate) async fn acquire_download(&self) -> Option<OwnedSemaphorePermit> {
+        Self::acquire(self.download_permits.as_ref().or(DOWNLOAD_PERMITS.get())).await
+    }
+
+    async fn acquire(permits: Option<&Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+        match permits {
+            Some(permits) => Some(Arc::clone(permits).acquire_owned().await.unwrap()),
+            None => None,
         }
     }
-}
-impl std::error::Error for FailReason {}
+}
+
+/// Settings for a single operation, e.g. `Version::repair_with_options`. Settings that aren't set
+/// fall back to the global ones, e.g. from `set_download_timeout` and `set_hash_cache_path`.
+#[derive(Debug, Clone, Default)]
+pub struct OperationOptions {
+    concurrency: ConcurrencyConfig,
+    download_control: Option<DownloadControl>,
+    download_timeouts: Option<(Option<Duration>, Option<Duration>)>,
+    main_file_alongside_bundles: Option<bool>,
+    check_mirror_size: Option<bool>,
+    bundle_store: Option<PathBuf>,
+    hash_cache: Option<Arc<util::HashCache>>,
+    mirrors: Option<Vec<String>>,
+    validation_pacer: Option<Option<Arc<util::IoPacer>>>,
+    hash_algorithm: HashAlgorithm,
+    /// Built on first use and shared by clones, so every download of the operation reuses its connections.
+    http_client: Arc<OnceLock<reqwest::Client>>,
+}
+impl OperationOptions {
+    /// Uses the limits of `concurrency` instead of the global ones.
+    pub fn with_concurrency(mut self, concurrency: ConcurrencyConfig) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn get_concurrency(&self) -> &ConcurrencyConfig {
+        &self.concurrency
+    }
+
+    /// Lets downloads be paused and resumed through `control`.
+    pub fn with_download_control(mut self, control: DownloadControl) -> Self {
+        self.download_control = Some(control);
+        self
+    }
+
+    /// Overrides the download timeouts from `set_download_timeout`. `None` means no limit.
+    pub fn with_download_timeout(
+        mut self,
+        request_timeout: Option<Duration>,
+        total_timeout: Option<Duration>,
+    ) -> Self {
+        self.download_timeouts = Some((request_timeout, total_timeout));
+        // the request timeout is part of the client
+        self.http_client = Arc::default();
+        self
+    }
+
+    /// Overrides `set_check_mirror_size`.
+    pub fn with_mirror_size_check(mut self, enabled: bool) -> Self {
+        self.check_mirror_size = Some(enabled);
+        self
+    }
+
+    pub(crate) fn is_mirror_size_check_enabled(&self) -> bool {
+        self.check_mirror_size
+            .unwrap_or_else(|| CHECK_MIRROR_SIZE.load(Ordering::Relaxed))
+    }
+
+    /// Uses the shared bundle store at `path` instead of the one from `set_shared_bundle_store`.
+    /// The directory is created when the first bundle is added.
+    pub fn with_bundle_store(mut self, path: PathBuf) -> Self {
+        self.bundle_store = Some(path);
+        self
+    }
+
+    pub(crate) fn get_bundle_store(&self) -> Option<&Path> {
+        self.bundle_store
+            .as_deref()
+            .or(util::BUNDLE_STORE.get().map(PathBuf::as_path))
+    }
+
+    /// Uses `cache` for file hashes instead of the one from `set_hash_cache_path`.
+    /// It's saved at the end of each build or validation pass.
+    pub fn with_hash_cache(mut self, cache: Arc<util::HashCache>) -> Self {
+        self.hash_cache = Some(cache);
+        self
+    }
+
+    pub(crate) fn get_hash_cache(&self) -> Option<Arc<util::HashCache>> {
+        self.hash_cache.clone().or_else(util::get_global_hash_cache)
+    }
+
+    /// Adds asset URLs that serve the same files as the build's own. Before each download attempt,
+    /// the best of the build's asset URL and `mirrors` is picked from the throughput and failures
+    /// of earlier downloads (see `util::get_mirror_rankings`), so slow or failing mirrors are avoided.
+    pub fn with_mirrors(mut self, mirrors: &[&str]) -> Self {
+        self.mirrors = Some(
+            mirrors
+                .iter()
+                .map(|mirror| mirror.trim_end_matches('/').to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Returns `url`, moved to the best mirror if any are set.
+    pub(crate) fn select_download_url(&self, url: &str) -> String {
+        let (Some(mirrors), Some((base, file_name))) = (&self.mirrors, url.rsplit_once('/')) else {
+            return url.to_string();
+        };
+        let mut candidates = vec![base];
+        candidates.extend(mirrors.iter().map(String::as_str));
+        let best = util::select_mirror(&candidates).unwrap_or(base);
+        format!("{}/{}", best, file_name)
+    }
+
+    /// Overrides `set_validation_io_rate`. The limit is shared by everything validated with these options.
+    pub fn with_validation_io_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.validation_pacer = Some(util::IoPacer::new(bytes_per_sec).map(Arc::new));
+        self
+    }
+
+    pub(crate) fn get_validation_pacer(&self) -> Option<Arc<util::IoPacer>> {
+        self.validation_pacer
+            .clone()
+            .unwrap_or_else(util::get_global_validation_io_pacer)
+    }
+
+    /// Overrides `set_main_file_alongside_bundles`.
+    pub fn with_main_file_alongside_bundles(mut self, enabled: bool) -> Self {
+        self.main_file_alongside_bundles = Some(enabled);
+        self
+    }
+
+    /// Hashes the files of newly generated metadata, e.g. in `VersionBuilder::build`, with `algorithm`.
+    /// Existing metadata is always validated with the algorithm its hashes were made with.
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    pub(crate) fn get_hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    pub(crate) fn is_main_file_alongside_bundles(&self) -> bool {
+        self.main_file_alongside_bundles
+            .unwrap_or_else(|| MAIN_FILE_ALONGSIDE_BUNDLES.load(Ordering::Relaxed))
+    }
+
+    /// Returns the per-request and total download timeouts.
+    pub(crate) fn get_download_timeouts(&self) -> (Option<Duration>, Option<Duration>) {
+        self.download_timeouts
+            .unwrap_or_else(util::get_global_download_timeouts)
+    }
+
+    /// Returns the HTTP client for the operation's downloads, with the per-request timeout applied
+    /// to connecting and to each read. The total timeout is tracked separately, since it doesn't
+    /// count time spent paused.
+    pub(crate) fn get_http_client(&self) -> Result<reqwest::Client, Error> {
+        if let Some(client) = self.http_client.get() {
+            return Ok(client.clone());
+        }
+        let mut builder = reqwest::Client::builder();
+        if let (Some(request_timeout), _) = self.get_download_timeouts() {
+            builder = builder
+                .connect_timeout(request_timeout)
+                .read_timeout(request_timeout);
+        }
+        let client = builder.build()?;
+        Ok(self.http_client.get_or_init(|| client).clone())
+    }
+
+    /// Waits until downloads aren't paused. Returns how long that took.
+    pub(crate) async fn wait_if_paused(&self) -> Duration {
+        match &self.download_control {
+            Some(control) => control.wait_if_paused().await,
+            None => Duration::ZERO,
+        }
+    }
+
+    pub(crate) async fn acquire_item(&self) -> Option<OwnedSemaphorePermit> {
+        self.concurrency.acquire_item().await
+    }
+
+    pub(crate) async fn acquire_download(&self) -> Option<OwnedSemaphorePermit> {
+        self.concurrency.acquire_download().await
+    }
+}
+
+/// A handle for pausing and resuming the downloads of an operation from elsewhere, e.g. a UI.
+/// Pass a clone to the operation with `OperationOptions::with_download_control`. While paused,
+/// downloads stop reading from their connections, which are kept open, and report no progress.
+#[derive(Debug, Clone)]
+pub struct DownloadControl {
+    paused: Arc<tokio::sync::watch::Sender<bool>>,
+}
+impl Default for DownloadControl {
+    fn default() -> Self {
+        Self {
+            paused: Arc::new(tokio::sync::watch::Sender::new(false)),
+        }
+    }
+}
+impl DownloadControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    async fn wait_if_paused(&self) -> Duration {
+        if !self.is_paused() {
+            return Duration::ZERO;
+        }
+        let start = Instant::now();
+        // the sender is never dropped while we hold it, so this only returns once resumed
+        let _ = self.paused.subscribe().wait_for(|paused| !paused).await;
+        start.elapsed()
+    }
+}
+
+/// Sets additional file extensions (without the leading dot, e.g. `assetbundle`) that are treated as
+/// asset bundles when scanning a build root, on top of `unity3d` and `resourceFile`.
+/// Returns an error if the value has already been set.
+pub fn set_extra_bundle_extensions(extensions: &[&str]) -> Result<(), String> {
+    EXTRA_BUNDLE_EXTENSIONS
+        .set(extensions.iter().map(|ext| ext.to_string()).collect())
+        .map_err(|_| "Extensions already set".to_string())
+}
+
+/// Sets the directory that temporary files and directories (e.g. downloaded manifests) are created in,
+/// creating it if needed. Defaults to the system temp directory.
+/// Returns an error if the value has already been set.
+pub fn set_temp_dir(path: PathBuf) -> Result<(), String> {
+    std::fs::create_dir_all(&path)
+        .map_err(|e| format!("Couldn't create temp dir {}: {}", path.display(), e))?;
+    util::TEMP_DIR
+        .set(path)
+        .map_err(|_| "Temp dir already set".to_string())
+}
+
+/// Enables a content-addressed store of compressed bundles at `path`, shared between builds and
+/// creating it if needed. Before a bundle is downloaded, the store is checked for a copy with the
+/// expected hash, which is copied into place and validated like a download. Bundles that are downloaded
+/// or pass validation during downloads and repairs are copied into the store, so later changes to
+/// a build's files never affect it. `OperationOptions::with_bundle_store` overrides it for a single operation.
+/// Returns an error if the value has already been set.
+pub fn set_shared_bundle_store(path: PathBuf) -> Result<(), String> {
+    std::fs::create_dir_all(&path)
+        .map_err(|e| format!("Couldn't create bundle store {}: {}", path.display(), e))?;
+    util::BUNDLE_STORE
+        .set(path)
+        .map_err(|_| "Bundle store already set".to_string())
+}
+
+/// Enables caching file hashes in a JSON file at `path`, keyed by the file's path, modification time and size.
+/// Existing entries are loaded immediately; the cache is saved after each build or validation pass.
+/// `OperationOptions::with_hash_cache` overrides it for a single operation.
+pub fn set_hash_cache_path(path: &str) -> Result<(), Error> {
+    util::load_hash_cache(path)
+}
+
+fn save_hash_cache(cache: Option<Arc<util::HashCache>>) {
+    let Some(cache) = cache else {
+        return;
+    };
+    if let Err(e) = cache.save() {
+        warn!("Couldn't save hash cache: {}", e);
+    }
+}
+
+/// Sets whether repairs check the size of each file on the mirror with a HEAD request before downloading it.
+/// If the size doesn't match the manifest, the file fails with `FailReason::MirrorMismatch` right away
+/// instead of being downloaded repeatedly. Mirrors that apply a `Content-Encoding` aren't checked,
+/// since the size they report isn't the file's. Off by default.
+/// `OperationOptions::with_mirror_size_check` overrides it for a single operation.
+pub fn set_check_mirror_size(enabled: bool) {
+    CHECK_MIRROR_SIZE.store(enabled, Ordering::Relaxed);
+}
+
+/// Sets whether compressed validation, downloads and repairs handle the main file alongside the bundles
+/// instead of first and alone. When enabled, the main file gets a dedicated download permit so it
+/// doesn't wait behind the bundles for one of the shared permits. Off by default.
+/// `OperationOptions::with_main_file_alongside_bundles` overrides it for a single operation.
+pub fn set_main_file_alongside_bundles(enabled: bool) {
+    MAIN_FILE_ALONGSIDE_BUNDLES.store(enabled, Ordering::Relaxed);
+}
+
+/// Limits how fast files are read from disk while hashing them for validation, in bytes per second.
+/// The limit is shared by all concurrent validations. Pass 0 to read at full speed (the default).
+/// Generating manifests always reads at full speed.
+/// `OperationOptions::with_validation_io_rate` overrides it for a single operation.
+pub fn set_validation_io_rate(bytes_per_sec: u64) {
+    util::set_global_validation_io_rate(bytes_per_sec);
+}
+
+/// Sets how long a download may wait for the server to respond or send more data, and how long
+/// a whole download may take, not counting time spent paused. A download that times out counts
+/// as a failed attempt and is retried like any other. `None` (the default) means no limit.
+/// `OperationOptions::with_download_timeout` overrides it for a single operation.
+pub fn set_download_timeout(request_timeout: Option<Duration>, total_timeout: Option<Duration>) {
+    // zero means no limit, so round tiny timeouts up instead of disabling them
+    let to_ms = |timeout: Option<Duration>| timeout.map_or(0, |t| (t.as_millis() as u64).max(1));
+    util::DOWNLOAD_REQUEST_TIMEOUT_MS.store(to_ms(request_timeout), Ordering::Relaxed);
+    util::DOWNLOAD_TOTAL_TIMEOUT_MS.store(to_ms(total_timeout), Ordering::Relaxed);
+}
+
+/// How directory listings and copies treat symlinks. See `set_symlink_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Treat symlinks like their targets. Symlinked directories that lead back into a directory
+    /// that's already being copied are skipped instead of looping.
+    #[default]
+    Follow,
+    /// Leave symlinks out, as if they weren't there.
+    Skip,
+    /// Fail the listing or copy on the first symlink.
+    Error,
+}
+
+/// Sets how symlinks are handled when listing a build root or copying directories. Defaults to following them.
+/// `util::copy_dir_with_policy` and `util::list_filenames_in_directory_with_policy` override it per call.
+pub fn set_symlink_policy(policy: SymlinkPolicy) {
+    *util::SYMLINK_POLICY.lock().unwrap() = policy;
+}
+
+/// Bundle info, validation reports and warnings gathered while scanning a build root.
+type BundleInfoResults = (
+    HashMap<String, BundleInfo>,
+    Vec<ItemReport>,
+    Vec<BuildWarning>,
+);
+
+/// Builder for generating `Version` metadata from a local build root,
+/// as an alternative to the positional arguments of `Version::build`.
+#[derive(Clone, Default)]
+pub struct VersionBuilder {
+    asset_url: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    parent: Option<Uuid>,
+    hidden: bool,
+    min_client_version: Option<String>,
+    main_file_name: Option<String>,
+    extra_bundle_extensions: Option<Vec<String>>,
+    callback: Option<BuildCallback>,
+    options: OperationOptions,
+}
+impl VersionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the URL the build is served from. Required.
+    pub fn asset_url(mut self, asset_url: &str) -> Self {
+        self.asset_url = Some(asset_url.to_string());
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn parent(mut self, parent: Uuid) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Sets the minimum client version the build requires. See `Version::get_min_client_version`.
+    pub fn min_client_version(mut self, min_client_version: &str) -> Self {
+        self.min_client_version = Some(min_client_version.to_string());
+        self
+    }
+
+    /// Sets the name of the main file, if it isn't `main.unity3d`.
+    pub fn main_file_name(mut self, main_file_name: &str) -> Self {
+        self.main_file_name = Some(main_file_name.to_string());
+        self
+    }
+
+    /// Sets additional file extensions that are treated as asset bundles, instead of the ones from
+    /// `set_extra_bundle_extensions`.
+    pub fn extra_bundle_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extra_bundle_extensions = Some(extensions.iter().map(|ext| ext.to_string()).collect());
+        self
+    }
+
+    pub fn callback(mut self, callback: BuildCallback) -> Self {
+        self.callback = Some(callback);
+        self
+    }
 
-#[derive(Debug)]
-pub enum ItemProgress {
-    Downloading {
-        bytes_downloaded: u64,
-        total_bytes: u64,
-    },
-    Validating,
-    Passed {
-        item_size: u64,
-    },
-    Failed {
-        item_size: u64,
-        reason: FailReason,
-    },
-}
+    /// Sets the concurrency limits and hash cache used while scanning the build root.
+    pub fn options(mut self, options: OperationOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-// uuid, item name, progress
-pub type ProgressCallback = Arc<dyn Fn(&Uuid, &str, ItemProgress) + Send + Sync>;
+    /// Generates the `Version` metadata for the build in `asset_root`, like `Version::build_with_progress`.
+    pub async fn build(self, asset_root: &str) -> Result<Version, Error> {
+        let (version, _) = self.build_with_warnings(asset_root).await?;
+        Ok(version)
+    }
 
-static ITEM_PERMITS: OnceLock<Semaphore> = OnceLock::new();
-static DOWNLOAD_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+    /// Same as `build`, but also returns anything suspicious found in the build root,
+    /// e.g. bundles whose header doesn't match the file. Warnings are sorted by bundle name.
+    pub async fn build_with_warnings(
+        self,
+        asset_root: &str,
+    ) -> Result<(Version, Vec<BuildWarning>), Error> {
+        let (version, _, warnings) = self.build_internal(asset_root, None).await?;
+        Ok((version, warnings))
+    }
 
-/// Sets the maximum number of concurrent items that can be processed at once for all operations.
-/// Returns an error if the value has already been set.
-pub fn set_max_concurrent_items(max: usize) -> Result<(), String> {
-    ITEM_PERMITS
-        .set(Semaphore::new(max))
-        .map_err(|_| "Limit already set".to_string())
-}
+    /// Same as `build_with_warnings`, but also validates the local build root against `reference`
+    /// using the hashes computed for the new `Version`, so each bundle is only read once.
+    /// Bundles that fail validation are left out of the new `Version`.
+    /// Returns the new `Version` along with a report of how the files compare to `reference`.
+    pub async fn build_validated(
+        self,
+        asset_root: &str,
+        reference: &Version,
+    ) -> Result<(Version, ValidationReport, Vec<BuildWarning>), Error> {
+        let (version, items, warnings) = self.build_internal(asset_root, Some(reference)).await?;
+        let report = ValidationReport::new(reference.uuid, asset_root, items);
+        info!(
+            "[{}] Validation against {} complete; {} corrupted bundles",
+            version.uuid,
+            reference.uuid,
+            report.get_corrupted().len()
+        );
+        Ok((version, report, warnings))
+    }
 
-/// Sets the maximum number of concurrent downloads that can run at once for download/repair operations.
-/// Returns an error if the value has already been set.
-pub fn set_max_concurrent_downloads(max: usize) -> Result<(), String> {
-    DOWNLOAD_PERMITS
-        .set(Semaphore::new(max))
-        .map_err(|_| "Limit already set".to_string())
+    async fn build_internal(
+        self,
+        asset_root: &str,
+        reference: Option<&Version>,
+    ) -> Result<(Version, Vec<ItemReport>, Vec<BuildWarning>), Error> {
+        let Some(asset_url) = self.asset_url else {
+            return Err("Asset URL not set".into());
+        };
+        let (mut version, items, warnings) = Version::build_internal(
+            asset_root,
+            &asset_url,
+            self.name.as_deref(),
+            self.description.as_deref(),
+            self.parent,
+            self.main_file_name.as_deref(),
+            reference,
+            self.extra_bundle_extensions.as_deref(),
+            self.callback,
+            &self.options,
+        )
+        .await?;
+        version.set_hidden(self.hidden);
+        if let Some(min_client_version) = &self.min_client_version {
+            version.set_min_client_version(min_client_version);
+        }
+        Ok((version, items, warnings))
+    }
 }
 
 /// Contains all the info comprising a FusionFall build.
@@ -101,6 +1115,20 @@ pub struct Version {
     #[serde(skip_serializing_if = "Option::is_none")]
     hidden: Option<bool>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_client_version: Option<String>,
+
+    /// When the manifest was generated, in RFC 3339 format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+
+    /// Version of ffbuildtool that generated the manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generator_version: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    main_file_name: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     main_file_url: Option<String>,
 
@@ -113,12 +1141,22 @@ pub struct Version {
     #[serde(skip_serializing_if = "Option::is_none")]
     total_uncompressed_size: Option<u64>,
 
+    /// Merkle root of the build's file hashes. See `merkle_root`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_root: Option<String>,
+
+    /// Info of the single-file archive of the build, if one was made with `export_archive`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive_info: Option<FileInfo>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(serialize_with = "util::serialize_sorted")]
     bundles: HashMap<String, BundleInfo>,
 }
 impl Version {
     /// Generates `Version` metadata given a local build root (compressed asset bundles).
+    /// Use `Version::builder` for a main file that isn't named `main.unity3d`.
     pub async fn build(
         asset_root: &str,
         asset_url: &str,
@@ -126,42 +1164,396 @@ impl Version {
         description: Option<&str>,
         parent: Option<Uuid>,
     ) -> Result<Self, Error> {
-        let main_path = PathBuf::from(asset_root).join("main.unity3d");
-        let main_file_info = FileInfo::build(&main_path.to_string_lossy()).await.ok();
-        let (total_compressed_size, total_uncompressed_size, bundles) =
-            Self::get_bundle_info(asset_root).await?;
+        Self::build_with_progress(asset_root, asset_url, name, description, parent, None).await
+    }
+
+    /// Same as `build`, but calls `callback` each time a bundle finishes processing.
+    pub async fn build_with_progress(
+        asset_root: &str,
+        asset_url: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        parent: Option<Uuid>,
+        callback: Option<BuildCallback>,
+    ) -> Result<Self, Error> {
+        let (version, _, _) = Self::build_internal(
+            asset_root,
+            asset_url,
+            name,
+            description,
+            parent,
+            None,
+            None,
+            None,
+            callback,
+            &OperationOptions::default(),
+        )
+        .await?;
+        Ok(version)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn build_internal(
+        asset_root: &str,
+        asset_url: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        parent: Option<Uuid>,
+        main_file_name: Option<&str>,
+        reference: Option<&Version>,
+        extra_extensions: Option<&[String]>,
+        callback: Option<BuildCallback>,
+        options: &OperationOptions,
+    ) -> Result<(Self, Vec<ItemReport>, Vec<BuildWarning>), Error> {
+        check_asset_url(asset_url)?;
+        let uuid = Uuid::new_v4();
+        // only record the name in the manifest if it isn't the default
+        let main_file_name = main_file_name.filter(|name| *name != DEFAULT_MAIN_FILE_NAME);
+        let main_name = main_file_name.unwrap_or(DEFAULT_MAIN_FILE_NAME);
+        let main_path = PathBuf::from(asset_root).join(main_name);
+        let main_file_info = FileInfo::build(&main_path.to_string_lossy(), options)
+            .await
+            .ok();
+        let expected = reference.map(|r| {
+            Arc::new(
+                r.bundles
+                    .iter()
+                    .map(|(name, info)| (name.clone(), info.compressed_info.clone()))
+                    .collect::<HashMap<_, _>>(),
+            )
+        });
+        let (bundles, mut items, warnings) = Self::get_bundle_info(
+            uuid,
+            asset_root,
+            main_name,
+            extra_extensions,
+            expected,
+            callback,
+            options,
+        )
+        .await?;
+
+        if let Some(reference) = reference {
+            if let Some(expected) = &reference.main_file_info {
+                let (actual, result) = main_file_info
+                    .clone()
+                    .unwrap_or_default()
+                    .validate_rehashed(&main_path.to_string_lossy(), expected, options)
+                    .await;
+                items.push(ItemReport::new(
+                    main_name,
+                    expected.clone(),
+                    actual,
+                    result.err(),
+                ));
+            }
+            for (bundle_name, bundle_info) in &reference.bundles {
+                if !items.iter().any(|item| &item.name == bundle_name) {
+                    warn!("[{}] {} missing", uuid, bundle_name);
+                    items.push(ItemReport::new(
+                        bundle_name,
+                        bundle_info.compressed_info.clone(),
+                        FileInfo::default(),
+                        Some(FailReason::Missing),
+                    ));
+                }
+            }
+        }
+
+        let total_compressed_size = bundles.values().map(|b| b.compressed_info.size).sum();
+        let total_uncompressed_size = bundles.values().map(|b| b.get_uncompressed_size()).sum();
+        info!("[{}] {} bytes compressed", uuid, total_compressed_size);
+        info!("[{}] {} bytes uncompressed", uuid, total_uncompressed_size);
+
         let asset_url = asset_url.trim_end_matches('/');
-        let main_file_url = format!("{}/main.unity3d", asset_url);
-        Ok(Self {
-            uuid: Uuid::new_v4(),
+        let main_file_url = format!("{}/{}", asset_url, main_name);
+        let mut version = Self {
+            uuid,
             name: name.map(|s| s.to_string()),
             description: description.map(|s| s.to_string()),
             parent_uuid: parent,
+            main_file_name: main_file_name.map(|s| s.to_string()),
             main_file_url: Some(main_file_url),
             main_file_info,
             hidden: Some(false),
+            min_client_version: None,
+            created_at: Some(util::format_rfc3339(std::time::SystemTime::now())),
+            generator_version: Some(env!("CARGO_PKG_VERSION").to_string()),
             total_compressed_size: Some(total_compressed_size),
             total_uncompressed_size: Some(total_uncompressed_size),
+            content_root: None,
+            archive_info: None,
             asset_url: asset_url.to_string(),
             bundles,
-        })
+        };
+        version.check_case_collisions()?;
+        version.content_root = Some(version.merkle_root());
+        Ok((version, items, warnings))
+    }
+
+    /// Returns a `VersionBuilder` for generating `Version` metadata from a local build root.
+    pub fn builder() -> VersionBuilder {
+        VersionBuilder::new()
     }
 
     // Generates barebones `Version` metadata with only the asset URL and optional name.
-    pub fn build_barebones(asset_url: &str, name: Option<&str>) -> Self {
-        Self {
+    pub fn build_barebones(asset_url: &str, name: Option<&str>) -> Result<Self, Error> {
+        check_asset_url(asset_url)?;
+        Ok(Self {
             uuid: Uuid::new_v4(),
             name: name.map(|s| s.to_string()),
             description: None,
             parent_uuid: None,
+            main_file_name: None,
             main_file_url: None,
             main_file_info: None,
             hidden: None,
+            min_client_version: None,
+            created_at: None,
+            generator_version: None,
             asset_url: asset_url.to_string(),
             total_compressed_size: None,
             total_uncompressed_size: None,
+            content_root: None,
+            archive_info: None,
             bundles: HashMap::new(),
+        })
+    }
+
+    /// Generates `Version` metadata from a tree of already-extracted asset bundles, for builds that are
+    /// distributed without the compressed bundles. Each subdirectory of `asset_root` is taken as a bundle
+    /// extracted to `<asset_root>/<url-encoded bundle name>`, the layout `validate_uncompressed` expects,
+    /// and the files in it are hashed into the bundle's uncompressed info. The bundles have no compressed
+    /// info, so the result can't be used to validate or download compressed bundles. A main file in
+    /// `asset_root`, named `main_file_name` or `main.unity3d` if not specified, is recorded as usual.
+    pub async fn build_uncompressed(
+        asset_root: &str,
+        asset_url: &str,
+        name: Option<&str>,
+        main_file_name: Option<&str>,
+    ) -> Result<Self, Error> {
+        let mut version = Self::build_barebones(asset_url, name)?;
+        let options = OperationOptions::default();
+        // only record the name in the manifest if it isn't the default
+        version.main_file_name = main_file_name
+            .filter(|name| *name != DEFAULT_MAIN_FILE_NAME)
+            .map(|name| name.to_string());
+        let main_name = version.get_main_file_name().to_string();
+        let main_path = PathBuf::from(asset_root).join(&main_name);
+        if main_path.is_file() {
+            version.main_file_url = Some(format!("{}/{}", version.get_asset_url(), main_name));
+            version.main_file_info =
+                Some(FileInfo::build(&main_path.to_string_lossy(), &options).await?);
+        }
+
+        let mut tasks = Vec::new();
+        for entry in std::fs::read_dir(asset_root)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let dir_name = util::get_file_name_without_parent(&path.to_string_lossy()).to_string();
+            let Some(bundle_name) = util::url_decode(&dir_name) else {
+                warn!(
+                    "[{}] Skipping {}: not an extracted bundle directory",
+                    version.uuid, dir_name
+                );
+                continue;
+            };
+            let uuid = version.uuid;
+            let options = options.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = options.acquire_item().await;
+                debug!("[{}] Hashing files in {}", uuid, dir_name);
+                let dir_path = path.to_string_lossy().to_string();
+                let hash_cache = options.get_hash_cache();
+                let algorithm = options.get_hash_algorithm();
+                let uncompressed_info = tokio::task::spawn_blocking(move || {
+                    let mut uncompressed_info = HashMap::new();
+                    let file_names = util::list_filenames_in_directory(&dir_path)
+                        .map_err(|e| format!("Couldn't list files in {}: {}", dir_path, e))?;
+                    for file_name in file_names {
+                        let file_path = PathBuf::from(&dir_path).join(&file_name);
+                        let file_info = FileInfo::try_build_file(
+                            &file_path,
+                            algorithm,
+                            hash_cache.as_deref(),
+                            None,
+                        )
+                        .map_err(|e| format!("Couldn't read {}: {}", file_path.display(), e))?;
+                        uncompressed_info.insert(file_name, file_info);
+                    }
+                    Ok::<_, String>(uncompressed_info)
+                })
+                .await
+                .map_err(|e| e.to_string())??;
+                let bundle_info = BundleInfo {
+                    uncompressed_info,
+                    ..BundleInfo::from(FileInfo::default())
+                };
+                Ok::<_, String>((bundle_name, bundle_info))
+            }));
+        }
+        for task in tasks {
+            let (bundle_name, bundle_info) = task.await??;
+            version.bundles.insert(bundle_name, bundle_info);
+        }
+        info!(
+            "[{}] Found {} extracted bundles in {}",
+            version.uuid,
+            version.bundles.len(),
+            asset_root
+        );
+
+        version.check_case_collisions()?;
+        version.total_uncompressed_size = Some(
+            version
+                .bundles
+                .values()
+                .map(|b| b.get_uncompressed_size())
+                .sum(),
+        );
+        Ok(version)
+    }
+
+    /// Generates `Version` metadata from a `sha256sum`-style file at `path`, with a hash and file name
+    /// on each line. The file named `main_file_name` (`main.unity3d` if not specified) is taken as
+    /// the main file and every other file as a compressed asset bundle, without any uncompressed
+    /// file info. The files' sizes are read from `asset_root` if given, or requested from the asset
+    /// URL otherwise.
+    pub async fn from_sha256sums(
+        path: &str,
+        asset_url: &str,
+        asset_root: Option<&str>,
+        main_file_name: Option<&str>,
+    ) -> Result<Self, Error> {
+        let mut version = Self::build_barebones(asset_url, None)?;
+        // only record the name in the manifest if it isn't the default
+        version.main_file_name = main_file_name
+            .filter(|name| *name != DEFAULT_MAIN_FILE_NAME)
+            .map(|name| name.to_string());
+        let sums = util::parse_sha256sums(&std::fs::read_to_string(path)?)?;
+        info!("[{}] Found {} files in {}", version.uuid, sums.len(), path);
+
+        for (name, hash) in sums {
+            let size = match asset_root {
+                Some(asset_root) => std::fs::metadata(PathBuf::from(asset_root).join(&name))
+                    .map_err(|e| format!("Couldn't read {} in {}: {}", name, asset_root, e))?
+                    .len(),
+                None => {
+                    let url = format!("{}/{}", version.get_asset_url(), name);
+                    let (size, _) =
+                        util::fetch_remote_file_info(&url, None, &OperationOptions::default())
+                            .await
+                            .map_err(|e| format!("Couldn't get size of {}: {}", url, e))?;
+                    size
+                }
+            };
+            let file_info = FileInfo { hash, size };
+            if name == version.get_main_file_name() {
+                version.main_file_url = Some(format!("{}/{}", version.get_asset_url(), name));
+                version.main_file_info = Some(file_info);
+            } else {
+                version.bundles.insert(name, file_info.into());
+            }
+        }
+
+        version.check_case_collisions()?;
+        version.total_compressed_size = Some(
+            version
+                .bundles
+                .values()
+                .map(|b| b.compressed_info.size)
+                .sum(),
+        );
+        version.total_uncompressed_size = Some(0);
+        version.content_root = Some(version.merkle_root());
+        Ok(version)
+    }
+
+    /// Writes the hashes of the main file and all compressed asset bundles to `path` in the format
+    /// `sha256sum` uses, sorted by file name, so they can be checked with `sha256sum -c`.
+    /// Fails if any of the hashes isn't plain SHA-256.
+    pub fn export_sha256sums(&self, path: &str) -> Result<(), Error> {
+        let mut sums: Vec<(&str, &str)> = self
+            .bundles
+            .iter()
+            .map(|(name, bundle_info)| (name.as_str(), bundle_info.compressed_info.hash.as_str()))
+            .collect();
+        if let Some(main_file_info) = &self.main_file_info {
+            sums.push((self.get_main_file_name(), &main_file_info.hash));
+        }
+        if let Some((name, _)) = sums
+            .iter()
+            .find(|(_, hash)| HashAlgorithm::of(hash) != HashAlgorithm::Sha256)
+        {
+            return Err(format!("{} doesn't have a plain SHA-256 hash", name).into());
         }
+        sums.sort();
+        let text: String = sums
+            .into_iter()
+            .map(|(name, hash)| format!("{}  {}\n", hash, name))
+            .collect();
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Flattens the uncompressed info of every bundle into an `UncompressedManifest`, without re-hashing
+    /// anything. Bundles without uncompressed info are left out.
+    pub fn to_uncompressed_manifest(&self) -> UncompressedManifest {
+        let mut files = BTreeMap::new();
+        for (bundle_name, bundle_info) in &self.bundles {
+            if bundle_info.uncompressed_info.is_empty() {
+                warn!(
+                    "[{}] {} has no uncompressed info; leaving it out",
+                    self.uuid, bundle_name
+                );
+            }
+            let dir_name = util::url_encode(bundle_name);
+            for (file_name, file_info) in &bundle_info.uncompressed_info {
+                files.insert(format!("{}/{}", dir_name, file_name), file_info.clone());
+            }
+        }
+        UncompressedManifest {
+            uuid: self.uuid,
+            asset_url: self.asset_url.clone(),
+            name: self.name.clone(),
+            files,
+        }
+    }
+
+    /// Regroups the files in an `UncompressedManifest` into bundles, giving `Version` metadata
+    /// that can be used with `validate_uncompressed`. Like `build_uncompressed`, the bundles have no
+    /// compressed info, and the part of each bundle name before its first escaped character is lowercase.
+    pub fn from_uncompressed_manifest(manifest: &UncompressedManifest) -> Result<Self, Error> {
+        let mut version = Self::build_barebones(&manifest.asset_url, manifest.name.as_deref())?;
+        version.uuid = manifest.uuid;
+        for (path, file_info) in &manifest.files {
+            let bundle_name = path
+                .split_once('/')
+                .and_then(|(dir_name, _)| util::url_decode(dir_name));
+            let (Some(bundle_name), Some((_, file_name))) = (bundle_name, path.split_once('/'))
+            else {
+                return Err(
+                    format!("{} isn't a path to a file in an extracted bundle", path).into(),
+                );
+            };
+            version
+                .bundles
+                .entry(bundle_name)
+                .or_insert_with(|| FileInfo::default().into())
+                .uncompressed_info
+                .insert(file_name.to_string(), file_info.clone());
+        }
+        version.check_case_collisions()?;
+        version.total_uncompressed_size = Some(
+            version
+                .bundles
+                .values()
+                .map(|b| b.get_uncompressed_size())
+                .sum(),
+        );
+        Ok(version)
     }
 
     pub fn get_uuid(&self) -> Uuid {
@@ -180,6 +1572,17 @@ impl Version {
         self.description.as_deref()
     }
 
+    /// Returns when the manifest was generated, in RFC 3339 format (e.g. `2024-11-02T18:30:00Z`).
+    /// Older manifests don't have this.
+    pub fn get_created_at(&self) -> Option<&str> {
+        self.created_at.as_deref()
+    }
+
+    /// Returns the version of ffbuildtool that generated the manifest, if recorded.
+    pub fn get_generator_version(&self) -> Option<&str> {
+        self.generator_version.as_deref()
+    }
+
     /// Returns the total size of the build in bytes, including the main file.
     pub fn get_total_compressed_size(&self) -> u64 {
         self.main_file_info.clone().unwrap_or_default().size + self.get_compressed_assets_size()
@@ -195,99 +1598,540 @@ impl Version {
         self.total_uncompressed_size.unwrap_or(0)
     }
 
+    /// Returns the total uncompressed size of the asset bundles divided by their total
+    /// compressed size, or `None` if either is unknown.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        get_compression_ratio(
+            self.get_uncompressed_assets_size(),
+            self.get_compressed_assets_size(),
+        )
+    }
+
     /// Returns the asset URL for the build without a trailing slash.
     pub fn get_asset_url(&self) -> String {
         let mut url = self.asset_url.clone();
         if url.ends_with('/') {
             url.pop();
         }
-        url
-    }
+        url
+    }
+
+    /// Returns the URL to download an asset bundle from. This is under the build's asset URL unless
+    /// the bundle was inherited from a parent build hosted elsewhere, see `resolve_with_parent`.
+    pub fn get_bundle_url(&self, bundle_name: &str) -> String {
+        let asset_url = self
+            .bundles
+            .get(bundle_name)
+            .and_then(|bundle_info| bundle_info.asset_url.as_deref())
+            .unwrap_or(&self.asset_url);
+        format!("{}/{}", asset_url.trim_end_matches('/'), bundle_name)
+    }
+
+    /// Returns the file name of the main file in the build root, `main.unity3d` unless the manifest says otherwise.
+    pub fn get_main_file_name(&self) -> &str {
+        self.main_file_name
+            .as_deref()
+            .unwrap_or(DEFAULT_MAIN_FILE_NAME)
+    }
+
+    /// Returns the main file URL for the build.
+    pub fn get_main_file_url(&self) -> Option<String> {
+        self.main_file_url.clone()
+    }
+
+    /// Returns the size and hash of the main file, if present in the manifest.
+    pub fn get_main_file_info(&self) -> Option<&FileInfo> {
+        self.main_file_info.as_ref()
+    }
+
+    /// Marks the build as hidden or unhidden. What this means is up to the client code;
+    /// in OpenFusionLauncher, it will hide the build from the list of available builds.
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = Some(hidden);
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden.unwrap_or(false)
+    }
+
+    /// Sets the minimum client version required to run the build. Like `set_hidden`, it's up to
+    /// the client code to interpret it, e.g. by refusing to launch the build.
+    pub fn set_min_client_version(&mut self, min_client_version: &str) {
+        self.min_client_version = Some(min_client_version.to_string());
+    }
+
+    pub fn get_min_client_version(&self) -> Option<&str> {
+        self.min_client_version.as_deref()
+    }
+
+    /// Overrides the asset URL for the build. Useful for testing.
+    pub fn set_asset_url(&mut self, asset_url: &str) -> Result<(), Error> {
+        check_asset_url(asset_url)?;
+        self.asset_url = asset_url.to_string();
+        Ok(())
+    }
+
+    /// Overrides the main file URL for the build. Useful for testing.
+    pub fn set_main_file_url(&mut self, main_file_url: &str) {
+        self.main_file_url = Some(main_file_url.to_string());
+    }
+
+    /// Loads the `Version` metadata from a JSON manifest file path or URL. `file://` URLs are read from disk.
+    pub async fn from_manifest(path_or_url: &str) -> Result<Self, Error> {
+        if let Some(path) = util::file_uri_to_path(path_or_url) {
+            Self::from_manifest_file(&path)
+        } else if path_or_url.starts_with("http") {
+            Self::from_manifest_url(path_or_url).await
+        } else {
+            Self::from_manifest_file(path_or_url)
+        }
+    }
+
+    /// Same as `from_manifest`, but fails if the manifest isn't internally consistent (see `validate_self`).
+    pub async fn from_manifest_checked(path_or_url: &str) -> Result<Self, Error> {
+        let version = Self::from_manifest(path_or_url).await?;
+        let problems = version.validate_self();
+        if !problems.is_empty() {
+            return Err(format!("Inconsistent manifest: {}", problems.join("; ")).into());
+        }
+        Ok(version)
+    }
+
+    /// Checks the metadata for internal consistency, e.g. after the manifest was edited by hand.
+    /// Returns a description of each problem found.
+    pub fn validate_self(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Err(e) = check_asset_url(&self.asset_url) {
+            problems.push(e.to_string());
+        }
+
+        if self.main_file_url.is_some() && self.main_file_info.is_none() {
+            problems.push("Main file URL is set but main file info is missing".to_string());
+        }
+
+        let mut bundle_names: Vec<&String> = self.bundles.keys().collect();
+        bundle_names.sort();
+        for bundle_name in bundle_names {
+            let bundle_info = &self.bundles[bundle_name];
+            if bundle_info.compressed_info.hash.is_empty() {
+                problems.push(format!("{} has an empty hash", bundle_name));
+            }
+            if let Some(Err(e)) = bundle_info.asset_url.as_deref().map(check_asset_url) {
+                problems.push(format!("{}: {}", bundle_name, e));
+            }
+            for (file_name, file_info) in &bundle_info.uncompressed_info {
+                if file_info.hash.is_empty() {
+                    problems.push(format!("{}/{} has an empty hash", bundle_name, file_name));
+                }
+            }
+        }
+
+        let compressed_sum: u64 = self.bundles.values().map(|b| b.compressed_info.size).sum();
+        if let Some(total) = self.total_compressed_size {
+            if total != compressed_sum {
+                problems.push(format!(
+                    "Total compressed size is {} but the bundles add up to {}",
+                    total, compressed_sum
+                ));
+            }
+        }
+        let uncompressed_sum: u64 = self
+            .bundles
+            .values()
+            .map(|b| b.get_uncompressed_size())
+            .sum();
+        if let Some(total) = self.total_uncompressed_size {
+            if total != uncompressed_sum {
+                problems.push(format!(
+                    "Total uncompressed size is {} but the bundles add up to {}",
+                    total, uncompressed_sum
+                ));
+            }
+        }
+
+        problems
+    }
+
+    /// Loads the `Version` metadata from a JSON manifest file.
+    /// Files ending in `.json.gz` or `.json.zst` are decompressed with gzip or zstd.
+    /// Fails if any bundle names differ only in case (see `find_case_collisions`).
+    pub fn from_manifest_file(path: &str) -> Result<Self, Error> {
+        let data = std::fs::read(path)?;
+        let json = util::ManifestCompression::from_path(path).decompress(&data)?;
+        Self::from_manifest_json(&json)
+    }
+
+    /// Loads the `Version` metadata from a JSON manifest file hosted on the web.
+    /// The manifest is decompressed according to the `Content-Encoding` header or,
+    /// failing that, the extension in the URL (see `from_manifest_file`).
+    pub async fn from_manifest_url(url: &str) -> Result<Self, Error> {
+        let json = Self::fetch_manifest_json(url, None).await?;
+        Self::from_manifest_json(&json)
+    }
+
+    /// Same as `from_manifest_url`, but fails if the SHA-256 hash of the manifest file as served
+    /// (before any decompression) doesn't match `expected_hash`, e.g. when the mirror was compromised.
+    pub async fn from_manifest_url_verified(url: &str, expected_hash: &str) -> Result<Self, Error> {
+        let json = Self::fetch_manifest_json(url, Some(expected_hash)).await?;
+        Self::from_manifest_json(&json)
+    }
+
+    /// Downloads the manifest at `url` (or reads it, for `file://` URIs) but only parses the
+    /// top-level metadata, skipping the per-bundle info. Useful for rendering a list of builds.
+    pub async fn peek_metadata(url: &str) -> Result<BuildSummary, Error> {
+        let json = match util::file_uri_to_path(url) {
+            Some(path) => {
+                let data = std::fs::read(&path)?;
+                util::ManifestCompression::from_path(&path).decompress(&data)?
+            }
+            None => Self::fetch_manifest_json(url, None).await?,
+        };
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    async fn fetch_manifest_json(url: &str, expected_hash: Option<&str>) -> Result<Vec<u8>, Error> {
+        let permit = ConcurrencyConfig::default().acquire_download().await;
+        let response = reqwest::get(url).await?.error_for_status()?;
+        let compression = response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|encoding| encoding.to_str().ok())
+            .and_then(util::ManifestCompression::from_content_encoding)
+            .unwrap_or_else(|| util::ManifestCompression::from_path(url));
+        let data = response.bytes().await?;
+        drop(permit);
+
+        if let Some(expected_hash) = expected_hash {
+            let hash = util::get_buffer_hash(&data);
+            if !hash.eq_ignore_ascii_case(expected_hash) {
+                return Err(format!(
+                    "Manifest at {} failed validation: {}",
+                    url,
+                    FailReason::BadHash {
+                        expected: expected_hash.to_string(),
+                        actual: hash,
+                        first_mismatch: None,
+                    }
+                )
+                .into());
+            }
+        }
+        compression.decompress(&data)
+    }
+
+    fn from_manifest_json(json: &[u8]) -> Result<Self, Error> {
+        let version: Self = serde_json::from_slice(json)?;
+        version.check_case_collisions()?;
+        if version
+            .get_content_root()
+            .is_some_and(|root| root != version.merkle_root())
+        {
+            warn!(
+                "[{}] Content root doesn't match the file info; the manifest was edited",
+                version.uuid
+            );
+        }
+        Ok(version)
+    }
+
+    /// Exports the `Version` metadata to a JSON file to be served from an API server.
+    /// Paths ending in `.json.gz` or `.json.zst` are compressed with gzip or zstd.
+    pub fn export_manifest(&self, path: &str) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self)?;
+        let data = util::ManifestCompression::from_path(path).compress(json.as_bytes())?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn get_bundle(&self, name: &str) -> Option<&BundleInfo> {
+        self.bundles.get(name)
+    }
+
+    /// Iterates over every asset bundle in the build by name, in no particular order.
+    pub fn get_bundles(&self) -> impl Iterator<Item = (&str, &BundleInfo)> {
+        self.bundles
+            .iter()
+            .map(|(name, bundle_info)| (name.as_str(), bundle_info))
+    }
+
+    /// Produces a complete build from a thin child manifest by overlaying its asset bundles onto
+    /// those of `parent`. Bundles in the child win on name collisions, and the main file is taken
+    /// from the parent if the child doesn't have one. Everything else comes from the child.
+    /// Inherited bundles are still downloaded from the parent's asset URL.
+    pub fn resolve_with_parent(&self, parent: &Version) -> Version {
+        if self.parent_uuid != Some(parent.uuid) {
+            warn!(
+                "[{}] Resolving with {}, which isn't the build's parent",
+                self.uuid, parent.uuid
+            );
+        }
 
-    /// Returns the main file URL for the build.
-    pub fn get_main_file_url(&self) -> Option<String> {
-        self.main_file_url.clone()
-    }
+        let mut resolved = self.clone();
+        // the child's archive, if any, doesn't contain the parent's bundles
+        resolved.archive_info = None;
+        resolved.bundles = parent.bundles.clone();
+        if parent.get_asset_url() != self.get_asset_url() {
+            for bundle_info in resolved.bundles.values_mut() {
+                bundle_info
+                    .asset_url
+                    .get_or_insert_with(|| parent.get_asset_url());
+            }
+        }
+        resolved.bundles.extend(self.bundles.clone());
+        if resolved.main_file_info.is_none() {
+            resolved.main_file_name = parent.main_file_name.clone();
+            resolved.main_file_url = parent.main_file_url.clone();
+            resolved.main_file_info = parent.main_file_info.clone();
+        }
 
-    /// Marks the build as hidden or unhidden. What this means is up to the client code;
-    /// in OpenFusionLauncher, it will hide the build from the list of available builds.
-    pub fn set_hidden(&mut self, hidden: bool) {
-        self.hidden = Some(hidden);
+        let bundles = resolved.bundles.values();
+        resolved.total_compressed_size =
+            Some(bundles.clone().map(|b| b.compressed_info.size).sum());
+        resolved.total_uncompressed_size = Some(bundles.map(|b| b.get_uncompressed_size()).sum());
+        if resolved.content_root.is_some() {
+            resolved.content_root = Some(resolved.merkle_root());
+        }
+        resolved
     }
 
-    pub fn is_hidden(&self) -> bool {
-        self.hidden.unwrap_or(false)
+    /// Sets whether the per-bundle uncompressed file info should be stored as a compressed,
+    /// base64-encoded blob when exporting the manifest. Packed info is inflated transparently on load.
+    /// See the README for the size tradeoff.
+    pub fn set_pack_uncompressed_info(&mut self, pack: bool) {
+        for bundle_info in self.bundles.values_mut() {
+            bundle_info.pack_uncompressed_info = pack;
+        }
     }
 
-    /// Overrides the asset URL for the build. Useful for testing.
-    pub fn set_asset_url(&mut self, asset_url: &str) {
-        self.asset_url = asset_url.to_string();
+    /// Records per-block hashes of every compressed asset bundle in the local build root,
+    /// enabling `BundleInfo::validate_compressed_ranges`. Each bundle must pass validation first.
+    pub fn generate_block_hashes(
+        &mut self,
+        asset_root: &str,
+        block_size: u64,
+    ) -> Result<(), Error> {
+        for (bundle_name, bundle_info) in self.bundles.iter_mut() {
+            let file_path = PathBuf::from(asset_root).join(bundle_name);
+            let file_path = file_path.to_str().unwrap();
+            let algorithm = HashAlgorithm::of(&bundle_info.compressed_info.hash);
+            FileInfo::build_file(
+                file_path,
+                &OperationOptions::default().with_hash_algorithm(algorithm),
+            )
+            .validate(&bundle_info.compressed_info)
+            .map_err(|e| format!("Can't hash blocks of {}: {}", bundle_name, e))?;
+            bundle_info.compressed_blocks = Some(BlockHashes {
+                block_size,
+                hashes: util::get_file_block_hashes(file_path, block_size)?,
+            });
+        }
+        Ok(())
     }
 
-    /// Overrides the main file URL for the build. Useful for testing.
-    pub fn set_main_file_url(&mut self, main_file_url: &str) {
-        self.main_file_url = Some(main_file_url.to_string());
+    /// Returns groups of bundle names that differ only in case. Each group would map to the same
+    /// file on a case-insensitive filesystem (e.g. on Windows), making validation and downloads ambiguous.
+    pub fn find_case_collisions(&self) -> Vec<Vec<String>> {
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for bundle_name in self.bundles.keys() {
+            groups
+                .entry(bundle_name.to_lowercase())
+                .or_default()
+                .push(bundle_name.clone());
+        }
+        groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort();
+                group
+            })
+            .collect()
     }
 
-    /// Loads the `Version` metadata from a JSON manifest file path or URL.
-    pub async fn from_manifest(path_or_url: &str) -> Result<Self, Error> {
-        if path_or_url.starts_with("http") {
-            Self::from_manifest_url(path_or_url).await
-        } else {
-            Self::from_manifest_file(path_or_url)
+    /// Computes a single SHA-256 digest identifying the contents of the build, as a lowercase hex string.
+    /// Each leaf is the hash of a file's name and hash, sorted by name and including the main file,
+    /// and pairs of nodes are hashed together until one is left. Two builds with the same root have
+    /// identical files, regardless of their other metadata.
+    pub fn merkle_root(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut files: Vec<(&str, &str)> = self
+            .bundles
+            .iter()
+            .map(|(name, bundle_info)| (name.as_str(), bundle_info.compressed_info.hash.as_str()))
+            .collect();
+        if let Some(main_file_info) = &self.main_file_info {
+            files.push((self.get_main_file_name(), &main_file_info.hash));
         }
+        files.sort();
+
+        let mut level: Vec<Vec<u8>> = files
+            .iter()
+            .map(|(name, hash)| Sha256::digest(format!("{}:{}", name, hash)).to_vec())
+            .collect();
+        if level.is_empty() {
+            return format!("{:x}", Sha256::digest(b""));
+        }
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Sha256::digest([left.as_slice(), right].concat()).to_vec(),
+                    // an odd node out is carried up as-is
+                    [node] => node.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level[0].iter().map(|b| format!("{:02x}", b)).collect()
     }
 
-    /// Loads the `Version` metadata from a JSON manifest file.
-    pub fn from_manifest_file(path: &str) -> Result<Self, Error> {
-        let json = std::fs::read_to_string(path)?;
-        let version: Self = serde_json::from_str(&json)?;
-        Ok(version)
+    /// Returns the Merkle root recorded in the manifest when it was generated, if any.
+    /// Compare it with `merkle_root` to check that the file info wasn't edited since.
+    pub fn get_content_root(&self) -> Option<&str> {
+        self.content_root.as_deref()
     }
 
-    /// Loads the `Version` metadata from a JSON manifest file hosted on the web.
-    pub async fn from_manifest_url(url: &str) -> Result<Self, Error> {
-        let manifest = TempFile::download(url).await?;
-        let version = Self::from_manifest_file(manifest.path())?;
-        Ok(version)
+    fn check_case_collisions(&self) -> Result<(), Error> {
+        let collisions = self.find_case_collisions();
+        if collisions.is_empty() {
+            return Ok(());
+        }
+        let collisions: Vec<String> = collisions.iter().map(|group| group.join(", ")).collect();
+        Err(format!(
+            "Bundle names differ only in case: {}",
+            collisions.join("; ")
+        )
+        .into())
     }
 
-    /// Exports the `Version` metadata to a JSON file to be served from an API server.
-    pub fn export_manifest(&self, path: &str) -> Result<(), Error> {
-        let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(path, json)?;
-        Ok(())
+    /// Lists the asset bundles in the local build root that aren't part of this build,
+    /// e.g. left over from a larger build previously stored in the same directory.
+    pub fn files_to_remove(&self, path: &str) -> Result<Vec<String>, Error> {
+        let mut stale: Vec<String> =
+            get_bundle_names_from_asset_root(path, self.get_main_file_name(), None)?
+                .into_iter()
+                .filter(|name| !self.bundles.contains_key(name))
+                .collect();
+        stale.sort();
+        Ok(stale)
     }
 
-    pub fn get_bundle(&self, name: &str) -> Option<&BundleInfo> {
-        self.bundles.get(name)
+    /// Wraps `callback` into one that also reports the aggregate progress of the whole build to `total_callback`.
+    /// Pass the result to `download_compressed`, `repair` or `validate_compressed`.
+    pub fn track_total_progress(
+        &self,
+        callback: Option<ProgressCallback>,
+        total_callback: TotalProgressCallback,
+    ) -> ProgressCallback {
+        let tracker = Mutex::new(TotalProgressTracker::new(self.get_total_compressed_size()));
+        Arc::new(move |uuid: &Uuid, name: &str, progress: ItemProgress| {
+            let total = tracker.lock().unwrap().update(name, &progress);
+            total_callback(uuid, total);
+            if let Some(ref cb) = callback {
+                cb(uuid, name, progress);
+            }
+        })
     }
 
     /// Searches for asset bundles in the specified directory and generates validation data for each one.
+    /// If `expected` is provided, each bundle is also validated against its entry; bundles that
+    /// fail are reported and skipped instead of being unpacked.
     async fn get_bundle_info(
+        uuid: Uuid,
         asset_root: &str,
-    ) -> Result<(u64, u64, HashMap<String, BundleInfo>), Error> {
-        let bundle_names = get_bundle_names_from_asset_root(asset_root)?;
-        info!("Found {} bundles", bundle_names.len());
-        info!("Processing...");
+        main_file_name: &str,
+        extra_extensions: Option<&[String]>,
+        expected: Option<Arc<HashMap<String, FileInfo>>>,
+        callback: Option<BuildCallback>,
+        options: &OperationOptions,
+    ) -> Result<BundleInfoResults, Error> {
+        let bundle_names =
+            get_bundle_names_from_asset_root(asset_root, main_file_name, extra_extensions)?;
+        let total_bundles = bundle_names.len();
+        info!("[{}] Found {} bundles", uuid, total_bundles);
+        info!("[{}] Processing...", uuid);
 
         let bundles: Arc<Mutex<HashMap<String, BundleInfo>>> = Arc::new(Mutex::new(HashMap::new()));
-        let mut tasks: Vec<JoinHandle<Result<(), String>>> = Vec::with_capacity(bundle_names.len());
+        let items = Arc::new(Mutex::new(Vec::new()));
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let running_totals = Arc::new(Mutex::new((0, 0, 0)));
+        let mut tasks: Vec<JoinHandle<Result<(), String>>> = Vec::with_capacity(total_bundles);
         for bundle_name in bundle_names {
-            let root = asset_root.to_string();
+            let file_path = format!("{}/{}", asset_root, bundle_name);
             let bundles = Arc::clone(&bundles);
+            let items = Arc::clone(&items);
+            let warnings = Arc::clone(&warnings);
+            let running_totals = Arc::clone(&running_totals);
+            let expected = expected.clone();
+            let cb = callback.clone();
+            let options = options.clone();
             tasks.push(tokio::spawn(async move {
-                let _permit = if let Some(permits) = ITEM_PERMITS.get() {
-                    Some(permits.acquire().await.unwrap())
-                } else {
-                    None
-                };
+                let _permit = options.acquire_item().await;
 
-                let bundle_info = BundleInfo::build(&root, &bundle_name)
+                let compressed_info = FileInfo::build(&file_path, &options)
                     .await
                     .map_err(|e| e.to_string())?;
-                debug!("Processed {}", bundle_name);
-                bundles.lock().unwrap().insert(bundle_name, bundle_info);
+                let mut failed = false;
+                match expected.as_ref().map(|e| e.get(&bundle_name)) {
+                    Some(Some(expected)) => {
+                        let (actual, result) = compressed_info
+                            .clone()
+                            .validate_rehashed(&file_path, expected, &options)
+                            .await;
+                        if let Err(ref e) = result {
+                            warn!("[{}] {} failed validation: {}", uuid, bundle_name, e);
+                            failed = true;
+                        }
+                        items.lock().unwrap().push(ItemReport::new(
+                            &bundle_name,
+                            expected.clone(),
+                            actual,
+                            result.err(),
+                        ));
+                    }
+                    Some(None) => {
+                        warn!(
+                            "[{}] {} is not in the reference manifest",
+                            uuid, bundle_name
+                        );
+                    }
+                    None => {}
+                }
+
+                let bundle_info = if failed {
+                    None
+                } else {
+                    let (bundle_info, bundle_warnings) =
+                        BundleInfo::build(&file_path, &bundle_name, compressed_info)
+                            .map_err(|e| e.to_string())?;
+                    warnings.lock().unwrap().extend(bundle_warnings);
+                    Some(bundle_info)
+                };
+                debug!("[{}] Processed {}", uuid, bundle_name);
+                let (processed, compressed, uncompressed) = {
+                    let mut totals = running_totals.lock().unwrap();
+                    totals.0 += 1;
+                    if let Some(bundle_info) = bundle_info {
+                        totals.1 += bundle_info.compressed_info.size;
+                        totals.2 += bundle_info.get_uncompressed_size();
+                        bundles
+                            .lock()
+                            .unwrap()
+                            .insert(bundle_name.clone(), bundle_info);
+                    }
+                    *totals
+                };
+                if let Some(cb) = cb {
+                    cb(
+                        &bundle_name,
+                        processed,
+                        total_bundles,
+                        compressed,
+                        uncompressed,
+                    );
+                }
                 Ok(())
             }));
         }
@@ -297,14 +2141,14 @@ impl Version {
                 return Err(e.into());
             }
         }
-        info!("Done processing");
+        save_hash_cache(options.get_hash_cache());
+        info!("[{}] Done processing", uuid);
 
         let bundles = Arc::try_unwrap(bundles).unwrap().into_inner().unwrap();
-        let total_compressed_size = bundles.values().map(|b| b.compressed_info.size).sum();
-        let total_uncompressed_size = bundles.values().map(|b| b.get_uncompressed_size()).sum();
-        info!("{} bytes compressed", total_compressed_size);
-        info!("{} bytes uncompressed", total_uncompressed_size);
-        Ok((total_compressed_size, total_uncompressed_size, bundles))
+        let items = Arc::try_unwrap(items).unwrap().into_inner().unwrap();
+        let mut warnings = Arc::try_unwrap(warnings).unwrap().into_inner().unwrap();
+        warnings.sort_by(|a, b| a.get_bundle().cmp(b.get_bundle()));
+        Ok((bundles, items, warnings))
     }
 
     /// Validates the compressed asset bundles against the metadata. Returns a list of corrupted bundles.
@@ -313,10 +2157,101 @@ impl Version {
         path: &str,
         callback: Option<ProgressCallback>,
     ) -> Result<Vec<String>, Error> {
-        self.validate_compressed_internal(path, false, false, callback)
+        self.validate_compressed_with_options(path, callback, &OperationOptions::default())
+            .await
+    }
+
+    /// Same as `validate_compressed`, but only checks the named bundles. The main file is only checked
+    /// if its name is included. Fails without checking anything if a name isn't in the manifest.
+    pub async fn validate_compressed_subset(
+        &self,
+        path: &str,
+        names: &[String],
+        callback: Option<ProgressCallback>,
+    ) -> Result<Vec<String>, Error> {
+        let main_file_name = self.get_main_file_name();
+        let unknown: Vec<&str> = names
+            .iter()
+            .filter(|name| {
+                let is_main_file = self.main_file_info.is_some() && *name == main_file_name;
+                !is_main_file && !self.bundles.contains_key(*name)
+            })
+            .map(|name| name.as_str())
+            .collect();
+        if !unknown.is_empty() {
+            return Err(format!("Not in the manifest: {}", unknown.join(", ")).into());
+        }
+
+        let mut subset = self.clone();
+        subset.bundles.retain(|name, _| names.contains(name));
+        if !names.iter().any(|name| name == main_file_name) {
+            subset.main_file_info = None;
+        }
+        subset
+            .validate_compressed_internal(
+                path,
+                false,
+                false,
+                callback,
+                &OperationOptions::default(),
+                &[],
+            )
+            .await
+    }
+
+    /// Checks only the main file in `path` against the metadata, e.g. as a quick sanity check on startup.
+    /// Returns why it failed, or `None` if it passed or the manifest has no main file info.
+    pub async fn validate_main_file(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+    ) -> Result<Option<FailReason>, Error> {
+        let Some(main_file_info) = self.main_file_info.clone() else {
+            return Ok(None);
+        };
+        let main_bundle_info: BundleInfo = main_file_info.into();
+        let main_file_path = PathBuf::from(path).join(self.get_main_file_name());
+        match main_bundle_info
+            .validate_compressed(
+                &main_file_path.to_string_lossy(),
+                Some(self.uuid),
+                None,
+                callback,
+            )
+            .await
+        {
+            Ok(_) => Ok(None),
+            Err(e) => match e.downcast::<FileFailure>() {
+                Ok(failure) => Ok(Some(failure.reason)),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// Same as `validate_compressed`, but with settings that override the global ones.
+    pub async fn validate_compressed_with_options(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<Vec<String>, Error> {
+        self.validate_compressed_internal(path, false, false, callback, options, &[])
             .await
     }
 
+    /// Same as `validate_compressed`, but also sends each corrupted item and the reason it failed
+    /// to `failures` as soon as it's found, e.g. to show failures while a long validation is still running.
+    /// Failures are sent in the order they're found, and all of them have been sent by the time this returns.
+    pub async fn validate_compressed_streaming(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        failures: mpsc::UnboundedSender<(String, FailReason)>,
+    ) -> Result<Vec<String>, Error> {
+        let callback = forward_failures(callback, failures);
+        self.validate_compressed(path, Some(callback)).await
+    }
+
     /// Validates the compressed asset bundles against the metadata. Stops on the first failure.
     /// Returns the name of the first corrupted bundle.
     pub async fn validate_compressed_stop_on_first_fail(
@@ -325,11 +2260,279 @@ impl Version {
         callback: Option<ProgressCallback>,
     ) -> Result<Option<String>, Error> {
         let corrupted = self
-            .validate_compressed_internal(path, false, true, callback)
+            .validate_compressed_internal(
+                path,
+                false,
+                true,
+                callback,
+                &OperationOptions::default(),
+                &[],
+            )
             .await?;
         Ok(corrupted.first().cloned())
     }
 
+    /// Validates the compressed asset bundles against the metadata.
+    /// Returns a report with the status of every bundle, including the main file.
+    pub async fn validate_compressed_report(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+    ) -> Result<ValidationReport, Error> {
+        self.validate_compressed_report_with_options(path, callback, &OperationOptions::default())
+            .await
+    }
+
+    /// Same as `validate_compressed_report`, but with settings that override the global ones.
+    pub async fn validate_compressed_report_with_options(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<ValidationReport, Error> {
+        info!(
+            "[{}] Generating validation report for {}...",
+            self.uuid, path
+        );
+
+        let reports = self
+            .check_compressed_items(path, callback, options)
+            .await?
+            .into_iter()
+            .map(|item| ItemReport::new(&item.name, item.expected, item.actual, item.reason))
+            .collect();
+
+        let report = ValidationReport::new(self.uuid, path, reports);
+        save_hash_cache(options.get_hash_cache());
+        info!(
+            "[{}] Validation complete; {} corrupted bundles",
+            self.uuid,
+            report.get_corrupted().len()
+        );
+        Ok(report)
+    }
+
+    /// Validates the compressed asset bundles like `repair` would, without downloading anything.
+    /// Returns the name, failure reason and size of every bundle that would be re-downloaded.
+    pub async fn repair_dry_run(
+        &self,
+        path: &str,
+    ) -> Result<Vec<(String, FailReason, u64)>, Error> {
+        info!("[{}] Checking build at {} for repair...", self.uuid, path);
+        let options = OperationOptions::default();
+        let mut to_download: Vec<(String, FailReason, u64)> = self
+            .check_compressed_items(path, None, &options)
+            .await?
+            .into_iter()
+            .filter_map(|item| Some((item.name, item.reason?, item.expected.size)))
+            .collect();
+        to_download.sort_by(|a, b| a.0.cmp(&b.0));
+        save_hash_cache(options.get_hash_cache());
+        info!(
+            "[{}] Repair would download {} bundles",
+            self.uuid,
+            to_download.len()
+        );
+        Ok(to_download)
+    }
+
+    /// Validates the compressed asset bundles like `repair` would, without downloading anything.
+    /// Returns where to download every missing or corrupted file from, including the main file,
+    /// and where to save it, so that another tool can do the repair.
+    pub async fn generate_repair_script(&self, path: &str) -> Result<RepairScript, Error> {
+        info!("[{}] Generating repair script for {}...", self.uuid, path);
+        let options = OperationOptions::default();
+        let mut entries: Vec<RepairEntry> = self
+            .check_compressed_items(path, None, &options)
+            .await?
+            .into_iter()
+            .filter_map(|item| {
+                let reason = item.reason?;
+                Some(RepairEntry {
+                    url: self.get_bundle_url(&item.name),
+                    local_path: PathBuf::from(path)
+                        .join(&item.name)
+                        .to_string_lossy()
+                        .to_string(),
+                    bundle: item.name,
+                    expected_hash: item.expected.hash,
+                    expected_size: item.expected.size,
+                    reason: reason.to_string(),
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.bundle.cmp(&b.bundle));
+        save_hash_cache(options.get_hash_cache());
+        info!(
+            "[{}] Repair script lists {} files",
+            self.uuid,
+            entries.len()
+        );
+        Ok(RepairScript {
+            uuid: self.uuid,
+            path: path.to_string(),
+            entries,
+        })
+    }
+
+    /// Hashes the main file and every compressed asset bundle in the local build root
+    /// and compares them against the metadata, without repairing anything.
+    async fn check_compressed_items(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<Vec<CheckedItem>, Error> {
+        let mut items: Vec<(String, FileInfo)> = self
+            .bundles
+            .iter()
+            .map(|(name, info)| (name.clone(), info.compressed_info.clone()))
+            .collect();
+        if let Some(main_file_info) = self.main_file_info.clone() {
+            items.push((self.get_main_file_name().to_string(), main_file_info));
+        }
+
+        let mut tasks = Vec::with_capacity(items.len());
+        for (name, expected) in items {
+            let cb = callback.clone();
+            let file_path = PathBuf::from(path).join(&name);
+            let uuid = self.uuid;
+            let options = options.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = options.acquire_item().await;
+
+                if let Some(ref cb) = cb {
+                    cb(&uuid, &name, ItemProgress::Validating);
+                }
+                let start = Instant::now();
+                let hash_cache = options.get_hash_cache();
+                let pacer = options.get_validation_pacer();
+                let algorithm = HashAlgorithm::of(&expected.hash);
+                let actual = tokio::task::spawn_blocking(move || {
+                    FileInfo::try_build_file(
+                        &file_path,
+                        algorithm,
+                        hash_cache.as_deref(),
+                        pacer.as_deref(),
+                    )
+                })
+                .await
+                .unwrap_or_else(|e| {
+                    Err(FailReason::Unreadable {
+                        error: e.to_string(),
+                    })
+                });
+                let (actual, result) = match actual {
+                    Ok(actual) => {
+                        let result = actual.validate(&expected);
+                        (actual, result)
+                    }
+                    Err(reason) => (FileInfo::default(), Err(reason)),
+                };
+                if let Some(ref cb) = cb {
+                    let item_size = expected.size;
+                    let progress = match &result {
+                        Ok(()) => ItemProgress::Passed {
+                            item_size,
+                            duration: start.elapsed(),
+                            was_downloaded: false,
+                        },
+                        Err(reason) => ItemProgress::Failed {
+                            item_size,
+                            reason: reason.clone(),
+                        },
+                    };
+                    cb(&uuid, &name, progress);
+                }
+                CheckedItem {
+                    name,
+                    expected,
+                    actual,
+                    reason: result.err(),
+                }
+            }));
+        }
+
+        let mut checked = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            checked.push(task.await?);
+        }
+        Ok(checked)
+    }
+
+    /// Validates the compressed asset bundles of several builds at once, given each `Version`
+    /// and the path to its local build root. All builds share the limits of `options`.
+    /// Returns the result for each build, in the same order as `builds`.
+    pub async fn validate_many(
+        builds: &[(Version, String)],
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Vec<Result<ValidationReport, Error>> {
+        info!("Validating {} builds...", builds.len());
+        futures_util::future::join_all(builds.iter().map(|(version, path)| {
+            version.validate_compressed_report_with_options(path, callback.clone(), options)
+        }))
+        .await
+    }
+
+    /// Checks that the server at the asset URL is serving the main file and asset bundles described by
+    /// the metadata, without saving anything to disk. Only sizes are compared unless `verify_hashes`
+    /// is true, in which case each file is streamed in full to hash it.
+    /// Requests are limited by `set_max_concurrent_downloads`. Returns a list of corrupted files.
+    pub async fn validate_remote(
+        &self,
+        verify_hashes: bool,
+        callback: Option<ProgressCallback>,
+    ) -> Result<Vec<String>, Error> {
+        info!(
+            "[{}] Validating remote files at {}...",
+            self.uuid,
+            self.get_asset_url()
+        );
+
+        let asset_url = self.get_asset_url();
+        let mut items: Vec<(String, String, BundleInfo)> = self
+            .bundles
+            .iter()
+            .map(|(name, info)| (name.clone(), self.get_bundle_url(name), info.clone()))
+            .collect();
+        if let Some(main_file_info) = self.main_file_info.clone() {
+            let url = self.main_file_url.clone().unwrap_or(format!(
+                "{}/{}",
+                asset_url,
+                self.get_main_file_name()
+            ));
+            let name = self.get_main_file_name().to_string();
+            items.push((name, url, main_file_info.into()));
+        }
+
+        let mut tasks = Vec::with_capacity(items.len());
+        for (name, url, bundle_info) in items {
+            let cb = callback.clone();
+            let uuid = self.uuid;
+            tasks.push(tokio::spawn(async move {
+                bundle_info
+                    .validate_remote(&url, &name, uuid, verify_hashes, cb)
+                    .await
+                    .err()
+                    .map(|_| name)
+            }));
+        }
+
+        let mut corrupted = Vec::new();
+        for task in tasks {
+            if let Some(name) = task.await? {
+                corrupted.push(name);
+            }
+        }
+        info!(
+            "[{}] Remote validation complete; {} corrupted files",
+            self.uuid,
+            corrupted.len()
+        );
+        Ok(corrupted)
+    }
+
     /// Validates the compressed asset bundles against the metadata. Returns a list of corrupted bundles.
     /// If `download_failed_bundles` is true, corrupted bundles will be re-downloaded.
     /// If `stop_on_first_fail` is true, the function will return as soon as it encounters a corrupted bundle.
@@ -339,9 +2542,11 @@ impl Version {
         download_failed_bundles: bool,
         stop_on_first_fail: bool,
         callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+        fallback_dirs: &[String],
     ) -> Result<Vec<String>, Error> {
         info!(
-            "Validating compressed asset bundles for {} ({})...",
+            "[{}] Validating compressed asset bundles in {}...",
             self.uuid, path
         );
 
@@ -349,34 +2554,59 @@ impl Version {
             |name: &str| -> String { PathBuf::from(path).join(name).to_str().unwrap().to_string() };
         let mut corrupted_bundles = Vec::with_capacity(self.bundles.len() + 1);
 
+        let mut main_task = None;
         if let Some(main_file_info) = self.main_file_info.clone() {
-            info!("Checking main file");
+            info!("[{}] Checking main file", self.uuid);
+            // the main file is often the largest in the build. it goes through the same path as
+            // the bundles, so if it's re-downloaded, it's hashed as it streams in instead of being re-read
             let main_bundle_info: BundleInfo = main_file_info.into();
-            let main_file_path = get_path("main.unity3d");
+            let main_file_name = self.get_main_file_name();
+            let main_file_path = get_path(main_file_name);
             let main_file_url = match download_failed_bundles {
                 false => None,
-                true => Some(format!("{}/main.unity3d", self.get_asset_url())),
+                true => Some(format!("{}/{}", self.get_asset_url(), main_file_name)),
             };
-            if main_bundle_info
-                .validate_compressed(
-                    &main_file_path,
-                    Some(self.uuid),
-                    main_file_url.as_deref(),
-                    callback.clone(),
-                )
-                .await
-                .is_err()
-            {
+            let uuid = self.uuid;
+            let cb = callback.clone();
+            let alongside = options.is_main_file_alongside_bundles();
+            let main_options = match alongside {
+                false => options.clone(),
+                true => options
+                    .clone()
+                    .with_concurrency(options.get_concurrency().clone().with_max_downloads(1)),
+            };
+            let fallback_dirs = fallback_dirs.to_vec();
+            let task = tokio::spawn(async move {
+                // alongside the bundles, the main file is just another item
+                let _permit = match alongside {
+                    false => None,
+                    true => main_options.acquire_item().await,
+                };
+                main_bundle_info
+                    .validate_compressed_internal(
+                        &main_file_path,
+                        Some(uuid),
+                        main_file_url.as_deref(),
+                        cb,
+                        &main_options,
+                        &fallback_dirs,
+                    )
+                    .await
+                    .is_err()
+            });
+            if alongside {
+                main_task = Some(task);
+            } else if task.await? {
                 if stop_on_first_fail {
-                    info!("Main file corrupted");
-                    return Ok(vec!["main.unity3d".to_string()]);
+                    info!("[{}] Main file corrupted", self.uuid);
+                    return Ok(vec![main_file_name.to_string()]);
                 } else {
-                    corrupted_bundles.push("main.unity3d".to_string());
+                    corrupted_bundles.push(main_file_name.to_string());
                 }
             }
         }
 
-        info!("Checking asset bundles");
+        info!("[{}] Checking asset bundles", self.uuid);
         let bundles = self.bundles.clone();
         let repair_count = Arc::new(AtomicU64::new(0));
         let corrupted = Arc::new(Mutex::new(Vec::new()));
@@ -388,54 +2618,84 @@ impl Version {
             let corrupted = Arc::clone(&corrupted);
             let url = match download_failed_bundles {
                 false => None,
-                true => Some(format!("{}/{}", self.get_asset_url(), bundle_name)),
+                true => Some(self.get_bundle_url(&bundle_name)),
             };
             let uuid = self.uuid;
+            let options = options.clone();
+            let fallback_dirs = fallback_dirs.to_vec();
             tasks.push(tokio::spawn(async move {
-                let _permit = if let Some(permits) = crate::ITEM_PERMITS.get() {
-                    Some(permits.acquire().await.unwrap())
-                } else {
-                    None
-                };
+                let _permit = options.acquire_item().await;
 
                 match bundle_info
-                    .validate_compressed(&file_path, Some(uuid), url.as_deref(), cb)
+                    .validate_compressed_internal(
+                        &file_path,
+                        Some(uuid),
+                        url.as_deref(),
+                        cb,
+                        &options,
+                        &fallback_dirs,
+                    )
                     .await
                 {
                     Ok(true) => {
-                        info!("{} repaired", bundle_name);
+                        info!("[{}] {} repaired", uuid, bundle_name);
                         corrupted.lock().unwrap().push(bundle_name);
                         repair_count.fetch_add(1, Ordering::SeqCst);
                     }
                     Ok(false) => {
-                        debug!("{} validated", bundle_name);
+                        debug!("[{}] {} validated", uuid, bundle_name);
                     }
                     Err(e) => {
-                        warn!("{} failed validation: {}", bundle_name, e);
+                        warn!("[{}] {} failed validation: {}", uuid, bundle_name, e);
                         corrupted.lock().unwrap().push(bundle_name);
                     }
                 }
             }));
         }
 
-        for task in tasks {
-            task.await?;
-            if stop_on_first_fail {
-                let corrupted = corrupted.lock().unwrap();
-                if let Some(bundle) = corrupted.first() {
-                    info!(
-                        "Validation complete; at least {} corrupted bundles",
-                        corrupted.len()
-                    );
-                    return Ok(vec![bundle.clone()]);
+        let mut tasks = tasks.into_iter();
+        while let Some(task) = tasks.next() {
+            let result = task.await;
+            let first_corrupted = match stop_on_first_fail {
+                false => None,
+                true => corrupted.lock().unwrap().first().cloned(),
+            };
+            if result.is_err() || first_corrupted.is_some() {
+                // nothing is left to wait for the other items, so don't leave them running
+                if let Some(main_task) = &main_task {
+                    main_task.abort();
+                }
+                tasks.by_ref().for_each(|task| task.abort());
+            }
+            result?;
+            if let Some(bundle) = first_corrupted {
+                save_hash_cache(options.get_hash_cache());
+                info!(
+                    "[{}] Validation complete; at least {} corrupted bundles",
+                    self.uuid,
+                    corrupted.lock().unwrap().len()
+                );
+                return Ok(vec![bundle]);
+            }
+        }
+
+        if let Some(task) = main_task {
+            if task.await? {
+                let main_file_name = self.get_main_file_name().to_string();
+                if stop_on_first_fail {
+                    info!("[{}] Main file corrupted", self.uuid);
+                    return Ok(vec![main_file_name]);
                 }
+                corrupted_bundles.push(main_file_name);
             }
         }
 
         let repair_count = repair_count.load(Ordering::SeqCst);
         corrupted_bundles.extend(Arc::try_unwrap(corrupted).unwrap().into_inner().unwrap());
+        save_hash_cache(options.get_hash_cache());
         info!(
-            "Validation complete; {}/{} missing or corrupted bundles repaired",
+            "[{}] Validation complete; {}/{} missing or corrupted bundles repaired",
+            self.uuid,
             repair_count,
             corrupted_bundles.len()
         );
@@ -448,10 +2708,86 @@ impl Version {
         path: &str,
         callback: Option<ProgressCallback>,
     ) -> Result<Vec<String>, Error> {
-        self.validate_uncompressed_internal(path, false, callback)
+        self.validate_uncompressed_with_options(path, callback, &OperationOptions::default())
             .await
     }
 
+    /// Same as `validate_uncompressed`, but with settings that override the global ones.
+    pub async fn validate_uncompressed_with_options(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<Vec<String>, Error> {
+        info!(
+            "[{}] Validating uncompressed asset bundles in {}...",
+            self.uuid, path
+        );
+        self.validate_uncompressed_internal(
+            Self::default_path_resolver(path),
+            false,
+            false,
+            callback,
+            options,
+        )
+        .await
+    }
+
+    /// Same as `validate_uncompressed`, but finds each bundle's extracted directory with `resolver`,
+    /// for builds that weren't extracted to `<path>/<url-encoded bundle name>`.
+    pub async fn validate_uncompressed_with_resolver(
+        &self,
+        resolver: BundlePathResolver,
+        callback: Option<ProgressCallback>,
+    ) -> Result<Vec<String>, Error> {
+        info!(
+            "[{}] Validating uncompressed asset bundles with a custom layout...",
+            self.uuid
+        );
+        self.validate_uncompressed_internal(
+            resolver,
+            false,
+            false,
+            callback,
+            &OperationOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as `validate_uncompressed_with_resolver`, but also reports files in a bundle's directory
+    /// that aren't in the manifest, e.g. leftovers from an interrupted extraction.
+    /// They fail with `FailReason::Unexpected`.
+    pub async fn validate_uncompressed_reporting_unexpected(
+        &self,
+        resolver: BundlePathResolver,
+        callback: Option<ProgressCallback>,
+    ) -> Result<Vec<String>, Error> {
+        info!(
+            "[{}] Validating uncompressed asset bundles and checking for unexpected files...",
+            self.uuid
+        );
+        self.validate_uncompressed_internal(
+            resolver,
+            false,
+            true,
+            callback,
+            &OperationOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as `validate_uncompressed`, but also sends each corrupted file and the reason it failed
+    /// to `failures` as soon as it's found, in the same way as `validate_compressed_streaming`.
+    pub async fn validate_uncompressed_streaming(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        failures: mpsc::UnboundedSender<(String, FailReason)>,
+    ) -> Result<Vec<String>, Error> {
+        let callback = forward_failures(callback, failures);
+        self.validate_uncompressed(path, Some(callback)).await
+    }
+
     /// Validates the uncompressed asset bundles against the metadata. Stops on the first failure.
     /// Returns the name of the first corrupted file.
     pub async fn validate_uncompressed_stop_on_first_fail(
@@ -459,49 +2795,75 @@ impl Version {
         path: &str,
         callback: Option<ProgressCallback>,
     ) -> Result<Option<String>, Error> {
+        info!(
+            "[{}] Validating uncompressed asset bundles in {}...",
+            self.uuid, path
+        );
         let corrupted = self
-            .validate_uncompressed_internal(path, true, callback)
+            .validate_uncompressed_internal(
+                Self::default_path_resolver(path),
+                true,
+                false,
+                callback,
+                &OperationOptions::default(),
+            )
             .await?;
         Ok(corrupted.first().cloned())
     }
 
     /// Validates the uncompressed asset bundles against the metadata. Returns a list of corrupted files.
     /// If `stop_on_first_fail` is true, the function will return as soon as it encounters a corrupted file.
+    /// If `report_unexpected` is true, files that aren't in the manifest are reported too.
     async fn validate_uncompressed_internal(
         &self,
-        path: &str,
+        resolver: BundlePathResolver,
         stop_on_first_fail: bool,
+        report_unexpected: bool,
         callback: Option<ProgressCallback>,
+        options: &OperationOptions,
     ) -> Result<Vec<String>, Error> {
-        info!(
-            "Validating uncompressed asset bundles for {} ({})...",
-            self.uuid, path
-        );
         let bundles = self.bundles.clone();
         let corrupted = Arc::new(Mutex::new(Vec::new()));
         let mut tasks = Vec::with_capacity(bundles.len());
         for (bundle_name, bundle_info) in bundles {
             let cb = callback.clone();
             let corrupted = Arc::clone(&corrupted);
-            let bundle_name_url_encoded = util::url_encode(&bundle_name);
-            let folder_path = PathBuf::from(path).join(&bundle_name_url_encoded);
+            let folder_path = resolver(&bundle_name);
             let uuid = self.uuid;
+            let options = options.clone();
             tasks.push(tokio::spawn(async move {
-                let _permit = if let Some(permits) = crate::ITEM_PERMITS.get() {
-                    Some(permits.acquire().await.unwrap())
-                } else {
-                    None
+                let _permit = options.acquire_item().await;
+
+                let Some(folder_path) = folder_path.to_str() else {
+                    warn!(
+                        "[{}] {} failed validation: {} isn't a valid UTF-8 path",
+                        uuid,
+                        bundle_name,
+                        folder_path.display()
+                    );
+                    corrupted.lock().unwrap().push(bundle_name);
+                    return;
                 };
-
-                match bundle_info.validate_uncompressed(
-                    folder_path.to_str().unwrap(),
-                    Some(uuid),
-                    cb,
-                ) {
+                // hashing may be paced by `set_validation_io_rate`, which blocks the thread
+                let folder_path = folder_path.to_string();
+                let result = tokio::task::spawn_blocking(move || {
+                    bundle_info
+                        .validate_uncompressed_internal(
+                            &folder_path,
+                            Some(uuid),
+                            cb,
+                            report_unexpected,
+                            &options,
+                        )
+                        .map_err(|e| e.to_string())
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+                match result {
                     Ok(corrupted_files) => {
                         if !corrupted_files.is_empty() {
                             for (file_name, e) in &corrupted_files {
-                                warn!("{} failed validation: {}", file_name, e);
+                                warn!("[{}] {} failed validation: {}", uuid, file_name, e);
                             }
                             corrupted.lock().unwrap().extend(
                                 corrupted_files.into_iter().map(|(file_name, _)| file_name),
@@ -509,7 +2871,7 @@ impl Version {
                         }
                     }
                     Err(e) => {
-                        warn!("{} failed validation: {}", bundle_name, e);
+                        warn!("[{}] {} failed validation: {}", uuid, bundle_name, e);
                         corrupted.lock().unwrap().push(bundle_name);
                     }
                 }
@@ -521,8 +2883,10 @@ impl Version {
             if stop_on_first_fail {
                 let corrupted = corrupted.lock().unwrap();
                 if let Some(file) = corrupted.first() {
+                    save_hash_cache(options.get_hash_cache());
                     info!(
-                        "Validation complete; at least {} corrupted files",
+                        "[{}] Validation complete; at least {} corrupted files",
+                        self.uuid,
                         corrupted.len()
                     );
                     return Ok(vec![file.clone()]);
@@ -531,21 +2895,57 @@ impl Version {
         }
 
         let corrupted = Arc::try_unwrap(corrupted).unwrap().into_inner().unwrap();
-        info!("Validation complete; {} corrupted files", corrupted.len());
+        save_hash_cache(options.get_hash_cache());
+        info!(
+            "[{}] Validation complete; {} corrupted files",
+            self.uuid,
+            corrupted.len()
+        );
         Ok(corrupted)
     }
 
+    /// Bundles are extracted to `<path>/<url-encoded bundle name>` by default.
+    fn default_path_resolver(path: &str) -> BundlePathResolver {
+        let path = PathBuf::from(path);
+        Arc::new(move |bundle_name: &str| path.join(util::url_encode(bundle_name)))
+    }
+
     /// Downloads all compressed asset bundles and the main file for this build to the specified path.
     pub async fn download_compressed(
         &self,
         path: &str,
         callback: Option<ProgressCallback>,
     ) -> Result<(), Error> {
-        info!("Downloading build {} to {}", self.uuid, path,);
+        self.download_compressed_with_options(path, callback, &OperationOptions::default())
+            .await
+    }
+
+    /// Same as `download_compressed`, but with settings that override the global ones.
+    pub async fn download_compressed_with_options(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<(), Error> {
+        info!("[{}] Downloading build to {}", self.uuid, path);
         std::fs::remove_dir_all(path)?;
         std::fs::create_dir_all(path)?;
+        self.repair_with_options(path, callback, options).await?;
+        info!("[{}] Download complete", self.uuid);
+        Ok(())
+    }
+
+    /// Same as `download_compressed`, but keeps any valid files already at the specified path
+    /// and only downloads missing or corrupted ones, so an interrupted download can pick up where it left off.
+    pub async fn download_compressed_resume(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+    ) -> Result<(), Error> {
+        info!("[{}] Resuming download of build to {}", self.uuid, path);
+        std::fs::create_dir_all(path)?;
         self.repair(path, callback).await?;
-        info!("Download complete");
+        info!("[{}] Download complete", self.uuid);
         Ok(())
     }
 
@@ -554,86 +2954,315 @@ impl Version {
         &self,
         path: &str,
         callback: Option<ProgressCallback>,
+    ) -> Result<Vec<String>, Error> {
+        self.repair_with_options(path, callback, &OperationOptions::default())
+            .await
+    }
+
+    /// Same as `repair`, but with settings that override the global ones.
+    pub async fn repair_with_options(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<Vec<String>, Error> {
+        self.repair_internal(path, callback, options, &[]).await
+    }
+
+    /// Same as `repair`, but before downloading a corrupted file, checks each of the `fallback_dirs`
+    /// (e.g. other builds on disk) for a valid copy with the same name and copies it instead.
+    /// Files are only downloaded if none of the fallback directories have a match.
+    pub async fn repair_with_fallback(
+        &self,
+        path: &str,
+        fallback_dirs: &[String],
+        callback: Option<ProgressCallback>,
+    ) -> Result<Vec<String>, Error> {
+        self.repair_internal(path, callback, &OperationOptions::default(), fallback_dirs)
+            .await
+    }
+
+    async fn repair_internal(
+        &self,
+        path: &str,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+        fallback_dirs: &[String],
     ) -> Result<Vec<String>, Error> {
         if !std::fs::exists(path).unwrap_or(false) {
             return Err(format!("Path does not exist: {}", path).into());
         }
         let uuid = self.uuid;
-        info!("Repairing build {} at {}", uuid, path);
+        info!("[{}] Repairing build at {}", uuid, path);
         let corrupted = self
-            .validate_compressed_internal(path, true, false, callback)
+            .validate_compressed_internal(path, true, false, callback, options, fallback_dirs)
             .await?;
-        info!("Repair complete");
+        info!("[{}] Repair complete", uuid);
         Ok(corrupted)
     }
 }
 
+/// Outcome of checking a single local file against the metadata.
+struct CheckedItem {
+    name: String,
+    expected: FileInfo,
+    actual: FileInfo,
+    reason: Option<FailReason>,
+}
+
+/// Hashes of consecutive fixed-size chunks of a file.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BlockHashes {
+    block_size: u64,
+    hashes: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "BundleInfoRepr", into = "BundleInfoRepr")]
 pub struct BundleInfo {
     compressed_info: FileInfo,
+    compressed_blocks: Option<BlockHashes>,
     uncompressed_info: HashMap<String, FileInfo>,
+    pack_uncompressed_info: bool,
+    // set for bundles inherited from a parent build hosted elsewhere
+    asset_url: Option<String>,
 }
 impl From<FileInfo> for BundleInfo {
     fn from(compressed_info: FileInfo) -> Self {
         Self {
             compressed_info,
+            compressed_blocks: None,
             uncompressed_info: HashMap::new(),
+            pack_uncompressed_info: false,
+            asset_url: None,
         }
     }
 }
-impl BundleInfo {
-    async fn build(asset_root: &str, bundle_name: &str) -> Result<Self, Error> {
-        let file_path = format!("{}/{}", asset_root, bundle_name);
 
-        let compressed_info = FileInfo::build(&file_path).await?;
+/// On-disk representation of `BundleInfo`. The uncompressed info is stored either
+/// as a plain map or, if packing is enabled, as a deflated and base64-encoded JSON blob.
+#[derive(Serialize, Deserialize)]
+struct BundleInfoRepr {
+    compressed_info: FileInfo,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compressed_blocks: Option<BlockHashes>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    // sorted so the manifest is stable across exports
+    uncompressed_info: Option<BTreeMap<String, FileInfo>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uncompressed_info_packed: Option<String>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asset_url: Option<String>,
+}
+impl TryFrom<BundleInfoRepr> for BundleInfo {
+    type Error = Error;
+
+    fn try_from(repr: BundleInfoRepr) -> Result<Self, Self::Error> {
+        let (uncompressed_info, pack_uncompressed_info) = match repr.uncompressed_info_packed {
+            Some(packed) => {
+                let json = util::inflate_base64(&packed)?;
+                (serde_json::from_slice(&json)?, true)
+            }
+            None => (
+                repr.uncompressed_info
+                    .map(|info| info.into_iter().collect())
+                    .unwrap_or_default(),
+                false,
+            ),
+        };
+        Ok(Self {
+            compressed_info: repr.compressed_info,
+            compressed_blocks: repr.compressed_blocks,
+            uncompressed_info,
+            pack_uncompressed_info,
+            asset_url: repr.asset_url,
+        })
+    }
+}
+impl From<BundleInfo> for BundleInfoRepr {
+    fn from(info: BundleInfo) -> Self {
+        let packed = if info.pack_uncompressed_info {
+            // sort the entries so the blob is stable across exports
+            let sorted: BTreeMap<_, _> = info.uncompressed_info.iter().collect();
+            let packed = serde_json::to_vec(&sorted)
+                .map_err(Error::from)
+                .and_then(|json| util::deflate_base64(&json));
+            match packed {
+                Ok(packed) => Some(packed),
+                Err(e) => {
+                    warn!(
+                        "Couldn't pack uncompressed info, writing it unpacked instead: {}",
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        Self {
+            compressed_info: info.compressed_info,
+            compressed_blocks: info.compressed_blocks,
+            uncompressed_info: match packed {
+                Some(_) => None,
+                None => Some(info.uncompressed_info.into_iter().collect()),
+            },
+            uncompressed_info_packed: packed,
+            asset_url: info.asset_url,
+        }
+    }
+}
+impl BundleInfo {
+    fn build(
+        file_path: &str,
+        bundle_name: &str,
+        compressed_info: FileInfo,
+    ) -> Result<(Self, Vec<BuildWarning>), Error> {
         #[cfg(feature = "lzma")]
-        let uncompressed_info = {
-            let (_, bundle) = bundle::AssetBundle::from_file(&file_path)?;
+        let (uncompressed_info, warnings) = {
+            let (header, bundle) = bundle::AssetBundle::from_file(file_path)?;
+            let mut warnings = Vec::new();
+            let header_size = header.get_bundle_size() as u64;
+            if header_size != compressed_info.size {
+                warnings.push(BuildWarning::BundleSizeMismatch {
+                    bundle: bundle_name.to_string(),
+                    header_size,
+                    file_size: compressed_info.size,
+                });
+            }
             // ff assets are always in level 0
-            bundle.get_uncompressed_info(0)?
+            (bundle.get_uncompressed_info(0)?, warnings)
         };
 
         #[cfg(not(feature = "lzma"))]
-        let uncompressed_info = HashMap::new();
+        let (uncompressed_info, warnings) = {
+            let _ = (file_path, bundle_name);
+            (HashMap::new(), Vec::new())
+        };
 
-        Ok(Self {
+        let bundle_info = Self {
             compressed_info,
+            compressed_blocks: None,
             uncompressed_info,
-        })
+            pack_uncompressed_info: false,
+            asset_url: None,
+        };
+        Ok((bundle_info, warnings))
     }
 
-    fn get_uncompressed_size(&self) -> u64 {
+    /// Returns the size of the compressed asset bundle in bytes, i.e. its download size.
+    pub fn get_compressed_size(&self) -> u64 {
+        self.compressed_info.size
+    }
+
+    /// Returns the expected size and hash of the compressed asset bundle.
+    pub fn get_compressed_info(&self) -> &FileInfo {
+        &self.compressed_info
+    }
+
+    /// Returns the expected size and hash of a file in the asset bundle, if known.
+    pub fn get_uncompressed_file_info(&self, file_name: &str) -> Option<&FileInfo> {
+        self.uncompressed_info.get(file_name)
+    }
+
+    /// Returns the total size of the files in the asset bundle in bytes.
+    pub fn get_uncompressed_size(&self) -> u64 {
         self.uncompressed_info.values().map(|info| info.size).sum()
     }
 
+    /// Returns the uncompressed size of the asset bundle divided by its compressed size,
+    /// or `None` if either is unknown.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        get_compression_ratio(self.get_uncompressed_size(), self.compressed_info.size)
+    }
+
     /// Validates the compressed asset bundle against the metadata.
     /// If the file is valid, the function returns `Ok(false)`.
     /// If the file fails validation, it will be re-downloaded up to `MAX_DOWNLOAD_ATTEMPTS` times.
     /// If the file was successfully re-downloaded, the function returns `Ok(true)`.
     /// If the file is still corrupted after the maximum number of attempts, an error will be returned.
+    /// If the file is still invalid, whether because it couldn't be downloaded, there's no download URL
+    /// or the mirror's copy is the wrong size, the error is a `FileFailure` with the last reason it failed.
     pub async fn validate_compressed(
         &self,
         file_path: &str,
         version_uuid: Option<Uuid>,
         download_url: Option<&str>,
         callback: Option<ProgressCallback>,
+    ) -> Result<bool, Error> {
+        self.validate_compressed_with_options(
+            file_path,
+            version_uuid,
+            download_url,
+            callback,
+            &OperationOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as `validate_compressed`, but with a download limit that overrides the global one.
+    pub async fn validate_compressed_with_options(
+        &self,
+        file_path: &str,
+        version_uuid: Option<Uuid>,
+        download_url: Option<&str>,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+    ) -> Result<bool, Error> {
+        self.validate_compressed_internal(
+            file_path,
+            version_uuid,
+            download_url,
+            callback,
+            options,
+            &[],
+        )
+        .await
+    }
+
+    /// Before downloading, each of the `fallback_dirs` is checked for a valid copy
+    /// of the file under the same name, which is copied into place instead.
+    async fn validate_compressed_internal(
+        &self,
+        file_path: &str,
+        version_uuid: Option<Uuid>,
+        download_url: Option<&str>,
+        callback: Option<ProgressCallback>,
+        options: &OperationOptions,
+        fallback_dirs: &[String],
     ) -> Result<bool, Error> {
         const MAX_DOWNLOAD_ATTEMPTS: usize = 5;
+        let uuid = version_uuid.unwrap_or_default();
         let file_name = util::get_file_name_without_parent(file_path);
-        let mut file_info = FileInfo::build_file(file_path);
+        let start = Instant::now();
+        let hash_cache = options.get_hash_cache();
+        let pacer = options.get_validation_pacer();
+        let algorithm = HashAlgorithm::of(&self.compressed_info.hash);
+        let mut file_info = FileInfo::build_file_for_validation(
+            file_path,
+            algorithm,
+            hash_cache.clone(),
+            pacer.clone(),
+        )
+        .await;
         let mut attempts = 0;
         while let Err(fail_reason) = {
             if let Some(ref cb) = callback {
-                let uuid = version_uuid.unwrap_or_default();
                 cb(&uuid, file_name, ItemProgress::Validating);
             }
             file_info.validate(&self.compressed_info)
         } {
-            warn!("{} invalid", file_name);
+            warn!("[{}] {} invalid", uuid, file_name);
             let Some(url) = download_url else {
                 if let Some(ref cb) = callback {
-                    let uuid = version_uuid.unwrap_or_default();
                     cb(
                         &uuid,
                         file_name,
@@ -643,12 +3272,13 @@ impl BundleInfo {
                         },
                     );
                 }
-                return Err(fail_reason.clone().into());
+                return Err(FileFailure::new(file_path, fail_reason.clone()).into());
             };
+            // a failed attempt demotes its mirror, so retries can go elsewhere
+            let url = &options.select_download_url(url);
 
             if attempts >= MAX_DOWNLOAD_ATTEMPTS {
                 if let Some(ref cb) = callback {
-                    let uuid = version_uuid.unwrap_or_default();
                     cb(
                         &uuid,
                         file_name,
@@ -658,48 +3288,348 @@ impl BundleInfo {
                         },
                     );
                 }
-                return Err(format!(
-                    "Failed to download {} after {} attempts: {}",
-                    file_path, attempts, fail_reason
-                )
-                .into());
+                warn!(
+                    "[{}] Giving up on {} after {} attempts",
+                    uuid, file_name, attempts
+                );
+                return Err(FileFailure::new(file_path, fail_reason.clone()).into());
             }
 
-            if let Err(e) =
-                util::download_to_file(version_uuid, url, file_path, callback.clone()).await
-            {
-                warn!("Failed to download {}: {}", file_path, e);
-            } else {
-                file_info = FileInfo::build_file(file_path);
+            if attempts == 0 {
+                if let Some(store) = options.get_bundle_store() {
+                    let hash = &self.compressed_info.hash;
+                    // the error isn't Send, so it can't be alive across the await below
+                    let fetched = util::fetch_from_bundle_store(store, hash, file_path)
+                        .map_err(|e| e.to_string());
+                    match fetched {
+                        Ok(true) => {
+                            debug!("[{}] {} found in bundle store", uuid, file_name);
+                            file_info = FileInfo::build_file_for_validation(
+                                file_path,
+                                algorithm,
+                                hash_cache.clone(),
+                                pacer.clone(),
+                            )
+                            .await;
+                            attempts += 1;
+                            continue;
+                        }
+                        Ok(false) => {}
+                        Err(e) => warn!(
+                            "[{}] Couldn't fetch {} from bundle store: {}",
+                            uuid, file_name, e
+                        ),
+                    }
+                }
+
+                if let Some(fallback_info) = self
+                    .copy_from_fallback(uuid, file_path, fallback_dirs, &hash_cache, &pacer)
+                    .await
+                {
+                    file_info = fallback_info;
+                    attempts += 1;
+                    continue;
+                }
+            }
+
+            if attempts == 0 && options.is_mirror_size_check_enabled() {
+                match util::remote_file_size(url).await {
+                    Ok(Some(remote_size)) if remote_size != self.compressed_info.size => {
+                        let reason = FailReason::MirrorMismatch {
+                            url: url.to_string(),
+                            expected: self.compressed_info.size,
+                            actual: remote_size,
+                        };
+                        warn!("[{}] {}", uuid, reason);
+                        if let Some(ref cb) = callback {
+                            cb(
+                                &uuid,
+                                file_name,
+                                ItemProgress::Failed {
+                                    item_size: self.compressed_info.size,
+                                    reason: reason.clone(),
+                                },
+                            );
+                        }
+                        return Err(FileFailure::new(file_path, reason).into());
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("[{}] Couldn't check size of {}: {}", uuid, url, e),
+                }
             }
+
+            // the download error isn't Send, so it can't be alive while waiting to retry
+            let retry_after = {
+                let download = util::download_to_file_internal(
+                    version_uuid,
+                    url,
+                    file_path,
+                    callback.clone(),
+                    // the local file already failed validation, so a conditional request
+                    // could only tell us to keep a bad copy
+                    false,
+                    algorithm,
+                    options,
+                    (attempts + 1, MAX_DOWNLOAD_ATTEMPTS),
+                )
+                .await;
+                match download {
+                    // the hash is computed while downloading, so there's no need to re-read the file
+                    Ok(hash) => {
+                        let hash = hash.expect("only a conditional download can be skipped");
+                        let size = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+                        file_info = FileInfo { hash, size };
+                        None
+                    }
+                    Err(e) => {
+                        warn!("[{}] Failed to download {}: {}", uuid, file_path, e);
+                        e.downcast_ref::<util::RateLimited>()
+                            .map(|rate_limited| rate_limited.retry_after)
+                    }
+                }
+            };
             attempts += 1;
+
+            if let Some(delay) = retry_after.filter(|_| attempts < MAX_DOWNLOAD_ATTEMPTS) {
+                info!(
+                    "[{}] Retrying {} in {:.1}s",
+                    uuid,
+                    file_name,
+                    delay.as_secs_f64()
+                );
+                if let Some(ref cb) = callback {
+                    cb(&uuid, file_name, ItemProgress::Retrying { delay });
+                }
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        if let (Some(store), Some(_)) = (options.get_bundle_store(), download_url) {
+            // a bad store entry will have been replaced by a fresh download
+            let replace = attempts > 0;
+            if let Err(e) = util::add_to_bundle_store(store, file_path, &file_info.hash, replace) {
+                warn!(
+                    "[{}] Couldn't add {} to bundle store: {}",
+                    uuid, file_name, e
+                );
+            }
         }
 
         if let Some(ref cb) = callback {
-            let uuid = version_uuid.unwrap_or_default();
             cb(
                 &uuid,
                 file_name,
                 ItemProgress::Passed {
                     item_size: self.compressed_info.size,
+                    duration: start.elapsed(),
+                    was_downloaded: attempts > 0,
                 },
             );
         }
         Ok(attempts > 0)
     }
 
+    /// Copies the first valid file with the same name in `fallback_dirs` to `file_path`.
+    /// Returns the info of the copied file, if any.
+    async fn copy_from_fallback(
+        &self,
+        uuid: Uuid,
+        file_path: &str,
+        fallback_dirs: &[String],
+        hash_cache: &Option<Arc<util::HashCache>>,
+        pacer: &Option<Arc<util::IoPacer>>,
+    ) -> Option<FileInfo> {
+        let file_name = util::get_file_name_without_parent(file_path);
+        for fallback_dir in fallback_dirs {
+            let fallback_path = PathBuf::from(fallback_dir).join(file_name);
+            let fallback_path = fallback_path.to_string_lossy();
+            let fallback_info = FileInfo::build_file_for_validation(
+                &fallback_path,
+                HashAlgorithm::of(&self.compressed_info.hash),
+                hash_cache.clone(),
+                pacer.clone(),
+            )
+            .await;
+            if fallback_info.validate(&self.compressed_info).is_err() {
+                continue;
+            }
+
+            match util::copy_to_file(&fallback_path, file_path) {
+                Ok(()) => {
+                    info!("[{}] Copied {} from {}", uuid, file_name, fallback_dir);
+                    return Some(fallback_info);
+                }
+                Err(e) => warn!(
+                    "[{}] Couldn't copy {} from {}: {}",
+                    uuid, file_name, fallback_dir, e
+                ),
+            }
+        }
+        None
+    }
+
+    /// Compares the compressed asset bundle against the block hashes in the metadata.
+    /// Returns the byte ranges of the file that differ, with adjacent ranges merged.
+    /// Bytes past the expected end of the file are reported as a final range.
+    /// Requires block hashes to be present (see `Version::generate_block_hashes`).
+    pub fn validate_compressed_ranges(&self, file_path: &str) -> Result<Vec<Range<u64>>, Error> {
+        let Some(blocks) = &self.compressed_blocks else {
+            return Err("No block hashes present".into());
+        };
+
+        let expected_size = self.compressed_info.size;
+        let actual_size = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        let actual_hashes = if actual_size > 0 {
+            util::get_file_block_hashes(file_path, blocks.block_size)?
+        } else {
+            Vec::new()
+        };
+
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        let mut push_range = |range: Range<u64>| match ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => ranges.push(range),
+        };
+
+        for (idx, expected_hash) in blocks.hashes.iter().enumerate() {
+            if actual_hashes.get(idx) != Some(expected_hash) {
+                let start = idx as u64 * blocks.block_size;
+                let end = (start + blocks.block_size).min(expected_size);
+                push_range(start..end);
+            }
+        }
+        if actual_size > expected_size {
+            push_range(expected_size..actual_size);
+        }
+        Ok(ranges)
+    }
+
+    async fn validate_remote(
+        &self,
+        url: &str,
+        name: &str,
+        uuid: Uuid,
+        verify_hash: bool,
+        callback: Option<ProgressCallback>,
+    ) -> Result<(), FailReason> {
+        let start = Instant::now();
+        if let Some(ref cb) = callback {
+            cb(&uuid, name, ItemProgress::Validating);
+        }
+
+        let good = &self.compressed_info;
+        let result = match util::fetch_remote_file_info(
+            url,
+            verify_hash.then(|| HashAlgorithm::of(&good.hash)),
+            &OperationOptions::default(),
+        )
+        .await
+        {
+            // without a hash, only the size can be compared
+            Ok((size, hash)) => FileInfo {
+                hash: hash.unwrap_or(good.hash.clone()),
+                size,
+            }
+            .validate(good),
+            Err(e) => {
+                warn!("[{}] Couldn't fetch {}: {}", uuid, url, e);
+                Err(fetch_fail_reason(e.as_ref()))
+            }
+        };
+
+        let progress = match &result {
+            Ok(()) => ItemProgress::Passed {
+                item_size: good.size,
+                duration: start.elapsed(),
+                was_downloaded: false,
+            },
+            Err(reason) => {
+                warn!("[{}] {} invalid on remote: {}", uuid, name, reason);
+                ItemProgress::Failed {
+                    item_size: good.size,
+                    reason: reason.clone(),
+                }
+            }
+        };
+        if let Some(ref cb) = callback {
+            cb(&uuid, name, progress);
+        }
+        result
+    }
+
     pub fn validate_uncompressed(
         &self,
         folder_path: &str,
         version_uuid: Option<Uuid>,
         callback: Option<ProgressCallback>,
+    ) -> Result<Vec<(String, FailReason)>, Error> {
+        self.validate_uncompressed_internal(
+            folder_path,
+            version_uuid,
+            callback,
+            false,
+            &OperationOptions::default(),
+        )
+    }
+
+    /// Same as `validate_uncompressed`, but also reports files in the directory that aren't in the manifest,
+    /// e.g. leftovers from an interrupted extraction. They fail with `FailReason::Unexpected`.
+    pub fn validate_uncompressed_reporting_unexpected(
+        &self,
+        folder_path: &str,
+        version_uuid: Option<Uuid>,
+        callback: Option<ProgressCallback>,
+    ) -> Result<Vec<(String, FailReason)>, Error> {
+        self.validate_uncompressed_internal(
+            folder_path,
+            version_uuid,
+            callback,
+            true,
+            &OperationOptions::default(),
+        )
+    }
+
+    fn validate_uncompressed_internal(
+        &self,
+        folder_path: &str,
+        version_uuid: Option<Uuid>,
+        callback: Option<ProgressCallback>,
+        report_unexpected: bool,
+        options: &OperationOptions,
     ) -> Result<Vec<(String, FailReason)>, Error> {
         let uuid = version_uuid.unwrap_or_default();
         let folder_path_leaf = util::get_file_name_without_parent(folder_path);
+        // nothing to check against, so don't let the bundle pass by default
+        if self.uncompressed_info.is_empty() {
+            let reason = FailReason::NoUncompressedInfo;
+            warn!(
+                "[{}] {} can't be validated: {}",
+                uuid, folder_path_leaf, reason
+            );
+            if let Some(ref cb) = callback {
+                let progress = ItemProgress::Failed {
+                    item_size: 0,
+                    reason: reason.clone(),
+                };
+                cb(&uuid, folder_path_leaf, progress);
+            }
+            return Ok(vec![(folder_path_leaf.to_string(), reason)]);
+        }
+
+        let hash_cache = options.get_hash_cache();
+        let pacer = options.get_validation_pacer();
         let mut corrupted = Vec::new();
         for (file_name, file_info_good) in &self.uncompressed_info {
             let file_path = PathBuf::from(folder_path).join(file_name);
-            let file_info = FileInfo::build_file(file_path.to_str().unwrap());
+            let start = Instant::now();
+            // each file fails on its own, so one unreadable file doesn't stop the rest from being checked
+            let validation = FileInfo::try_build_file(
+                &file_path,
+                HashAlgorithm::of(&file_info_good.hash),
+                hash_cache.as_deref(),
+                pacer.as_deref(),
+            )
+            .and_then(|file_info| file_info.validate(file_info_good));
             let file_id = format!("{}/{}", folder_path_leaf, file_name);
 
             if let Some(ref cb) = callback {
@@ -708,9 +3638,11 @@ impl BundleInfo {
 
             let mut result = ItemProgress::Passed {
                 item_size: file_info_good.size,
+                duration: start.elapsed(),
+                was_downloaded: false,
             };
-            if let Err(fail_reason) = file_info.validate(file_info_good) {
-                warn!("{} invalid: {}", file_id, fail_reason);
+            if let Err(fail_reason) = validation {
+                warn!("[{}] {} invalid: {}", uuid, file_id, fail_reason);
                 corrupted.push((file_id.clone(), fail_reason.clone()));
                 result = ItemProgress::Failed {
                     item_size: file_info_good.size,
@@ -722,6 +3654,36 @@ impl BundleInfo {
                 cb(&uuid, &file_id, result);
             }
         }
+
+        if report_unexpected {
+            // a missing directory is already reported through its missing files
+            let mut unexpected: Vec<(String, u64)> = std::fs::read_dir(folder_path)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    (metadata.is_file() && !self.uncompressed_info.contains_key(&file_name))
+                        .then_some((file_name, metadata.len()))
+                })
+                .collect();
+            unexpected.sort();
+
+            for (file_name, size) in unexpected {
+                let file_id = format!("{}/{}", folder_path_leaf, file_name);
+                let reason = FailReason::Unexpected;
+                warn!("[{}] {} invalid: {}", uuid, file_id, reason);
+                if let Some(ref cb) = callback {
+                    let progress = ItemProgress::Failed {
+                        item_size: size,
+                        reason: reason.clone(),
+                    };
+                    cb(&uuid, &file_id, progress);
+                }
+                corrupted.push((file_id, reason));
+            }
+        }
         Ok(corrupted)
     }
 }
@@ -732,35 +3694,198 @@ pub struct FileInfo {
     size: u64,
 }
 impl FileInfo {
-    async fn build(uri: &str) -> Result<Self, Error> {
-        if uri.starts_with("http") {
-            Self::build_http(uri).await
+    /// Returns the SHA-256 hash of the file as a lowercase hex string.
+    pub fn get_hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Validates the file at `file_path` against this info. If the hash doesn't match and `reference`
+    /// (a local path, `file://` URI or HTTP URL of a known-good copy) is given, both files are streamed
+    /// to find the first byte that differs, which is reported in `FailReason::BadHash`. This tells a
+    /// truncated or partially written file apart from corrupted content, but reads the whole reference.
+    pub async fn validate_verbose(
+        &self,
+        file_path: &str,
+        reference: Option<&str>,
+    ) -> Result<(), FailReason> {
+        let path = PathBuf::from(file_path);
+        let options = OperationOptions::default();
+        let hash_cache = options.get_hash_cache();
+        let pacer = options.get_validation_pacer();
+        let algorithm = HashAlgorithm::of(&self.hash);
+        let actual = tokio::task::spawn_blocking(move || {
+            Self::try_build_file(&path, algorithm, hash_cache.as_deref(), pacer.as_deref())
+        })
+        .await
+        .unwrap_or_else(|e| {
+            Err(FailReason::Unreadable {
+                error: e.to_string(),
+            })
+        })?;
+        let (expected, actual) = match actual.validate(self) {
+            Err(FailReason::BadHash {
+                expected, actual, ..
+            }) => (expected, actual),
+            result => return result,
+        };
+        let Some(reference) = reference else {
+            return Err(FailReason::BadHash {
+                expected,
+                actual,
+                first_mismatch: None,
+            });
+        };
+
+        let first_mismatch = match util::find_first_mismatch(file_path, reference, algorithm).await
+        {
+            Ok((first_mismatch, reference_hash)) if reference_hash == expected => first_mismatch,
+            Ok(_) => {
+                warn!("Reference {} doesn't match the expected hash", reference);
+                None
+            }
+            Err(e) => {
+                warn!("Couldn't compare {} with {}: {}", file_path, reference, e);
+                None
+            }
+        };
+        Err(FailReason::BadHash {
+            expected,
+            actual,
+            first_mismatch,
+        })
+    }
+
+    async fn build(uri: &str, options: &OperationOptions) -> Result<Self, Error> {
+        if let Some(path) = util::file_uri_to_path(uri) {
+            Ok(Self::build_file(&path, options))
+        } else if uri.starts_with("http") {
+            Self::build_http(uri, options).await
         } else {
-            Ok(Self::build_file(uri))
+            Ok(Self::build_file(uri, options))
         }
     }
 
-    async fn build_http(url: &str) -> Result<Self, Error> {
+    async fn build_http(url: &str, options: &OperationOptions) -> Result<Self, Error> {
         info!("Fetching {}", url);
-        let temp_file = TempFile::download(url).await?;
-        Ok(Self::build_file(temp_file.path()))
+        let temp_file =
+            TempFile::download_with_options(url, &util::get_temp_dir(), options).await?;
+        // temp files are never hashed twice, so skip the cache
+        let hash =
+            util::get_file_hash_with_algorithm(temp_file.path(), options.get_hash_algorithm())?;
+        let size = std::fs::metadata(temp_file.path())?.len();
+        Ok(Self { hash, size })
+    }
+
+    fn build_file(file_path: &str, options: &OperationOptions) -> Self {
+        Self::build_file_paced(
+            file_path,
+            options.get_hash_algorithm(),
+            options.get_hash_cache().as_deref(),
+            None,
+        )
+    }
+
+    /// Same as `build_file` for validation, hashing on a blocking thread so pacing by `pacer` doesn't stall the runtime.
+    async fn build_file_for_validation(
+        file_path: &str,
+        algorithm: HashAlgorithm,
+        hash_cache: Option<Arc<util::HashCache>>,
+        pacer: Option<Arc<util::IoPacer>>,
+    ) -> Self {
+        let file_path = file_path.to_string();
+        tokio::task::spawn_blocking(move || {
+            Self::build_file_paced(
+                &file_path,
+                algorithm,
+                hash_cache.as_deref(),
+                pacer.as_deref(),
+            )
+        })
+        .await
+        .unwrap_or_default()
     }
 
-    fn build_file(file_path: &str) -> Self {
+    fn build_file_paced(
+        file_path: &str,
+        algorithm: HashAlgorithm,
+        hash_cache: Option<&util::HashCache>,
+        pacer: Option<&util::IoPacer>,
+    ) -> Self {
         let build_file_internal = || -> Result<Self, Error> {
-            let hash = util::get_file_hash(file_path)?;
+            let hash = util::get_file_hash_with_cache(file_path, algorithm, hash_cache, pacer)?;
             let size = std::fs::metadata(file_path)?.len();
             Ok(Self { hash, size })
         };
-        // if we can't access the file, assume it's corrupt
+        // if we can't access the file, assume it's missing. the default has no hash,
+        // which tells it apart from a file that's really empty
         build_file_internal().unwrap_or_default()
     }
 
+    /// Like `build_file_paced`, but reports why the file couldn't be read instead of treating it as missing.
+    /// Reads are kept under the rate of `pacer`, if any, which blocks the thread.
+    fn try_build_file(
+        file_path: &std::path::Path,
+        algorithm: HashAlgorithm,
+        hash_cache: Option<&util::HashCache>,
+        pacer: Option<&util::IoPacer>,
+    ) -> Result<Self, FailReason> {
+        let Some(path) = file_path.to_str() else {
+            return Err(FailReason::Unreadable {
+                error: format!("{} isn't a valid UTF-8 path", file_path.display()),
+            });
+        };
+        let size = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(FailReason::Missing),
+            Err(e) => {
+                return Err(FailReason::Unreadable {
+                    error: e.to_string(),
+                })
+            }
+        };
+        let hash =
+            util::get_file_hash_with_cache(path, algorithm, hash_cache, pacer).map_err(|e| {
+                FailReason::Unreadable {
+                    error: e.to_string(),
+                }
+            })?;
+        Ok(Self { hash, size })
+    }
+
+    /// Same as `validate` for info built from `uri`, but if `good` was hashed with another algorithm,
+    /// builds the info again with that one first. Returns the info that was compared.
+    async fn validate_rehashed(
+        self,
+        uri: &str,
+        good: &Self,
+        options: &OperationOptions,
+    ) -> (Self, Result<(), FailReason>) {
+        let algorithm = HashAlgorithm::of(&good.hash);
+        let actual = if self.hash.is_empty() || HashAlgorithm::of(&self.hash) == algorithm {
+            self
+        } else {
+            let options = options.clone().with_hash_algorithm(algorithm);
+            Self::build(uri, &options).await.unwrap_or_default()
+        };
+        let result = actual.validate(good);
+        (actual, result)
+    }
+
     fn validate(&self, good: &Self) -> Result<(), FailReason> {
-        if self.size == 0 {
+        // only files that couldn't be read have no hash
+        if self.hash.is_empty() {
             return Err(FailReason::Missing);
         }
 
+        if self.size == 0 && good.size != 0 {
+            return Err(FailReason::Empty);
+        }
+
         if self.size != good.size {
             return Err(FailReason::BadSize {
                 expected: good.size,
@@ -772,6 +3897,7 @@ impl FileInfo {
             return Err(FailReason::BadHash {
                 expected: good.hash.clone(),
                 actual: self.hash.clone(),
+                first_mismatch: None,
             });
         }
 
@@ -779,16 +3905,77 @@ impl FileInfo {
     }
 }
 
-fn get_bundle_names_from_asset_root(asset_root: &str) -> Result<Vec<String>, Error> {
+/// Tells a file that isn't on the server apart from one that couldn't be fetched at all.
+fn fetch_fail_reason(error: &(dyn std::error::Error + 'static)) -> FailReason {
+    let not_found = match error.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.status() == Some(reqwest::StatusCode::NOT_FOUND),
+        None => error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound),
+    };
+    if not_found {
+        FailReason::Missing
+    } else {
+        FailReason::Unreachable {
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Wraps `callback` so that every failed item is also sent to `failures`.
+fn forward_failures(
+    callback: Option<ProgressCallback>,
+    failures: mpsc::UnboundedSender<(String, FailReason)>,
+) -> ProgressCallback {
+    Arc::new(move |uuid, name, progress| {
+        if let ItemProgress::Failed { reason, .. } = &progress {
+            // the receiver may have stopped listening, which doesn't stop the validation
+            let _ = failures.send((name.to_string(), reason.clone()));
+        }
+        if let Some(ref cb) = callback {
+            cb(uuid, name, progress);
+        }
+    })
+}
+
+/// Makes sure the asset URL parses and uses a scheme we can download from.
+fn check_asset_url(asset_url: &str) -> Result<(), Error> {
+    let url = reqwest::Url::parse(asset_url)
+        .map_err(|e| format!("Invalid asset URL {}: {}", asset_url, e))?;
+    match url.scheme() {
+        "http" | "https" | "file" => Ok(()),
+        scheme => Err(format!("Unsupported scheme {} in asset URL {}", scheme, asset_url).into()),
+    }
+}
+
+fn get_compression_ratio(uncompressed_size: u64, compressed_size: u64) -> Option<f64> {
+    if uncompressed_size == 0 || compressed_size == 0 {
+        return None;
+    }
+    Some(uncompressed_size as f64 / compressed_size as f64)
+}
+
+/// Lists the asset bundles in `asset_root`, i.e. the files with a bundle extension other than the main file.
+/// `extra_extensions` are used instead of the ones from `set_extra_bundle_extensions` if given.
+pub fn get_bundle_names_from_asset_root(
+    asset_root: &str,
+    main_file_name: &str,
+    extra_extensions: Option<&[String]>,
+) -> Result<Vec<String>, Error> {
+    let extra_extensions =
+        extra_extensions.unwrap_or_else(|| EXTRA_BUNDLE_EXTENSIONS.get().map_or(&[][..], |e| e));
     let filtered = util::list_filenames_in_directory(asset_root)?
         .iter()
         .filter_map(|filename| {
-            if filename.eq_ignore_ascii_case("main.unity3d") {
+            if filename.eq_ignore_ascii_case(main_file_name) {
                 None
             } else {
                 let extension = util::get_file_extension(filename)?;
                 if extension.eq_ignore_ascii_case("unity3d")
                     || extension.eq_ignore_ascii_case("resourceFile")
+                    || extra_extensions
+                        .iter()
+                        .any(|ext| extension.eq_ignore_ascii_case(ext))
                 {
                     Some(filename.to_string())
                 } else {
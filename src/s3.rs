@@ -0,0 +1,201 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use aws_sdk_s3::{
+    config::{Credentials, Region},
+    primitives::ByteStream,
+    Client,
+};
+use log::*;
+use md5::{Digest, Md5};
+use uuid::Uuid;
+
+use crate::{util, Error, Version};
+
+/// Destination for an uploaded build in S3-compatible storage.
+/// Credentials are read from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+/// and (optionally) `AWS_SESSION_TOKEN`.
+#[derive(Debug, Clone)]
+pub struct S3Target {
+    pub bucket: String,
+    /// Custom endpoint for non-AWS providers, e.g. `http://localhost:9000`.
+    pub endpoint: Option<String>,
+    pub region: String,
+    /// Key prefix that all objects are uploaded under.
+    pub prefix: String,
+}
+impl S3Target {
+    fn get_client(&self) -> Result<Client, Error> {
+        let access_key = std::env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| "AWS_ACCESS_KEY_ID is not set".to_string())?;
+        let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+            .map_err(|_| "AWS_SECRET_ACCESS_KEY is not set".to_string())?;
+        let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+        let credentials = Credentials::new(access_key, secret_key, session_token, None, "env");
+
+        let mut config = aws_sdk_s3::Config::builder()
+            .credentials_provider(credentials)
+            .region(Region::new(self.region.clone()));
+        if let Some(endpoint) = &self.endpoint {
+            // most S3-compatible providers don't support virtual-hosted buckets
+            config = config.endpoint_url(endpoint).force_path_style(true);
+        }
+        Ok(Client::from_conf(config.build()))
+    }
+
+    fn get_key(&self, name: &str) -> String {
+        let prefix = self.prefix.trim_matches('/');
+        if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", prefix, name)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UploadSummary {
+    pub uploaded: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Uploads the file unless an object with a matching ETag already exists.
+/// Returns whether the file was uploaded.
+async fn upload_file(
+    uuid: Uuid,
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    file_path: &Path,
+) -> Result<bool, Error> {
+    // checking whether the object changed reads the whole file, so it counts against the limit too
+    let _permit = crate::ConcurrencyConfig::default().acquire_download().await;
+
+    let md5_path = file_path.to_path_buf();
+    let local_etag = tokio::task::spawn_blocking(move || get_file_md5(&md5_path)).await??;
+    let local_etag = format!("\"{}\"", local_etag);
+    if let Ok(head) = client.head_object().bucket(bucket).key(key).send().await {
+        if head.e_tag() == Some(local_etag.as_str()) {
+            debug!("[{}] {} unchanged; skipping", uuid, key);
+            return Ok(false);
+        }
+    }
+
+    info!(
+        "[{}] Uploading {} to {}/{}",
+        uuid,
+        file_path.display(),
+        bucket,
+        key
+    );
+    let body = ByteStream::from_path(file_path).await?;
+    client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .body(body)
+        .send()
+        .await?;
+    Ok(true)
+}
+
+/// Single-part S3 uploads use the MD5 of the object as the ETag.
+fn get_file_md5(file_path: &Path) -> std::io::Result<String> {
+    let file = std::fs::File::open(file_path)?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut hasher = Md5::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl Version {
+    /// Uploads the main file and all compressed asset bundles in the local build root,
+    /// followed by the manifest at `manifest_path`, to S3-compatible storage.
+    /// Objects whose ETag already matches the local file are skipped.
+    /// Upload parallelism is limited by `set_max_concurrent_downloads`.
+    pub async fn upload_to_s3(
+        &self,
+        path: &str,
+        manifest_path: &str,
+        target: &S3Target,
+    ) -> Result<UploadSummary, Error> {
+        info!(
+            "[{}] Uploading build in {} to bucket {}",
+            self.uuid, path, target.bucket
+        );
+        let client = target.get_client()?;
+
+        let mut names: Vec<String> = self.bundles.keys().cloned().collect();
+        if self.main_file_info.is_some() {
+            names.push(self.get_main_file_name().to_string());
+        }
+
+        let summary = Arc::new(Mutex::new(UploadSummary::default()));
+        let mut tasks = Vec::with_capacity(names.len());
+        for name in names {
+            let client = client.clone();
+            let bucket = target.bucket.clone();
+            let key = target.get_key(&name);
+            let file_path = PathBuf::from(path).join(&name);
+            let summary = Arc::clone(&summary);
+            let uuid = self.uuid;
+            tasks.push(tokio::spawn(async move {
+                let uploaded = upload_file(uuid, &client, &bucket, &key, &file_path)
+                    .await
+                    .map_err(|e| format!("Couldn't upload {}: {}", name, e))?;
+                let mut summary = summary.lock().unwrap();
+                if uploaded {
+                    summary.uploaded.push(name);
+                } else {
+                    summary.skipped.push(name);
+                }
+                Ok::<(), String>(())
+            }));
+        }
+
+        // let every upload finish before reporting, so none are left running in the background
+        let mut errors = Vec::new();
+        for task in tasks {
+            match task.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => errors.push(e),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(format!(
+                "{} files failed to upload: {}",
+                errors.len(),
+                errors.join("; ")
+            )
+            .into());
+        }
+
+        // upload the manifest last so clients never see it before the assets are in place
+        let manifest_name = util::get_file_name_without_parent(manifest_path).to_string();
+        let uploaded = upload_file(
+            self.uuid,
+            &client,
+            &target.bucket,
+            &target.get_key(&manifest_name),
+            Path::new(manifest_path),
+        )
+        .await?;
+
+        let mut summary = Arc::try_unwrap(summary).unwrap().into_inner().unwrap();
+        if uploaded {
+            summary.uploaded.push(manifest_name);
+        } else {
+            summary.skipped.push(manifest_name);
+        }
+        info!(
+            "[{}] Upload complete; {} uploaded, {} unchanged",
+            self.uuid,
+            summary.uploaded.len(),
+            summary.skipped.len()
+        );
+        Ok(summary)
+    }
+}
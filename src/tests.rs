@@ -2,6 +2,68 @@ use uuid::Uuid;
 
 use crate::{util::TempDir, Version};
 
+/// A raw HTTP response for `spawn_test_server`.
+struct TestResponse {
+    data: Vec<u8>,
+    /// Keeps the connection open for a while after sending `data` instead of closing it.
+    stall: bool,
+}
+impl TestResponse {
+    /// Builds a response with `headers` and `body`. `Content-Length` is added unless it's in `headers`.
+    fn new(status: &str, headers: &[&str], body: &[u8]) -> Self {
+        let mut data = format!("HTTP/1.1 {}\r\n", status);
+        for header in headers {
+            data.push_str(&format!("{}\r\n", header));
+        }
+        if !headers.iter().any(|h| h.starts_with("Content-Length:")) {
+            data.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        data.push_str("Connection: close\r\n\r\n");
+        let mut data = data.into_bytes();
+        data.extend_from_slice(body);
+        Self { data, stall: false }
+    }
+
+    fn ok(body: &[u8]) -> Self {
+        Self::new("200 OK", &[], body)
+    }
+
+    fn stalled(self) -> Self {
+        Self {
+            stall: true,
+            ..self
+        }
+    }
+}
+
+/// Serves every request on a local port with the response `handler` builds from the request text.
+/// Returns the server's base URL.
+async fn spawn_test_server<F>(handler: F) -> String
+where
+    F: Fn(&str) -> TestResponse + Send + 'static,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let read = stream.read(&mut buf).await.unwrap();
+            let response = handler(&String::from_utf8_lossy(&buf[..read]));
+            stream.write_all(&response.data).await.unwrap();
+            if response.stall {
+                tokio::spawn(async move {
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                    drop(stream);
+                });
+            }
+        }
+    });
+    url
+}
+
 #[tokio::test]
 async fn test_validate_compressed_good() {
     let manifest_path = "example_manifest.json";
@@ -28,6 +90,399 @@ async fn test_validate_compressed_bad() {
     assert_eq!(corrupted, vec!["Map_00_00.unity3d"]);
 }
 
+#[tokio::test]
+async fn test_validate_compressed_subset() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let asset_root_bad = "example_builds/compressed/bad/";
+
+    let names = vec!["Map_00_00.unity3d".to_string(), "main.unity3d".to_string()];
+    let corrupted = version
+        .validate_compressed_subset(asset_root_bad, &names, None)
+        .await
+        .unwrap();
+    assert_eq!(corrupted, vec!["Map_00_00.unity3d"]);
+
+    let mut names: Vec<String> = version
+        .get_bundles()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| name != "Map_00_00.unity3d")
+        .collect();
+    assert!(version
+        .validate_compressed_subset(asset_root_bad, &names, None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    names.push("Map_99_99.unity3d".to_string());
+    let err = version
+        .validate_compressed_subset(asset_root_bad, &names, None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Map_99_99.unity3d"));
+}
+
+#[tokio::test]
+async fn test_validate_streaming() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let corrupted = version
+        .validate_compressed_streaming("example_builds/compressed/bad/", None, sender)
+        .await
+        .unwrap();
+    let (name, reason) = receiver.recv().await.unwrap();
+    assert_eq!(vec![name], corrupted);
+    assert!(matches!(reason, crate::FailReason::BadSize { .. }));
+    assert!(receiver.recv().await.is_none());
+
+    // nothing is read until the validation is done, and every failure has been sent by then
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut corrupted = version
+        .validate_uncompressed_streaming("example_builds/uncompressed/bad/", None, sender)
+        .await
+        .unwrap();
+    let mut streamed = Vec::new();
+    while let Ok((name, _)) = receiver.try_recv() {
+        streamed.push(name);
+    }
+    assert!(receiver.is_closed());
+    corrupted.sort();
+    streamed.sort();
+    assert!(!corrupted.is_empty());
+    assert_eq!(streamed, corrupted);
+}
+
+#[tokio::test]
+async fn test_validate_remote() {
+    let manifest_path = "example_manifest.json";
+    let mut version = Version::from_manifest_file(manifest_path).unwrap();
+
+    let asset_root = std::fs::canonicalize("example_builds/compressed/good/").unwrap();
+    let asset_url = crate::util::file_path_to_uri(asset_root.to_str().unwrap());
+    version.set_asset_url(&asset_url).unwrap();
+    version.set_main_file_url(&format!("{}/main.unity3d", asset_url));
+    assert!(version
+        .validate_remote(true, None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let asset_root_bad = std::fs::canonicalize("example_builds/compressed/bad/").unwrap();
+    let asset_url_bad = crate::util::file_path_to_uri(asset_root_bad.to_str().unwrap());
+    version.set_asset_url(&asset_url_bad).unwrap();
+    version.set_main_file_url(&format!("{}/main.unity3d", asset_url_bad));
+    let corrupted = version.validate_remote(false, None).await.unwrap();
+    assert_eq!(corrupted, vec!["Map_00_00.unity3d"]);
+
+    // a file that isn't there is missing, but one that can't be fetched at all is unreachable
+    let reasons = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let cb = {
+        let reasons = std::sync::Arc::clone(&reasons);
+        move |_: &Uuid, name: &str, progress: crate::ItemProgress| {
+            if let crate::ItemProgress::Failed { reason, .. } = progress {
+                reasons.lock().unwrap().push((name.to_string(), reason));
+            }
+        }
+    };
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed_url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    version
+        .set_asset_url(&format!("{}/nonexistent", asset_url))
+        .unwrap();
+    version.set_main_file_url(&format!("{}/main.unity3d", closed_url));
+    version
+        .validate_remote(false, Some(std::sync::Arc::new(cb)))
+        .await
+        .unwrap();
+    let reasons = reasons.lock().unwrap();
+    assert_eq!(reasons.len(), version.get_bundles().count() + 1);
+    for (name, reason) in reasons.iter() {
+        if name == "main.unity3d" {
+            assert!(
+                matches!(reason, crate::FailReason::Unreachable { .. }),
+                "{}",
+                reason
+            );
+        } else {
+            assert!(matches!(reason, crate::FailReason::Missing), "{}", reason);
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_blocking_validate_and_repair() {
+    use crate::blocking;
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let asset_root_good = "example_builds/compressed/good/";
+    assert!(
+        blocking::validate_compressed(&version, asset_root_good, None)
+            .unwrap()
+            .is_empty()
+    );
+
+    let asset_root_bad = "example_builds/compressed/bad/";
+    let corrupted = blocking::validate_compressed(&version, asset_root_bad, None).unwrap();
+    assert_eq!(corrupted, vec!["Map_00_00.unity3d"]);
+
+    let mut version = version;
+    let asset_root = std::fs::canonicalize(asset_root_good).unwrap();
+    version
+        .set_asset_url(&crate::util::file_path_to_uri(asset_root.to_str().unwrap()))
+        .unwrap();
+    let build_dir = TempDir::new();
+    crate::util::copy_dir(asset_root_bad, build_dir.path(), false).unwrap();
+    let repaired = blocking::repair(&version, build_dir.path(), None).unwrap();
+    assert_eq!(repaired, vec!["Map_00_00.unity3d"]);
+    assert!(
+        blocking::validate_compressed(&version, build_dir.path(), None)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_generate_repair_script() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+
+    let build_dir = TempDir::new();
+    crate::util::copy_dir("example_builds/compressed/bad/", build_dir.path(), false).unwrap();
+    std::fs::remove_file(format!("{}/main.unity3d", build_dir.path())).unwrap();
+
+    let script = version
+        .generate_repair_script(build_dir.path())
+        .await
+        .unwrap();
+    assert_eq!(script.get_uuid(), version.get_uuid());
+    let entries = script.get_entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].get_bundle(), "Map_00_00.unity3d");
+    assert_eq!(entries[1].get_bundle(), "main.unity3d");
+    assert_eq!(entries[1].get_reason(), "File missing");
+
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    assert_eq!(
+        entries[0].get_url(),
+        format!("{}/Map_00_00.unity3d", version.get_asset_url())
+    );
+    assert_eq!(
+        entries[0].get_local_path(),
+        format!("{}/Map_00_00.unity3d", build_dir.path())
+    );
+    assert_eq!(
+        entries[0].get_expected_hash(),
+        bundle_info.get_compressed_info().get_hash()
+    );
+    assert_eq!(
+        entries[0].get_expected_size(),
+        bundle_info.get_compressed_size()
+    );
+
+    let json = script.to_json().unwrap();
+    assert_eq!(json["entries"][1]["bundle"], "main.unity3d");
+}
+
+#[tokio::test]
+async fn test_validate_compressed_report() {
+    let manifest_path = "example_manifest.json";
+    let version = Version::from_manifest_file(manifest_path).unwrap();
+
+    let asset_root_bad = "example_builds/compressed/bad/";
+    let report = version
+        .validate_compressed_report(asset_root_bad, None)
+        .await
+        .unwrap();
+    assert_eq!(report.get_corrupted(), vec!["Map_00_00.unity3d"]);
+    assert_eq!(report.get_items().len(), 5);
+
+    let json = report.to_json().unwrap();
+    let item = json["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["name"] == "Map_00_00.unity3d")
+        .unwrap();
+    assert_eq!(item["status"], "failed");
+    assert!(item["reason"].is_string());
+    assert!(item["expected"]["hash"].is_string());
+
+    // an empty file was still read, a missing one wasn't
+    let temp_dir = TempDir::new();
+    let asset_root = temp_dir.path();
+    crate::util::copy_dir("example_builds/compressed/good/", asset_root, false).unwrap();
+    std::fs::write(format!("{}/Map_00_00.unity3d", asset_root), b"").unwrap();
+    std::fs::remove_file(format!("{}/Map_01_03.unity3d", asset_root)).unwrap();
+    let report = version
+        .validate_compressed_report(asset_root, None)
+        .await
+        .unwrap();
+    let json = report.to_json().unwrap();
+    let item = |name: &str| {
+        json["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["name"] == name)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(item("Map_00_00.unity3d")["actual"]["size"], 0);
+    assert!(item("Map_01_03.unity3d")["actual"].is_null());
+}
+
+#[tokio::test]
+async fn test_validate_compressed_with_options() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{ConcurrencyConfig, ItemProgress, OperationOptions};
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+    let cb = {
+        let in_flight = Arc::clone(&in_flight);
+        let max_in_flight = Arc::clone(&max_in_flight);
+        move |_: &Uuid, _: &str, progress: ItemProgress| match progress {
+            ItemProgress::Validating => {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+            }
+            ItemProgress::Passed { .. } | ItemProgress::Failed { .. } => {
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+    };
+
+    let options = OperationOptions::default()
+        .with_concurrency(ConcurrencyConfig::default().with_max_items(1));
+    let corrupted = version
+        .validate_compressed_with_options(
+            "example_builds/compressed/bad/",
+            Some(Arc::new(cb)),
+            &options,
+        )
+        .await
+        .unwrap();
+    assert_eq!(corrupted, vec!["Map_00_00.unity3d"]);
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_validate_many() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{ConcurrencyConfig, ItemProgress, OperationOptions, ValidationReport};
+
+    let manifest_path = "example_manifest.json";
+    let version = Version::from_manifest_file(manifest_path).unwrap();
+
+    let builds = vec![
+        (
+            version.clone(),
+            "example_builds/compressed/good/".to_string(),
+        ),
+        (version, "example_builds/compressed/bad/".to_string()),
+    ];
+    let results = Version::validate_many(&builds, None, &OperationOptions::default()).await;
+    let reports: Vec<ValidationReport> = results.into_iter().map(Result::unwrap).collect();
+    assert_eq!(reports.len(), 2);
+    assert!(reports[0].get_corrupted().is_empty());
+    assert_eq!(reports[1].get_corrupted(), vec!["Map_00_00.unity3d"]);
+    assert_eq!(reports[1].get_path(), "example_builds/compressed/bad/");
+
+    // the builds share one limit, so only one item is ever checked at a time
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+    let cb = {
+        let in_flight = Arc::clone(&in_flight);
+        let max_in_flight = Arc::clone(&max_in_flight);
+        move |_: &Uuid, _: &str, progress: ItemProgress| match progress {
+            ItemProgress::Validating => {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+            }
+            ItemProgress::Passed { .. } | ItemProgress::Failed { .. } => {
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+    };
+    let options = OperationOptions::default()
+        .with_concurrency(ConcurrencyConfig::default().with_max_items(1));
+    let results = Version::validate_many(&builds, Some(Arc::new(cb)), &options).await;
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_repair_dry_run() {
+    let manifest_path = "example_manifest.json";
+    let version = Version::from_manifest_file(manifest_path).unwrap();
+
+    let asset_root_good = "example_builds/compressed/good/";
+    assert!(version
+        .repair_dry_run(asset_root_good)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let asset_root_bad = "example_builds/compressed/bad/";
+    let to_download = version.repair_dry_run(asset_root_bad).await.unwrap();
+    assert_eq!(to_download.len(), 1);
+    let (name, reason, size) = &to_download[0];
+    assert_eq!(name, "Map_00_00.unity3d");
+    assert!(matches!(reason, crate::FailReason::BadSize { .. }));
+    assert_eq!(
+        *size,
+        version
+            .get_bundle("Map_00_00.unity3d")
+            .unwrap()
+            .compressed_info
+            .size
+    );
+}
+
+#[tokio::test]
+async fn test_validate_compressed_ranges() {
+    let manifest_path = "example_manifest.json";
+    let mut version = Version::from_manifest_file(manifest_path).unwrap();
+
+    let asset_root_good = "example_builds/compressed/good/";
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    assert!(bundle_info
+        .validate_compressed_ranges("example_builds/compressed/good/Map_00_00.unity3d")
+        .is_err());
+
+    version
+        .generate_block_hashes(asset_root_good, 1024)
+        .unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    let ranges = bundle_info
+        .validate_compressed_ranges("example_builds/compressed/good/Map_00_00.unity3d")
+        .unwrap();
+    assert!(ranges.is_empty());
+
+    // the bad copy has an extra byte inserted near the start, shifting every block
+    let ranges = bundle_info
+        .validate_compressed_ranges("example_builds/compressed/bad/Map_00_00.unity3d")
+        .unwrap();
+    assert_eq!(ranges, vec![0..3269]);
+
+    let ranges = bundle_info
+        .validate_compressed_ranges("example_builds/compressed/bad/missing.unity3d")
+        .unwrap();
+    assert_eq!(ranges, vec![0..3268]);
+}
+
 #[tokio::test]
 async fn test_validate_uncompressed_good() {
     let asset_root = "example_builds/uncompressed/good/";
@@ -61,6 +516,241 @@ async fn test_validate_uncompressed_bad() {
     );
 }
 
+#[tokio::test]
+async fn test_validate_uncompressed_with_resolver() {
+    use std::{path::PathBuf, sync::Arc};
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+
+    // lay the build out with plain bundle names instead of url-encoded ones
+    let asset_root = "example_builds/uncompressed/good/";
+    let output_dir = TempDir::new();
+    for (bundle_name, _) in version.get_bundles() {
+        let from = format!("{}/{}", asset_root, crate::util::url_encode(bundle_name));
+        let to = format!("{}/{}", output_dir.path(), bundle_name);
+        std::fs::create_dir(&to).unwrap();
+        crate::util::copy_dir(&from, &to, false).unwrap();
+    }
+
+    let root = PathBuf::from(output_dir.path());
+    let resolver = move |bundle_name: &str| root.join(bundle_name);
+    let corrupted = version
+        .validate_uncompressed_with_resolver(Arc::new(resolver), None)
+        .await
+        .unwrap();
+    assert!(corrupted.is_empty());
+    assert!(!version
+        .validate_uncompressed(output_dir.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_validate_uncompressed_no_info() {
+    use crate::{BundleInfo, FailReason};
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let compressed_info = version
+        .get_bundle("Map_00_00.unity3d")
+        .unwrap()
+        .compressed_info
+        .clone();
+    let bundle_info = BundleInfo::from(compressed_info);
+
+    let folder_path = "example_builds/uncompressed/good/map_5f00_5f00_2eunity3d";
+    let corrupted = bundle_info
+        .validate_uncompressed(folder_path, None, None)
+        .unwrap();
+    assert_eq!(corrupted.len(), 1);
+    assert!(matches!(corrupted[0].1, FailReason::NoUncompressedInfo));
+}
+
+#[test]
+fn test_validate_uncompressed_unreadable() {
+    use crate::FailReason;
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+
+    let folder_path = TempDir::new();
+    crate::util::copy_dir(
+        "example_builds/uncompressed/good/map_5f00_5f00_2eunity3d",
+        folder_path.path(),
+        false,
+    )
+    .unwrap();
+
+    // a directory in place of a file exists but can't be hashed
+    let mut file_names = crate::util::list_filenames_in_directory(folder_path.path()).unwrap();
+    file_names.sort();
+    let unreadable = format!("{}/{}", folder_path.path(), file_names[0]);
+    std::fs::remove_file(&unreadable).unwrap();
+    std::fs::create_dir(&unreadable).unwrap();
+    let missing = format!("{}/{}", folder_path.path(), file_names[1]);
+    std::fs::remove_file(&missing).unwrap();
+
+    let mut corrupted = bundle_info
+        .validate_uncompressed(folder_path.path(), None, None)
+        .unwrap();
+    corrupted.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(corrupted.len(), 2);
+    assert!(corrupted[0].0.ends_with(&file_names[0]));
+    assert!(matches!(corrupted[0].1, FailReason::Unreadable { .. }));
+    assert!(corrupted[1].0.ends_with(&file_names[1]));
+    assert!(matches!(corrupted[1].1, FailReason::Missing));
+}
+
+#[test]
+fn test_validate_uncompressed_unexpected() {
+    use crate::FailReason;
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+
+    let folder_path = TempDir::new();
+    crate::util::copy_dir(
+        "example_builds/uncompressed/good/map_5f00_5f00_2eunity3d",
+        folder_path.path(),
+        false,
+    )
+    .unwrap();
+    std::fs::write(format!("{}/leftover.part", folder_path.path()), b"junk").unwrap();
+
+    let corrupted = bundle_info
+        .validate_uncompressed(folder_path.path(), None, None)
+        .unwrap();
+    assert!(corrupted.is_empty());
+
+    let corrupted = bundle_info
+        .validate_uncompressed_reporting_unexpected(folder_path.path(), None, None)
+        .unwrap();
+    assert_eq!(corrupted.len(), 1);
+    assert!(corrupted[0].0.ends_with("/leftover.part"));
+    assert!(matches!(corrupted[0].1, FailReason::Unexpected));
+}
+
+#[test]
+fn test_resolve_with_parent() {
+    use crate::{BundleInfo, FileInfo};
+
+    let parent = Version::from_manifest_file("example_manifest.json").unwrap();
+
+    let mut child = Version::build_barebones("http://example.url/child/", Some("Child")).unwrap();
+    child.parent_uuid = Some(parent.get_uuid());
+    let mut map_info = parent.get_bundle("Map_00_00.unity3d").unwrap().clone();
+    map_info.compressed_info.size += 1;
+    map_info.compressed_info.hash = "0".repeat(64);
+    child
+        .bundles
+        .insert("Map_00_00.unity3d".to_string(), map_info.clone());
+    let extra_info = BundleInfo::from(FileInfo {
+        hash: "1".repeat(64),
+        size: 10,
+    });
+    child
+        .bundles
+        .insert("Extra.unity3d".to_string(), extra_info.clone());
+
+    let resolved = child.resolve_with_parent(&parent);
+    assert_eq!(resolved.get_uuid(), child.get_uuid());
+    assert_eq!(resolved.get_asset_url(), "http://example.url/child");
+    assert_eq!(
+        resolved.get_bundles().count(),
+        parent.get_bundles().count() + 1
+    );
+    assert_eq!(resolved.get_bundle("Map_00_00.unity3d"), Some(&map_info));
+    assert_eq!(resolved.get_bundle("Extra.unity3d"), Some(&extra_info));
+    assert_eq!(
+        resolved
+            .get_bundle("Map_01_03.unity3d")
+            .unwrap()
+            .get_compressed_info(),
+        parent
+            .get_bundle("Map_01_03.unity3d")
+            .unwrap()
+            .get_compressed_info()
+    );
+    assert_eq!(resolved.get_main_file_info(), parent.get_main_file_info());
+
+    // inherited bundles are still fetched from the parent
+    assert_eq!(
+        resolved.get_bundle_url("Map_01_03.unity3d"),
+        format!("{}/Map_01_03.unity3d", parent.get_asset_url())
+    );
+    assert_eq!(
+        resolved.get_bundle_url("Map_00_00.unity3d"),
+        "http://example.url/child/Map_00_00.unity3d"
+    );
+    assert_eq!(
+        resolved.get_bundle_url("Extra.unity3d"),
+        "http://example.url/child/Extra.unity3d"
+    );
+
+    // and the base URL survives a round trip through the manifest
+    let temp_dir = TempDir::new();
+    let manifest_path = format!("{}/resolved.json", temp_dir.path());
+    resolved.export_manifest(&manifest_path).unwrap();
+    let reloaded = Version::from_manifest_file(&manifest_path).unwrap();
+    assert_eq!(
+        reloaded.get_bundle_url("Map_01_03.unity3d"),
+        resolved.get_bundle_url("Map_01_03.unity3d")
+    );
+    assert_eq!(
+        resolved.get_compressed_assets_size(),
+        parent.get_compressed_assets_size() + 11
+    );
+    assert!(resolved.validate_self().is_empty());
+}
+
+#[tokio::test]
+async fn test_custom_main_file_name() {
+    let asset_root = TempDir::new();
+    crate::util::copy_dir("example_builds/compressed/good/", asset_root.path(), false).unwrap();
+    std::fs::rename(
+        format!("{}/main.unity3d", asset_root.path()),
+        format!("{}/game.unity3d", asset_root.path()),
+    )
+    .unwrap();
+
+    let example_version = Version::from_manifest_file("example_manifest.json").unwrap();
+    assert_eq!(example_version.get_main_file_name(), "main.unity3d");
+
+    let version = Version::builder()
+        .asset_url("http://example.url/")
+        .main_file_name("game.unity3d")
+        .build(asset_root.path())
+        .await
+        .unwrap();
+    assert_eq!(version.get_main_file_name(), "game.unity3d");
+    assert_eq!(
+        version.get_main_file_url().as_deref(),
+        Some("http://example.url/game.unity3d")
+    );
+    assert_eq!(
+        version.get_main_file_info(),
+        example_version.get_main_file_info()
+    );
+    assert!(version.get_bundle("game.unity3d").is_none());
+    assert_eq!(version.get_bundles().count(), 4);
+
+    let json = serde_json::to_string(&version).unwrap();
+    let version: Version = serde_json::from_str(&json).unwrap();
+    assert_eq!(version.get_main_file_name(), "game.unity3d");
+    assert!(version
+        .validate_compressed(asset_root.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        version
+            .validate_compressed("example_builds/compressed/good/", None)
+            .await
+            .unwrap(),
+        vec!["game.unity3d"]
+    );
+}
+
 #[tokio::test]
 async fn test_generate_manifest() {
     let asset_root = "example_builds/compressed/good/";
@@ -76,33 +766,1988 @@ async fn test_generate_manifest() {
 
     let example_manifest = "example_manifest.json";
     let example_version = Version::from_manifest_file(example_manifest).unwrap();
+    assert!(version.get_created_at().is_some());
+    assert_eq!(
+        version.get_generator_version(),
+        Some(env!("CARGO_PKG_VERSION"))
+    );
+    assert_eq!(
+        version.get_content_root(),
+        Some(example_version.merkle_root().as_str())
+    );
     version.uuid = example_version.uuid;
+    version.created_at = None;
+    version.generator_version = None;
+    version.content_root = None;
     assert_eq!(version, example_version);
 
     let corrupted = version.validate_compressed(asset_root, None).await.unwrap();
     assert!(corrupted.is_empty());
 }
 
-#[cfg(feature = "lzma")]
 #[tokio::test]
-async fn test_extract_bundle() {
-    use crate::bundle::AssetBundle;
+async fn test_version_builder() {
+    let asset_root = "example_builds/compressed/good/";
+    let uuid_104 = Uuid::parse_str("ec8063b2-54d4-4ee1-8d9e-381f5babd420").unwrap();
+    let mut version = Version::builder()
+        .asset_url("http://example.url/builds/example_build/")
+        .name("example-build")
+        .description("Example build")
+        .parent(uuid_104)
+        .hidden(true)
+        .min_client_version("1.2.0")
+        .build(asset_root)
+        .await
+        .unwrap();
+    assert!(version.is_hidden());
+    assert_eq!(version.get_min_client_version(), Some("1.2.0"));
+    let json = serde_json::to_string(&version).unwrap();
+    assert!(json.contains("\"min_client_version\":\"1.2.0\""));
+    assert_eq!(serde_json::from_str::<Version>(&json).unwrap(), version);
 
-    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
-    let output_dir = TempDir::new();
+    let example_version = Version::from_manifest_file("example_manifest.json").unwrap();
+    version.uuid = example_version.uuid;
+    version.created_at = None;
+    version.generator_version = None;
+    version.content_root = None;
+    version.set_hidden(false);
+    version.min_client_version = None;
+    assert_eq!(version, example_version);
+    assert!(!serde_json::to_string(&version)
+        .unwrap()
+        .contains("min_client_version"));
 
-    let (_, bundle) = AssetBundle::from_file(bundle_path).unwrap();
-    bundle.extract_files(output_dir.path()).unwrap();
+    assert!(Version::builder().build(asset_root).await.is_err());
+}
 
-    let version = Version::from_manifest_file("example_manifest.json").unwrap();
-    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+#[tokio::test]
+async fn test_generate_manifest_validated() {
+    let asset_root_bad = "example_builds/compressed/bad/";
+    let asset_url = "http://example.url/builds/example_build/";
+    let reference = Version::from_manifest_file("example_manifest.json").unwrap();
 
-    let corrupted = bundle_info
-        .validate_uncompressed(output_dir.path(), Some(version.get_uuid()), None)
+    let (version, report, warnings) = Version::builder()
+        .asset_url(asset_url)
+        .build_validated(asset_root_bad, &reference)
+        .await
+        .unwrap();
+    assert_eq!(report.get_corrupted(), vec!["Map_00_00.unity3d"]);
+    assert_eq!(report.get_items().len(), 5);
+    assert!(version.get_bundle("Map_00_00.unity3d").is_none());
+    assert!(warnings.is_empty());
+
+    let corrupted = version
+        .validate_compressed(asset_root_bad, None)
+        .await
         .unwrap();
     assert!(corrupted.is_empty());
 }
 
+#[test]
+fn test_bundle_sizes() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundles: Vec<_> = version.get_bundles().collect();
+    assert_eq!(bundles.len(), 4);
+
+    let compressed_size: u64 = bundles.iter().map(|(_, b)| b.get_compressed_size()).sum();
+    let uncompressed_size: u64 = bundles.iter().map(|(_, b)| b.get_uncompressed_size()).sum();
+    assert_eq!(compressed_size, version.get_compressed_assets_size());
+    assert_eq!(uncompressed_size, version.get_uncompressed_assets_size());
+
+    let (name, bundle_info) = bundles[0];
+    assert_eq!(version.get_bundle(name), Some(bundle_info));
+}
+
+#[test]
+fn test_file_info_getters() {
+    let example_manifest = "example_manifest.json";
+    let version = Version::from_manifest_file(example_manifest).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(example_manifest).unwrap()).unwrap();
+
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    let compressed_info = bundle_info.get_compressed_info();
+    let compressed_json = &json["bundles"]["Map_00_00.unity3d"]["compressed_info"];
+    assert_eq!(compressed_info.get_hash(), compressed_json["hash"]);
+    assert_eq!(compressed_info.get_size(), compressed_json["size"]);
+    assert_eq!(
+        compressed_info.get_size(),
+        bundle_info.get_compressed_size()
+    );
+
+    let (file_name, file_json) = json["bundles"]["Map_00_00.unity3d"]["uncompressed_info"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .next()
+        .unwrap();
+    let file_info = bundle_info.get_uncompressed_file_info(file_name).unwrap();
+    assert_eq!(file_info.get_hash(), file_json["hash"]);
+    assert_eq!(file_info.get_size(), file_json["size"]);
+    assert!(bundle_info.get_uncompressed_file_info("nope").is_none());
+}
+
+#[test]
+fn test_manifest_case_collisions() {
+    let example_manifest = "example_manifest.json";
+    let version = Version::from_manifest_file(example_manifest).unwrap();
+    assert!(version.find_case_collisions().is_empty());
+
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(example_manifest).unwrap()).unwrap();
+    let bundles = json["bundles"].as_object_mut().unwrap();
+    let bundle = bundles["Map_00_00.unity3d"].clone();
+    bundles.insert("map_00_00.unity3d".to_string(), bundle);
+
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/manifest.json", output_dir.path());
+    std::fs::write(&output_path, json.to_string()).unwrap();
+    let err = Version::from_manifest_file(&output_path)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Map_00_00.unity3d, map_00_00.unity3d"));
+
+    let version: Version = serde_json::from_value(json).unwrap();
+    assert_eq!(
+        version.find_case_collisions(),
+        vec![vec!["Map_00_00.unity3d", "map_00_00.unity3d"]]
+    );
+}
+
+#[tokio::test]
+async fn test_asset_url_validation() {
+    let asset_root = "example_builds/compressed/good/";
+    for bad_url in ["htp://example.url/", "example.url/builds", ""] {
+        assert!(Version::build(asset_root, bad_url, None, None, None)
+            .await
+            .is_err());
+        assert!(Version::build_barebones(bad_url, None).is_err());
+    }
+
+    let mut version = Version::build_barebones("file:///srv/builds/", None).unwrap();
+    assert!(version.set_asset_url("ftp://example.url/").is_err());
+    version
+        .set_asset_url("https://example.url/builds/")
+        .unwrap();
+    assert_eq!(version.get_asset_url(), "https://example.url/builds");
+}
+
+#[test]
+fn test_files_to_remove() {
+    let asset_root = "example_builds/compressed/good/";
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    assert!(version.files_to_remove(asset_root).unwrap().is_empty());
+
+    let barebones = Version::build_barebones("http://example.url/", None).unwrap();
+    assert_eq!(
+        barebones.files_to_remove(asset_root).unwrap(),
+        vec![
+            "DongResources_00_09.resourceFile",
+            "DongResources_03_01.resourceFile",
+            "Map_00_00.unity3d",
+            "Map_01_03.unity3d",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_download_compressed_resume() {
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    // nothing should be fetched since every file is already valid
+    version.set_asset_url("http://invalid.invalid").unwrap();
+
+    let output_dir = TempDir::new();
+    let asset_root = "example_builds/compressed/good/";
+    for name in crate::util::list_filenames_in_directory(asset_root).unwrap() {
+        std::fs::copy(
+            format!("{}/{}", asset_root, name),
+            format!("{}/{}", output_dir.path(), name),
+        )
+        .unwrap();
+    }
+
+    version
+        .download_compressed_resume(output_dir.path(), None)
+        .await
+        .unwrap();
+    assert!(std::fs::exists(format!("{}/ignored_file.txt", output_dir.path())).unwrap());
+}
+
+#[tokio::test]
+async fn test_item_progress_timing() {
+    use std::sync::{Arc, Mutex};
+
+    use crate::ItemProgress;
+
+    let asset_root = "example_builds/compressed/good/";
+    let asset_root_abs = std::fs::canonicalize(asset_root).unwrap();
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    version
+        .set_asset_url(&crate::util::file_path_to_uri(
+            asset_root_abs.to_str().unwrap(),
+        ))
+        .unwrap();
+
+    let output_dir = TempDir::new();
+    for name in crate::util::list_filenames_in_directory(asset_root).unwrap() {
+        if name != "Map_00_00.unity3d" {
+            std::fs::copy(
+                format!("{}/{}", asset_root, name),
+                format!("{}/{}", output_dir.path(), name),
+            )
+            .unwrap();
+        }
+    }
+
+    let passed = Arc::new(Mutex::new(Vec::new()));
+    let cb = {
+        let passed = Arc::clone(&passed);
+        move |_: &Uuid, name: &str, progress: ItemProgress| {
+            if let ItemProgress::Passed { was_downloaded, .. } = progress {
+                passed
+                    .lock()
+                    .unwrap()
+                    .push((name.to_string(), was_downloaded));
+            }
+        }
+    };
+    version
+        .download_compressed_resume(output_dir.path(), Some(Arc::new(cb)))
+        .await
+        .unwrap();
+
+    let passed = passed.lock().unwrap();
+    assert!(passed.contains(&("Map_00_00.unity3d".to_string(), true)));
+    assert!(passed.contains(&("Map_01_03.unity3d".to_string(), false)));
+}
+
+#[tokio::test]
+async fn test_download_to_file_part() {
+    use crate::util;
+
+    let output_dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let part_path = format!("{}.part", file_path);
+
+    let source_path =
+        std::fs::canonicalize("example_builds/compressed/good/Map_00_00.unity3d").unwrap();
+    let source_uri = util::file_path_to_uri(source_path.to_str().unwrap());
+    util::download_to_file(None, &source_uri, &file_path, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(&file_path).unwrap(),
+        std::fs::read(&source_path).unwrap()
+    );
+    assert!(!std::fs::exists(&part_path).unwrap());
+
+    let hash = util::download_to_file_hashed(None, &source_uri, &file_path, None)
+        .await
+        .unwrap();
+    assert_eq!(hash, util::get_file_hash(&file_path).unwrap());
+
+    // a failed download leaves the existing file alone
+    let missing_uri = format!("{}.missing", source_uri);
+    assert!(util::download_to_file(None, &missing_uri, &file_path, None)
+        .await
+        .is_err());
+    assert!(std::fs::exists(&file_path).unwrap());
+    assert!(!std::fs::exists(&part_path).unwrap());
+}
+
+#[tokio::test]
+async fn test_shared_bundle_store() {
+    let store_dir = TempDir::new();
+    let store_path = format!("{}/store", store_dir.path());
+    let options = crate::OperationOptions::default().with_bundle_store(store_path.clone().into());
+
+    let asset_root = std::fs::canonicalize("example_builds/compressed/good/").unwrap();
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    version
+        .set_asset_url(&crate::util::file_path_to_uri(asset_root.to_str().unwrap()))
+        .unwrap();
+
+    let first_build = TempDir::new();
+    version
+        .download_compressed_with_options(first_build.path(), None, &options)
+        .await
+        .unwrap();
+    let map_hash = version
+        .get_bundle("Map_00_00.unity3d")
+        .unwrap()
+        .compressed_info
+        .hash
+        .clone();
+    let stored_path = format!("{}/{}", store_path, map_hash);
+    assert!(std::fs::exists(&stored_path).unwrap());
+
+    // the store keeps its own copy, so damaging the build doesn't damage it
+    std::fs::write(
+        format!("{}/Map_00_00.unity3d", first_build.path()),
+        b"corrupt",
+    )
+    .unwrap();
+    assert_eq!(crate::util::get_file_hash(&stored_path).unwrap(), *map_hash);
+
+    // everything should come from the store now
+    version.set_asset_url("http://invalid.invalid").unwrap();
+    let second_build = TempDir::new();
+    version
+        .download_compressed_with_options(second_build.path(), None, &options)
+        .await
+        .unwrap();
+    assert!(version
+        .validate_compressed(second_build.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // hashes that aren't SHA-256 are never joined into a store path
+    assert!(crate::util::fetch_from_bundle_store(
+        std::path::Path::new(&store_path),
+        &format!("../{}", map_hash),
+        &format!("{}/escaped", second_build.path())
+    )
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_mirror_size_check() {
+    use crate::util;
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap().clone();
+
+    // the bad build has an extra byte in this bundle, so pretend it's a broken mirror
+    let mirror_path =
+        std::fs::canonicalize("example_builds/compressed/bad/Map_00_00.unity3d").unwrap();
+    let mirror_url = util::file_path_to_uri(mirror_path.to_str().unwrap());
+    assert_eq!(
+        util::remote_file_size(&mirror_url).await.unwrap(),
+        Some(bundle_info.get_compressed_size() + 1)
+    );
+
+    let output_dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let options = crate::OperationOptions::default().with_mirror_size_check(true);
+    let result = bundle_info
+        .validate_compressed_with_options(&file_path, None, Some(&mirror_url), None, &options)
+        .await;
+    let err = result.unwrap_err();
+    let failure = err.downcast_ref::<crate::FileFailure>().unwrap();
+    assert!(matches!(
+        failure.get_reason(),
+        crate::FailReason::MirrorMismatch { .. }
+    ));
+    assert!(!std::fs::exists(&file_path).unwrap());
+}
+
+#[tokio::test]
+async fn test_download_attempts_exhausted() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+
+    // the bad build has an extra byte in this bundle, so every download fails validation
+    let mirror_path =
+        std::fs::canonicalize("example_builds/compressed/bad/Map_00_00.unity3d").unwrap();
+    let mirror_url = crate::util::file_path_to_uri(mirror_path.to_str().unwrap());
+
+    let output_dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let err = bundle_info
+        .validate_compressed(&file_path, None, Some(&mirror_url), None)
+        .await
+        .unwrap_err();
+    let failure = err.downcast_ref::<crate::FileFailure>().unwrap();
+    assert_eq!(failure.get_path(), file_path);
+    assert!(matches!(
+        failure.get_reason(),
+        crate::FailReason::BadSize { .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_remote_file_size_encoded() {
+    let server_url = spawn_test_server(|request| {
+        // the gzipped copy is smaller than the file itself
+        let headers: &[&str] = match request.contains("/gzip") {
+            true => &["Content-Encoding: gzip", "Content-Length: 123"],
+            false => &["Content-Length: 123"],
+        };
+        TestResponse::new("200 OK", headers, b"")
+    })
+    .await;
+
+    let url = |path: &str| format!("{}/{}", server_url, path);
+    assert_eq!(
+        crate::util::remote_file_size(&url("plain")).await.unwrap(),
+        Some(123)
+    );
+    assert_eq!(
+        crate::util::remote_file_size(&url("gzip")).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_repair_main_file() {
+    use std::sync::{Arc, Mutex};
+
+    use crate::ItemProgress;
+
+    let asset_root = std::fs::canonicalize("example_builds/compressed/good/").unwrap();
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    version
+        .set_asset_url(&crate::util::file_path_to_uri(asset_root.to_str().unwrap()))
+        .unwrap();
+
+    let output_dir = TempDir::new();
+    for name in crate::util::list_filenames_in_directory(asset_root.to_str().unwrap()).unwrap() {
+        std::fs::copy(
+            asset_root.join(&name),
+            format!("{}/{}", output_dir.path(), name),
+        )
+        .unwrap();
+    }
+    std::fs::write(format!("{}/main.unity3d", output_dir.path()), "bad").unwrap();
+
+    let phases = Arc::new(Mutex::new(Vec::new()));
+    let cb = {
+        let phases = Arc::clone(&phases);
+        move |_: &Uuid, name: &str, progress: ItemProgress| {
+            if name != "main.unity3d" {
+                return;
+            }
+            let phase = match progress {
+                ItemProgress::Downloading { .. } => "downloading",
+                ItemProgress::Validating => "validating",
+                ItemProgress::Passed {
+                    was_downloaded: true,
+                    ..
+                } => "passed",
+                _ => "other",
+            };
+            let mut phases = phases.lock().unwrap();
+            if phases.last() != Some(&phase) {
+                phases.push(phase);
+            }
+        }
+    };
+    version
+        .repair(output_dir.path(), Some(Arc::new(cb)))
+        .await
+        .unwrap();
+    assert_eq!(
+        *phases.lock().unwrap(),
+        vec!["validating", "downloading", "validating", "passed"]
+    );
+}
+
+#[tokio::test]
+async fn test_pause_downloads() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{DownloadControl, ItemProgress, OperationOptions};
+
+    let asset_root = std::fs::canonicalize("example_builds/compressed/good/").unwrap();
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    version
+        .set_asset_url(&crate::util::file_path_to_uri(asset_root.to_str().unwrap()))
+        .unwrap();
+
+    let downloads = Arc::new(AtomicUsize::new(0));
+    let cb = {
+        let downloads = Arc::clone(&downloads);
+        move |_: &Uuid, _: &str, progress: ItemProgress| {
+            if let ItemProgress::Downloading { .. } = progress {
+                downloads.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    };
+
+    let control = DownloadControl::new();
+    control.pause();
+    assert!(control.is_paused());
+    let options = OperationOptions::default().with_download_control(control.clone());
+    let output_dir = TempDir::new();
+    let task = {
+        let path = output_dir.path().to_string();
+        tokio::spawn(async move {
+            version
+                .download_compressed_with_options(&path, Some(Arc::new(cb)), &options)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok::<_, String>(version)
+        })
+    };
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(!task.is_finished());
+    assert_eq!(downloads.load(Ordering::SeqCst), 0);
+
+    control.resume();
+    let version = task.await.unwrap().unwrap();
+    assert!(downloads.load(Ordering::SeqCst) > 0);
+    assert!(version
+        .validate_compressed(output_dir.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_repair_main_file_alongside_bundles() {
+    use crate::{ConcurrencyConfig, OperationOptions};
+
+    let asset_root = std::fs::canonicalize("example_builds/compressed/good/").unwrap();
+    // file:// downloads don't take download permits, so serve the build over HTTP
+    let server_url = {
+        let asset_root = asset_root.clone();
+        spawn_test_server(move |request| {
+            let path = request.split(' ').nth(1).unwrap();
+            TestResponse::ok(
+                &std::fs::read(asset_root.join(path.trim_start_matches("/build/"))).unwrap(),
+            )
+        })
+        .await
+    };
+    let asset_url = format!("{}/build", server_url);
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    version.set_asset_url(&asset_url).unwrap();
+
+    let output_dir = TempDir::new();
+    for name in crate::util::list_filenames_in_directory(asset_root.to_str().unwrap()).unwrap() {
+        std::fs::copy(
+            asset_root.join(&name),
+            format!("{}/{}", output_dir.path(), name),
+        )
+        .unwrap();
+    }
+    std::fs::write(format!("{}/main.unity3d", output_dir.path()), "bad").unwrap();
+
+    // with the only shared download permit taken, the main file can only be repaired
+    // through its dedicated permit
+    let options = OperationOptions::default()
+        .with_concurrency(ConcurrencyConfig::default().with_max_downloads(1))
+        .with_main_file_alongside_bundles(true);
+    let shared_permit = options.acquire_download().await;
+    let result = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        version.repair_with_options(output_dir.path(), None, &options),
+    )
+    .await
+    .expect("main file waited for a shared download permit");
+    assert_eq!(result.unwrap(), Vec::<String>::new());
+    assert!(version
+        .validate_main_file(output_dir.path(), None)
+        .await
+        .unwrap()
+        .is_none());
+    drop(shared_permit);
+
+    std::fs::write(format!("{}/main.unity3d", output_dir.path()), "bad").unwrap();
+    std::fs::remove_file(format!("{}/Map_00_00.unity3d", output_dir.path())).unwrap();
+    let result = version
+        .repair_with_options(output_dir.path(), None, &options)
+        .await;
+    assert_eq!(result.unwrap(), vec!["Map_00_00.unity3d"]);
+    assert!(version
+        .validate_compressed(output_dir.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_download_if_modified() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    // the server only has an unchanged copy for conditional requests
+    let conditional_requests = Arc::new(AtomicUsize::new(0));
+    let server_url = {
+        let conditional_requests = Arc::clone(&conditional_requests);
+        spawn_test_server(move |request| {
+            if request
+                .to_ascii_lowercase()
+                .contains("\r\nif-modified-since: ")
+            {
+                conditional_requests.fetch_add(1, Ordering::SeqCst);
+                TestResponse::new("304 Not Modified", &[], b"")
+            } else {
+                TestResponse::ok(b"fresh")
+            }
+        })
+        .await
+    };
+    let url = format!("{}/main.unity3d", server_url);
+
+    let dir = TempDir::new();
+    let file_path = format!("{}/main.unity3d", dir.path());
+    std::fs::write(&file_path, "local").unwrap();
+    let downloaded = crate::util::download_to_file_if_modified(None, &url, &file_path, None)
+        .await
+        .unwrap();
+    assert!(!downloaded);
+    assert_eq!(conditional_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "local");
+
+    // without a local copy, there's nothing to send a date for
+    std::fs::remove_file(&file_path).unwrap();
+    let downloaded = crate::util::download_to_file_if_modified(None, &url, &file_path, None)
+        .await
+        .unwrap();
+    assert!(downloaded);
+    assert_eq!(conditional_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "fresh");
+
+    // a repair only downloads files that already failed validation, so it never asks conditionally
+    std::fs::write(&file_path, "stale").unwrap();
+    let expected = crate::FileInfo {
+        hash: format!("{:x}", <sha2::Sha256 as sha2::Digest>::digest(b"fresh")),
+        size: 5,
+    };
+    let bundle_info = crate::BundleInfo::from(expected);
+    let repaired = bundle_info
+        .validate_compressed(&file_path, None, Some(&url), None)
+        .await
+        .unwrap();
+    assert!(repaired);
+    assert_eq!(conditional_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "fresh");
+}
+
+#[tokio::test]
+async fn test_download_honors_retry_after() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use std::time::{Duration, Instant};
+
+    use crate::ItemProgress;
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let body = std::fs::read(bundle_path).unwrap();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let server_url = {
+        let requests = Arc::clone(&requests);
+        spawn_test_server(move |_| {
+            // the first request is rate limited
+            match requests.fetch_add(1, Ordering::SeqCst) {
+                0 => TestResponse::new("429 Too Many Requests", &["Retry-After: 1"], b""),
+                _ => TestResponse::ok(&body),
+            }
+        })
+        .await
+    };
+    let url = format!("{}/Map_00_00.unity3d", server_url);
+
+    let delays = Arc::new(Mutex::new(Vec::new()));
+    let cb = {
+        let delays = Arc::clone(&delays);
+        move |_: &Uuid, _: &str, progress: ItemProgress| {
+            if let ItemProgress::Retrying { delay } = progress {
+                delays.lock().unwrap().push(delay);
+            }
+        }
+    };
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    let output_dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let start = Instant::now();
+    assert!(bundle_info
+        .validate_compressed(&file_path, None, Some(&url), Some(Arc::new(cb)))
+        .await
+        .unwrap());
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(*delays.lock().unwrap(), vec![Duration::from_secs(1)]);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    assert_eq!(
+        crate::util::parse_retry_after(" 120 "),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+        crate::util::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+        Some(Duration::ZERO)
+    );
+    assert_eq!(crate::util::parse_retry_after("soon"), None);
+}
+
+#[tokio::test]
+async fn test_download_timeout() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let body = std::fs::read(bundle_path).unwrap();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let server_url = {
+        let requests = Arc::clone(&requests);
+        spawn_test_server(move |_| {
+            let mut response = TestResponse::ok(&body);
+            // the first response stalls halfway through the body
+            if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                response.data.truncate(response.data.len() - body.len() / 2);
+                response = response.stalled();
+            }
+            response
+        })
+        .await
+    };
+    let url = format!("{}/Map_00_00.unity3d", server_url);
+
+    let options = crate::OperationOptions::default()
+        .with_download_timeout(Some(Duration::from_millis(500)), None);
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    let output_dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let cb = {
+        let attempts = Arc::clone(&attempts);
+        move |_: &Uuid, _: &str, progress: crate::ItemProgress| {
+            if let crate::ItemProgress::Downloading {
+                attempt,
+                max_attempts,
+                ..
+            } = progress
+            {
+                attempts.lock().unwrap().push((attempt, max_attempts));
+            }
+        }
+    };
+    let result = bundle_info
+        .validate_compressed_with_options(
+            &file_path,
+            None,
+            Some(&url),
+            Some(Arc::new(cb)),
+            &options,
+        )
+        .await;
+    assert!(result.unwrap());
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    // the stalled download is retried as the second attempt
+    let mut attempts = attempts.lock().unwrap().clone();
+    attempts.dedup();
+    assert_eq!(attempts, vec![(1, 5), (2, 5)]);
+}
+
+#[test]
+fn test_validate_empty_file() {
+    use crate::{FailReason, FileInfo};
+
+    let dir = TempDir::new();
+    let empty_path = format!("{}/empty.txt", dir.path());
+    std::fs::write(&empty_path, "").unwrap();
+    let full_path = format!("{}/full.txt", dir.path());
+    std::fs::write(&full_path, "full").unwrap();
+    let missing_path = format!("{}/missing.txt", dir.path());
+
+    let empty = FileInfo::build_file(&empty_path, &crate::OperationOptions::default());
+    let full = FileInfo::build_file(&full_path, &crate::OperationOptions::default());
+    let missing = FileInfo::build_file(&missing_path, &crate::OperationOptions::default());
+
+    // legitimately empty files validate
+    assert!(empty.validate(&empty).is_ok());
+    assert!(matches!(empty.validate(&full), Err(FailReason::Empty)));
+    assert!(matches!(missing.validate(&full), Err(FailReason::Missing)));
+    assert!(matches!(missing.validate(&empty), Err(FailReason::Missing)));
+    assert!(matches!(
+        full.validate(&empty),
+        Err(FailReason::BadSize {
+            expected: 0,
+            actual: 4
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_validate_verbose() {
+    use crate::FailReason;
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let expected = version
+        .get_bundle("Map_00_00.unity3d")
+        .unwrap()
+        .get_compressed_info();
+    let reference = "example_builds/compressed/good/Map_00_00.unity3d";
+
+    let dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", dir.path());
+    let mut data = std::fs::read(reference).unwrap();
+    data[1000] ^= 0xff;
+    std::fs::write(&file_path, &data).unwrap();
+
+    let first_mismatch = |result: Result<(), FailReason>| match result {
+        Err(FailReason::BadHash { first_mismatch, .. }) => first_mismatch,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert_eq!(
+        first_mismatch(expected.validate_verbose(&file_path, Some(reference)).await),
+        Some(1000)
+    );
+    assert_eq!(
+        first_mismatch(expected.validate_verbose(&file_path, None).await),
+        None
+    );
+    // a reference that isn't known-good is ignored
+    assert_eq!(
+        first_mismatch(
+            expected
+                .validate_verbose(&file_path, Some(&file_path))
+                .await
+        ),
+        None
+    );
+    assert!(expected
+        .validate_verbose(reference, Some(reference))
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn test_sha256sums() {
+    let reference = Version::from_manifest_file("example_manifest.json").unwrap();
+    let dir = TempDir::new();
+    let sums_path = format!("{}/SHA256SUMS", dir.path());
+    reference.export_sha256sums(&sums_path).unwrap();
+    let sums = std::fs::read_to_string(&sums_path).unwrap();
+    assert_eq!(sums.lines().count(), reference.get_bundles().count() + 1);
+
+    let asset_root = "example_builds/compressed/good/";
+    let version =
+        Version::from_sha256sums(&sums_path, "http://example.url/", Some(asset_root), None)
+            .await
+            .unwrap();
+    assert_eq!(version.merkle_root(), reference.merkle_root());
+    assert_eq!(
+        version.get_total_compressed_size(),
+        reference.get_total_compressed_size()
+    );
+    for (name, bundle_info) in reference.get_bundles() {
+        assert_eq!(
+            version.get_bundle(name).unwrap().get_compressed_info(),
+            bundle_info.get_compressed_info()
+        );
+    }
+    assert!(version
+        .validate_compressed(asset_root, None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        version
+            .validate_compressed("example_builds/compressed/bad/", None)
+            .await
+            .unwrap(),
+        vec!["Map_00_00.unity3d"]
+    );
+
+    // sizes come from the mirror without a local build, and binary-mode lines are accepted
+    std::fs::write(&sums_path, sums.replace("  ", " *")).unwrap();
+    let asset_url =
+        crate::util::file_path_to_uri(std::fs::canonicalize(asset_root).unwrap().to_str().unwrap());
+    let remote = Version::from_sha256sums(&sums_path, &asset_url, None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        remote.get_total_compressed_size(),
+        reference.get_total_compressed_size()
+    );
+
+    // a main file with another name isn't taken for a bundle
+    std::fs::write(&sums_path, sums.replace("main.unity3d", "game.unity3d")).unwrap();
+    let renamed_root = TempDir::new();
+    crate::util::copy_dir(asset_root, renamed_root.path(), false).unwrap();
+    std::fs::rename(
+        format!("{}/main.unity3d", renamed_root.path()),
+        format!("{}/game.unity3d", renamed_root.path()),
+    )
+    .unwrap();
+    let renamed = Version::from_sha256sums(
+        &sums_path,
+        "http://example.url/",
+        Some(renamed_root.path()),
+        Some("game.unity3d"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(renamed.get_main_file_name(), "game.unity3d");
+    assert_eq!(renamed.get_main_file_info(), reference.get_main_file_info());
+    assert!(renamed.get_bundle("game.unity3d").is_none());
+    assert!(renamed
+        .validate_compressed(renamed_root.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    std::fs::write(&sums_path, "not a checksum line\n").unwrap();
+    assert!(
+        Version::from_sha256sums(&sums_path, "http://example.url/", Some(asset_root), None)
+            .await
+            .is_err()
+    );
+
+    // a hand-edited manifest may have hashes of the wrong length
+    let mut edited = reference.clone();
+    edited
+        .bundles
+        .get_mut("Map_00_00.unity3d")
+        .unwrap()
+        .compressed_info
+        .hash = "abc".to_string();
+    edited.export_sha256sums(&sums_path).unwrap();
+    let sums = std::fs::read_to_string(&sums_path).unwrap();
+    let names: Vec<&str> = sums
+        .lines()
+        .map(|line| line.split_once("  ").unwrap().1)
+        .collect();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+    assert!(sums.contains("abc  Map_00_00.unity3d\n"));
+}
+
+#[tokio::test]
+async fn test_merkle_root() {
+    let asset_root = "example_builds/compressed/good/";
+    let version = Version::build(asset_root, "http://example.url/", None, None, None)
+        .await
+        .unwrap();
+    let root = version.merkle_root();
+    assert_eq!(root.len(), 64);
+    assert_eq!(version.get_content_root(), Some(root.as_str()));
+
+    // the root only depends on the files
+    let other = Version::build(asset_root, "http://other.url/", Some("Other"), None, None)
+        .await
+        .unwrap();
+    assert_ne!(other.get_uuid(), version.get_uuid());
+    assert_eq!(other.merkle_root(), root);
+
+    let reference = Version::from_manifest_file("example_manifest.json").unwrap();
+    assert_eq!(reference.get_content_root(), None);
+    assert_eq!(reference.merkle_root(), root);
+
+    let mut child = Version::build_barebones("http://example.url/child/", None).unwrap();
+    assert_ne!(child.merkle_root(), root);
+    child = child.resolve_with_parent(&reference);
+    assert_eq!(child.merkle_root(), root);
+}
+
+#[tokio::test]
+async fn test_from_manifest_url_verified() {
+    let body = std::fs::read("example_manifest.json").unwrap();
+    let hash = crate::util::get_buffer_hash(&body);
+    let server_url = spawn_test_server(move |_| TestResponse::ok(&body)).await;
+    let url = format!("{}/example_manifest.json", server_url);
+
+    let version = Version::from_manifest_url_verified(&url, &hash.to_uppercase())
+        .await
+        .unwrap();
+    assert_eq!(
+        version,
+        Version::from_manifest_file("example_manifest.json").unwrap()
+    );
+
+    let bad_hash = crate::util::get_buffer_hash(b"something else");
+    let err = Version::from_manifest_url_verified(&url, &bad_hash)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Bad hash"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_peek_metadata() {
+    let manifest_path = std::fs::canonicalize("example_manifest.json").unwrap();
+    let url = crate::util::file_path_to_uri(manifest_path.to_str().unwrap());
+    let summary = Version::peek_metadata(&url).await.unwrap();
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    assert_eq!(summary.get_uuid(), version.get_uuid());
+    assert_eq!(summary.get_name(), version.get_name());
+    assert_eq!(summary.get_description(), version.get_description());
+    assert_eq!(summary.is_hidden(), version.is_hidden());
+    assert_eq!(
+        summary.get_total_compressed_size(),
+        version.get_total_compressed_size()
+    );
+    assert_eq!(
+        summary.get_uncompressed_assets_size(),
+        version.get_uncompressed_assets_size()
+    );
+}
+
+#[tokio::test]
+async fn test_export_import_archive() {
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let archive_dir = TempDir::new();
+    let archive_path = format!("{}/build.zst", archive_dir.path());
+    version
+        .export_archive("example_builds/compressed/good/", &archive_path)
+        .unwrap();
+    let archive_info = version.get_archive_info().unwrap();
+    assert_eq!(
+        archive_info.get_size(),
+        std::fs::metadata(&archive_path).unwrap().len()
+    );
+
+    let output_dir = TempDir::new();
+    let mut unpacked = version
+        .import_archive(&archive_path, output_dir.path())
+        .unwrap();
+    unpacked.sort();
+    let mut expected: Vec<&str> = version.get_bundles().map(|(name, _)| name).collect();
+    expected.push("main.unity3d");
+    expected.sort();
+    assert_eq!(unpacked, expected);
+    assert!(version
+        .validate_compressed(output_dir.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // a build that doesn't validate can't be archived
+    assert!(version
+        .export_archive("example_builds/compressed/bad/", &archive_path)
+        .is_err());
+
+    let mut data = std::fs::read(&archive_path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    std::fs::write(&archive_path, data).unwrap();
+    assert!(version
+        .import_archive(&archive_path, output_dir.path())
+        .is_err());
+
+    // an unpacked file that fails validation is removed
+    let mut edited = version.clone();
+    edited
+        .export_archive("example_builds/compressed/good/", &archive_path)
+        .unwrap();
+    edited.archive_info = None;
+    edited
+        .bundles
+        .get_mut("Map_00_00.unity3d")
+        .unwrap()
+        .compressed_info
+        .hash = "0".repeat(64);
+    let output_dir = TempDir::new();
+    assert!(edited
+        .import_archive(&archive_path, output_dir.path())
+        .is_err());
+    assert!(!std::path::Path::new(&format!("{}/Map_00_00.unity3d", output_dir.path())).exists());
+
+    // the index length isn't trusted
+    let mut header = b"FFBA".to_vec();
+    header.extend_from_slice(&1u32.to_le_bytes());
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&archive_path, zstd::encode_all(&header[..], 3).unwrap()).unwrap();
+    let err = edited
+        .import_archive(&archive_path, output_dir.path())
+        .unwrap_err();
+    assert!(err.to_string().contains("Archive index"));
+}
+
+#[tokio::test]
+async fn test_repair_with_fallback() {
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    // nothing should be downloaded since the good build has everything
+    version.set_asset_url("http://invalid.invalid").unwrap();
+
+    let output_dir = TempDir::new();
+    let asset_root_bad = "example_builds/compressed/bad/";
+    for name in crate::util::list_filenames_in_directory(asset_root_bad).unwrap() {
+        std::fs::copy(
+            format!("{}/{}", asset_root_bad, name),
+            format!("{}/{}", output_dir.path(), name),
+        )
+        .unwrap();
+    }
+
+    let fallback_dirs = vec![
+        "example_builds/missing/".to_string(),
+        "example_builds/compressed/good/".to_string(),
+    ];
+    let repaired = version
+        .repair_with_fallback(output_dir.path(), &fallback_dirs, None)
+        .await
+        .unwrap();
+    assert_eq!(repaired, vec!["Map_00_00.unity3d"]);
+    assert!(version
+        .validate_compressed(output_dir.path(), None)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_generate_manifest_extra_extensions() {
+    let asset_root = TempDir::new();
+    std::fs::copy(
+        "example_builds/compressed/good/Map_00_00.unity3d",
+        format!("{}/Map_00_00.assetbundle", asset_root.path()),
+    )
+    .unwrap();
+
+    let version = crate::VersionBuilder::new()
+        .asset_url("http://example.url/")
+        .build(asset_root.path())
+        .await
+        .unwrap();
+    assert!(version.get_bundle("Map_00_00.assetbundle").is_none());
+
+    let version = crate::VersionBuilder::new()
+        .asset_url("http://example.url/")
+        .extra_bundle_extensions(&["assetbundle"])
+        .build(asset_root.path())
+        .await
+        .unwrap();
+    assert!(version.get_bundle("Map_00_00.assetbundle").is_some());
+}
+
+#[tokio::test]
+async fn test_hash_cache() {
+    use crate::util;
+
+    let dir = TempDir::new();
+    let file_path = format!("{}/file.bin", dir.path());
+    std::fs::write(&file_path, b"hello").unwrap();
+    let real_hash = util::get_file_hash(&file_path).unwrap();
+
+    // seed the cache with a bogus hash to check that it's consulted
+    let key = std::fs::canonicalize(&file_path)
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+    let metadata = std::fs::metadata(&file_path).unwrap();
+    let mtime = metadata
+        .modified()
+        .unwrap()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let cache_path = format!("{}/cache.json", dir.path());
+    let cache = serde_json::json!({ key.clone(): { "mtime": mtime, "size": 5, "hash": "cached" } });
+    std::fs::write(&cache_path, cache.to_string()).unwrap();
+
+    let hash_cache = util::HashCache::load(&cache_path).unwrap();
+    assert_eq!(hash_cache.get_file_hash(&file_path).unwrap(), "cached");
+
+    // a size change invalidates the entry
+    std::fs::write(&file_path, b"hello world").unwrap();
+    let new_hash = hash_cache.get_file_hash(&file_path).unwrap();
+    assert_ne!(new_hash, "cached");
+    assert_ne!(new_hash, real_hash);
+
+    hash_cache.save().unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&cache_path).unwrap()).unwrap();
+    assert_eq!(saved[&key]["hash"], new_hash);
+
+    // a validation pass fills and saves the cache it's given
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let build_cache_path = format!("{}/build_cache.json", dir.path());
+    let build_cache = std::sync::Arc::new(util::HashCache::load(&build_cache_path).unwrap());
+    let options = crate::OperationOptions::default().with_hash_cache(build_cache);
+    let corrupted = version
+        .repair_with_options("example_builds/compressed/good/", None, &options)
+        .await
+        .unwrap();
+    assert!(corrupted.is_empty());
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&build_cache_path).unwrap()).unwrap();
+    let map_path = std::fs::canonicalize("example_builds/compressed/good/Map_00_00.unity3d")
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+    assert_eq!(
+        saved[&map_path]["hash"],
+        version
+            .get_bundle("Map_00_00.unity3d")
+            .unwrap()
+            .compressed_info
+            .hash
+    );
+
+    // so do reports and builds
+    let report_cache_path = format!("{}/report_cache.json", dir.path());
+    let report_cache = std::sync::Arc::new(util::HashCache::load(&report_cache_path).unwrap());
+    let options = crate::OperationOptions::default().with_hash_cache(report_cache);
+    let report = version
+        .validate_compressed_report_with_options("example_builds/compressed/good/", None, &options)
+        .await
+        .unwrap();
+    assert!(report.get_corrupted().is_empty());
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&report_cache_path).unwrap()).unwrap();
+    assert!(saved.get(&map_path).is_some());
+
+    let gen_cache_path = format!("{}/gen_cache.json", dir.path());
+    let gen_cache = std::sync::Arc::new(util::HashCache::load(&gen_cache_path).unwrap());
+    Version::builder()
+        .asset_url("http://example.url/")
+        .options(crate::OperationOptions::default().with_hash_cache(gen_cache))
+        .build("example_builds/compressed/good/")
+        .await
+        .unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&gen_cache_path).unwrap()).unwrap();
+    assert!(saved.get(&map_path).is_some());
+}
+
+#[test]
+fn test_tree_hash() {
+    use sha2::{Digest, Sha256};
+
+    use crate::util::{self, HashAlgorithm};
+
+    let chunk_size = 16 * 1024 * 1024;
+    let data: Vec<u8> = (0..chunk_size + 100).map(|i| (i % 251) as u8).collect();
+    let dir = TempDir::new();
+    let file_path = format!("{}/file.bin", dir.path());
+    std::fs::write(&file_path, &data).unwrap();
+
+    let mut chunk_digests = Sha256::new();
+    chunk_digests.update(Sha256::digest(&data[..chunk_size]));
+    chunk_digests.update(Sha256::digest(&data[chunk_size..]));
+    let expected = format!("sha256-tree:{:x}", chunk_digests.finalize());
+    let hash = util::get_file_hash_with_algorithm(&file_path, HashAlgorithm::Sha256Tree).unwrap();
+    assert_eq!(hash, expected);
+    assert_eq!(HashAlgorithm::of(&hash), HashAlgorithm::Sha256Tree);
+    assert_eq!(
+        HashAlgorithm::of(&util::get_file_hash(&file_path).unwrap()),
+        HashAlgorithm::Sha256
+    );
+
+    // a file that fills its last chunk has no empty chunk after it
+    std::fs::write(&file_path, &data[..chunk_size]).unwrap();
+    let expected = format!(
+        "sha256-tree:{:x}",
+        Sha256::digest(Sha256::digest(&data[..chunk_size]))
+    );
+    let hash = util::get_file_hash_with_algorithm(&file_path, HashAlgorithm::Sha256Tree).unwrap();
+    assert_eq!(hash, expected);
+
+    // but an empty file is one empty chunk
+    std::fs::write(&file_path, b"").unwrap();
+    let expected = format!("sha256-tree:{:x}", Sha256::digest(Sha256::digest(b"")));
+    let hash = util::get_file_hash_with_algorithm(&file_path, HashAlgorithm::Sha256Tree).unwrap();
+    assert_eq!(hash, expected);
+
+    // hashing in parallel gives the same result as streaming
+    #[cfg(feature = "parallel-hash")]
+    {
+        use std::io::Write;
+
+        use crate::util::Hasher;
+
+        let size = util::PARALLEL_HASH_THRESHOLD as usize + chunk_size / 2;
+        let data: Vec<u8> = (0..size).map(|i| (i % 241) as u8).collect();
+        std::fs::write(&file_path, &data).unwrap();
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256Tree);
+        hasher.write_all(&data).unwrap();
+        let hash =
+            util::get_file_hash_with_algorithm(&file_path, HashAlgorithm::Sha256Tree).unwrap();
+        assert_eq!(hash, hasher.finalize());
+    }
+}
+
+#[tokio::test]
+async fn test_build_tree_hash() {
+    use crate::{util::HashAlgorithm, OperationOptions};
+
+    let asset_root_good = "example_builds/compressed/good/";
+    let asset_root_bad = "example_builds/compressed/bad/";
+    let asset_url = "http://example.url/builds/example_build/";
+    let options = OperationOptions::default().with_hash_algorithm(HashAlgorithm::Sha256Tree);
+    let version = Version::builder()
+        .asset_url(asset_url)
+        .options(options.clone())
+        .build(asset_root_good)
+        .await
+        .unwrap();
+    let bundle = version.get_bundle("Map_00_00.unity3d").unwrap();
+    assert_eq!(
+        HashAlgorithm::of(&bundle.compressed_info.hash),
+        HashAlgorithm::Sha256Tree
+    );
+
+    let corrupted = version
+        .validate_compressed(asset_root_good, None)
+        .await
+        .unwrap();
+    assert!(corrupted.is_empty());
+    let corrupted = version
+        .validate_compressed(asset_root_bad, None)
+        .await
+        .unwrap();
+    assert_eq!(corrupted, vec!["Map_00_00.unity3d"]);
+
+    // sha256sums can't hold tree hashes
+    let dir = TempDir::new();
+    let sums_path = format!("{}/SHA256SUMS", dir.path());
+    assert!(version.export_sha256sums(&sums_path).is_err());
+
+    // a reference hashed with plain SHA-256 still validates a tree-hashed build
+    let reference = Version::from_manifest_file("example_manifest.json").unwrap();
+    let (_, report, _) = Version::builder()
+        .asset_url(asset_url)
+        .options(options)
+        .build_validated(asset_root_bad, &reference)
+        .await
+        .unwrap();
+    assert_eq!(report.get_corrupted(), vec!["Map_00_00.unity3d"]);
+
+    // a cached hash made with another algorithm isn't reused
+    let cache_path = format!("{}/cache.json", dir.path());
+    let hash_cache = crate::util::HashCache::load(&cache_path).unwrap();
+    let map_path = format!("{}Map_00_00.unity3d", asset_root_good);
+    let sha256 = hash_cache.get_file_hash(&map_path).unwrap();
+    let tree = hash_cache
+        .get_file_hash_paced(&map_path, HashAlgorithm::Sha256Tree, None)
+        .unwrap();
+    assert_eq!(tree, bundle.compressed_info.hash);
+    assert_ne!(sha256, tree);
+}
+
+#[tokio::test]
+async fn test_validation_io_rate() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::{Duration, Instant};
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    // the build is a bit over 50 KiB, so this takes over a second
+    let options = crate::OperationOptions::default().with_validation_io_rate(32 * 1024);
+
+    // tests run on a single-threaded runtime, so the ticker only advances
+    // if pacing doesn't block it
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = {
+        let ticks = Arc::clone(&ticks);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                ticks.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+    };
+
+    let start = Instant::now();
+    let corrupted = version
+        .repair_with_options("example_builds/compressed/good/", None, &options)
+        .await
+        .unwrap();
+    let elapsed = start.elapsed();
+    ticker.abort();
+    assert!(corrupted.is_empty());
+    assert!(elapsed >= Duration::from_millis(800), "{:?}", elapsed);
+    assert!(ticks.load(Ordering::SeqCst) >= 10);
+}
+
+#[test]
+fn test_track_total_progress() {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{ItemProgress, TotalProgress};
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let last = Arc::new(Mutex::new(None));
+    let total_cb = {
+        let last = Arc::clone(&last);
+        move |_: &Uuid, total: TotalProgress| *last.lock().unwrap() = Some(total)
+    };
+    let cb = version.track_total_progress(None, Arc::new(total_cb));
+    let uuid = version.get_uuid();
+    let total_bytes = version.get_total_compressed_size();
+    let bundle_size = |name: &str| version.get_bundle(name).unwrap().compressed_info.size;
+
+    let map_size = bundle_size("Map_00_00.unity3d");
+    cb(
+        &uuid,
+        "Map_00_00.unity3d",
+        ItemProgress::Downloading {
+            bytes_downloaded: 1000,
+            total_bytes: map_size,
+            attempt: 1,
+            max_attempts: 5,
+        },
+    );
+    let total = last.lock().unwrap().unwrap();
+    assert_eq!((total.bytes_done, total.total_bytes), (1000, total_bytes));
+
+    // a restarted download doesn't count twice
+    cb(
+        &uuid,
+        "Map_00_00.unity3d",
+        ItemProgress::Downloading {
+            bytes_downloaded: 500,
+            total_bytes: map_size,
+            attempt: 2,
+            max_attempts: 5,
+        },
+    );
+    assert_eq!(last.lock().unwrap().unwrap().bytes_done, 500);
+
+    cb(
+        &uuid,
+        "Map_00_00.unity3d",
+        ItemProgress::Passed {
+            item_size: map_size,
+            duration: Duration::ZERO,
+            was_downloaded: false,
+        },
+    );
+    let other_size = bundle_size("Map_01_03.unity3d");
+    cb(
+        &uuid,
+        "Map_01_03.unity3d",
+        ItemProgress::Passed {
+            item_size: other_size,
+            duration: Duration::ZERO,
+            was_downloaded: false,
+        },
+    );
+    assert_eq!(
+        last.lock().unwrap().unwrap().bytes_done,
+        map_size + other_size
+    );
+}
+
+#[test]
+fn test_format_rfc3339() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::util::format_rfc3339;
+
+    assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3661);
+    assert_eq!(format_rfc3339(leap_day), "2000-02-29T01:01:01Z");
+    let new_years_eve = UNIX_EPOCH + Duration::from_secs(1_735_689_599);
+    assert_eq!(format_rfc3339(new_years_eve), "2024-12-31T23:59:59Z");
+}
+
+#[test]
+fn test_mirror_rankings() {
+    use std::time::Duration;
+
+    use crate::util;
+
+    let fast = "http://fast.mirror.test/main.unity3d";
+    let slow = "http://slow.mirror.test:8080/main.unity3d";
+    let broken = "https://broken.mirror.test/main.unity3d";
+    util::record_mirror_download(fast, 1_000_000, Duration::from_secs(1));
+    util::record_mirror_download(slow, 1_000, Duration::from_secs(1));
+    util::record_mirror_download(broken, 10_000_000, Duration::from_secs(1));
+    util::record_mirror_failure(broken);
+
+    // other tests may have downloaded from real mirrors
+    let rankings: Vec<_> = util::get_mirror_rankings()
+        .into_iter()
+        .filter(|stats| stats.origin.contains(".mirror.test"))
+        .collect();
+    let origins: Vec<_> = rankings.iter().map(|stats| stats.origin.as_str()).collect();
+    assert_eq!(
+        origins,
+        vec![
+            "http://fast.mirror.test",
+            "http://slow.mirror.test:8080",
+            "https://broken.mirror.test"
+        ]
+    );
+    assert_eq!(rankings[0].bytes_per_sec, 1_000_000.0);
+    assert_eq!(rankings[2].failures, 1);
+
+    // a successful download clears the demotion
+    util::record_mirror_download(broken, 3_000_000, Duration::from_secs(1));
+    let best = util::get_mirror_rankings()
+        .into_iter()
+        .find(|stats| stats.origin.contains(".mirror.test"))
+        .unwrap();
+    assert_eq!(best.origin, "https://broken.mirror.test");
+    assert_eq!(best.bytes_per_sec, 0.3 * 3_000_000.0 + 0.7 * 10_000_000.0);
+}
+
+#[tokio::test]
+async fn test_mirror_selection() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    async fn serve(body: Option<Vec<u8>>, requests: Arc<AtomicUsize>) -> String {
+        let server_url = spawn_test_server(move |_| {
+            requests.fetch_add(1, Ordering::SeqCst);
+            match &body {
+                Some(body) => TestResponse::ok(body),
+                None => TestResponse::new("500 Internal Server Error", &[], b""),
+            }
+        })
+        .await;
+        format!("{}/build", server_url)
+    }
+
+    let body = std::fs::read("example_builds/compressed/good/Map_00_00.unity3d").unwrap();
+    let broken_requests = Arc::new(AtomicUsize::new(0));
+    let broken_url = serve(None, Arc::clone(&broken_requests)).await;
+    let good_requests = Arc::new(AtomicUsize::new(0));
+    let good_url = serve(Some(body), Arc::clone(&good_requests)).await;
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    let output_dir = TempDir::new();
+    let file_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let options = crate::OperationOptions::default().with_mirrors(&[&good_url]);
+
+    // neither mirror has been measured, so the build's own asset URL goes first
+    // and the failure moves the retry to the other mirror
+    let repaired = bundle_info
+        .validate_compressed_with_options(
+            &file_path,
+            None,
+            Some(&format!("{}/Map_00_00.unity3d", broken_url)),
+            None,
+            &options,
+        )
+        .await
+        .unwrap();
+    assert!(repaired);
+    assert_eq!(broken_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(good_requests.load(Ordering::SeqCst), 1);
+
+    // the failing mirror stays demoted for later downloads
+    std::fs::remove_file(&file_path).unwrap();
+    bundle_info
+        .validate_compressed_with_options(
+            &file_path,
+            None,
+            Some(&format!("{}/Map_00_00.unity3d", broken_url)),
+            None,
+            &options,
+        )
+        .await
+        .unwrap();
+    assert_eq!(broken_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(good_requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_file_uris() {
+    use crate::util;
+
+    #[cfg(windows)]
+    assert_eq!(
+        util::file_uri_to_path("file:///C:/builds/main.unity3d").unwrap(),
+        "C:\\builds\\main.unity3d"
+    );
+    #[cfg(unix)]
+    assert_eq!(
+        util::file_uri_to_path("file:///srv/builds/main%20data.unity3d").unwrap(),
+        "/srv/builds/main data.unity3d"
+    );
+    assert!(util::file_uri_to_path("http://example.url/main.unity3d").is_none());
+
+    assert!(util::is_http_url("http://example.url/main.unity3d"));
+    assert!(util::is_http_url("HTTPS://example.url/main.unity3d"));
+    assert!(!util::is_http_url("http_mirror/main.unity3d"));
+    assert!(!util::is_http_url("file:///srv/builds/main.unity3d"));
+
+    let manifest_path = std::fs::canonicalize("example_manifest.json").unwrap();
+    let manifest_uri = util::file_path_to_uri(manifest_path.to_str().unwrap());
+    assert_eq!(
+        util::file_uri_to_path(&manifest_uri).unwrap(),
+        manifest_path.to_str().unwrap()
+    );
+
+    let version = Version::from_manifest(&manifest_uri).await.unwrap();
+    assert_eq!(
+        version,
+        Version::from_manifest_file("example_manifest.json").unwrap()
+    );
+
+    let temp_file = util::TempFile::download(&manifest_uri).await.unwrap();
+    assert_eq!(
+        std::fs::read(temp_file.path()).unwrap(),
+        std::fs::read(&manifest_path).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_temp_dir_in() {
+    let temp_root = TempDir::new();
+    let root_path = std::path::Path::new(temp_root.path());
+
+    let temp_dir = TempDir::new_in(root_path);
+    assert!(std::path::Path::new(temp_dir.path()).starts_with(root_path));
+
+    let manifest_path = std::fs::canonicalize("example_manifest.json").unwrap();
+    let manifest_uri = crate::util::file_path_to_uri(manifest_path.to_str().unwrap());
+    let temp_file = crate::util::TempFile::download_in(&manifest_uri, root_path)
+        .await
+        .unwrap();
+    assert!(std::path::Path::new(temp_file.path()).starts_with(root_path));
+
+    let (dir_path, file_path) = (temp_dir.path().to_string(), temp_file.path().to_string());
+    drop(temp_dir);
+    drop(temp_file);
+    assert!(!std::fs::exists(dir_path).unwrap());
+    assert!(!std::fs::exists(file_path).unwrap());
+}
+
+#[tokio::test]
+async fn test_validate_self() {
+    let example_manifest = "example_manifest.json";
+    let version = Version::from_manifest_file(example_manifest).unwrap();
+    assert!(version.validate_self().is_empty());
+
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(example_manifest).unwrap()).unwrap();
+    json["total_compressed_size"] = serde_json::json!(1);
+    json["bundles"]["Map_00_00.unity3d"]["compressed_info"]["hash"] = serde_json::json!("");
+    json.as_object_mut().unwrap().remove("main_file_info");
+
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/manifest.json", output_dir.path());
+    std::fs::write(&output_path, json.to_string()).unwrap();
+
+    let version = Version::from_manifest(&output_path).await.unwrap();
+    assert_eq!(version.validate_self().len(), 3);
+    assert!(Version::from_manifest_checked(&output_path).await.is_err());
+}
+
+#[test]
+fn test_compressed_manifests() {
+    let example_manifest = "example_manifest.json";
+    let version = Version::from_manifest_file(example_manifest).unwrap();
+    let plain_size = std::fs::metadata(example_manifest).unwrap().len();
+
+    let output_dir = TempDir::new();
+    for extension in ["json.gz", "json.zst"] {
+        let output_path = format!("{}/manifest.{}", output_dir.path(), extension);
+        version.export_manifest(&output_path).unwrap();
+        assert!(std::fs::metadata(&output_path).unwrap().len() < plain_size);
+        assert_eq!(Version::from_manifest_file(&output_path).unwrap(), version);
+    }
+
+    let output_path = format!("{}/manifest.json", output_dir.path());
+    version.export_manifest(&output_path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&output_path).unwrap(),
+        serde_json::to_string_pretty(&version).unwrap()
+    );
+}
+
+#[test]
+fn test_packed_uncompressed_info() {
+    let example_manifest = "example_manifest.json";
+    let mut version = Version::from_manifest_file(example_manifest).unwrap();
+    let plain_json = serde_json::to_string_pretty(&version).unwrap();
+
+    version.set_pack_uncompressed_info(true);
+    let packed_json = serde_json::to_string_pretty(&version).unwrap();
+    assert!(packed_json.contains("uncompressed_info_packed"));
+    assert!(!packed_json.contains("CustomAssetBundle-"));
+
+    let unpacked: Version = serde_json::from_str(&packed_json).unwrap();
+    assert_eq!(unpacked, version);
+
+    version.set_pack_uncompressed_info(false);
+    assert_eq!(serde_json::to_string_pretty(&version).unwrap(), plain_json);
+}
+
+#[tokio::test]
+async fn test_generate_manifest_progress() {
+    use std::sync::{Arc, Mutex};
+
+    let asset_root = "example_builds/compressed/good/";
+    let asset_url = "http://example.url/builds/example_build/";
+
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let cb = {
+        let processed = Arc::clone(&processed);
+        move |name: &str, count: usize, total: usize, compressed: u64, uncompressed: u64| {
+            processed.lock().unwrap().push((
+                name.to_string(),
+                count,
+                total,
+                compressed,
+                uncompressed,
+            ));
+        }
+    };
+    let version =
+        Version::build_with_progress(asset_root, asset_url, None, None, None, Some(Arc::new(cb)))
+            .await
+            .unwrap();
+
+    let processed = processed.lock().unwrap();
+    assert_eq!(processed.len(), 4);
+    let (_, count, total, compressed, uncompressed) = processed.iter().max_by_key(|p| p.1).unwrap();
+    assert_eq!((*count, *total), (4, 4));
+    assert_eq!(*compressed, version.get_compressed_assets_size());
+    assert_eq!(*uncompressed, version.get_uncompressed_assets_size());
+}
+
+#[cfg(feature = "lzma")]
+#[tokio::test]
+async fn test_build_with_warnings() {
+    use std::io::Write;
+
+    use crate::BuildWarning;
+
+    let asset_root = "example_builds/compressed/good/";
+    let build_dir = TempDir::new();
+    for name in crate::util::list_filenames_in_directory(asset_root).unwrap() {
+        std::fs::copy(
+            format!("{}/{}", asset_root, name),
+            format!("{}/{}", build_dir.path(), name),
+        )
+        .unwrap();
+    }
+    let bundle_path = format!("{}/Map_00_00.unity3d", build_dir.path());
+    let file_size = std::fs::metadata(&bundle_path).unwrap().len();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&bundle_path)
+        .unwrap()
+        .write_all(b"trailing garbage")
+        .unwrap();
+
+    let (version, warnings) = Version::builder()
+        .asset_url("http://example.url/")
+        .build_with_warnings(build_dir.path())
+        .await
+        .unwrap();
+    assert_eq!(
+        warnings,
+        vec![BuildWarning::BundleSizeMismatch {
+            bundle: "Map_00_00.unity3d".to_string(),
+            header_size: file_size,
+            file_size: file_size + 16,
+        }]
+    );
+    assert!(version.get_bundle("Map_00_00.unity3d").is_some());
+
+    let (_, warnings) = Version::builder()
+        .asset_url("http://example.url/")
+        .build_with_warnings(asset_root)
+        .await
+        .unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_read_stringz_latin1() {
+    use crate::bundle::read_stringz;
+
+    let mut reader = std::io::Cursor::new(b"CustomAssetBundle\0caf\xe9.png\0".to_vec());
+    assert_eq!(read_stringz(&mut reader).unwrap(), "CustomAssetBundle");
+    assert_eq!(read_stringz(&mut reader).unwrap(), "caf\u{e9}.png");
+}
+
+#[cfg(feature = "lzma")]
+#[tokio::test]
+async fn test_extract_bundle() {
+    use crate::bundle::AssetBundle;
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let output_dir = TempDir::new();
+
+    let (_, bundle) = AssetBundle::from_file(bundle_path).unwrap();
+    bundle.extract_files(output_dir.path()).unwrap();
+
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+
+    let corrupted = bundle_info
+        .validate_uncompressed(output_dir.path(), Some(version.get_uuid()), None)
+        .unwrap();
+    assert!(corrupted.is_empty());
+}
+
+#[cfg(feature = "lzma")]
+#[tokio::test]
+async fn test_extract_bundle_with_callback() {
+    use crate::bundle::AssetBundle;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+    fn cb(level_idx: usize, file: usize, total_files: usize, current_file_name: String) {
+        assert_eq!(level_idx, 0);
+        assert!(file <= total_files);
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        if file == total_files {
+            assert_eq!(current_file_name, "Done");
+            DONE.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let output_dir = TempDir::new();
+
+    let (_, bundle) = AssetBundle::from_file(bundle_path).unwrap();
+    bundle
+        .extract_files_with_callback(output_dir.path(), Some(cb))
+        .unwrap();
+
+    let num_files = bundle.get_num_files(0).unwrap();
+    assert_eq!(CALLS.load(Ordering::SeqCst), num_files + 1);
+    assert_eq!(DONE.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_replace_bundle_file() {
+    use crate::bundle::AssetBundle;
+
+    let mut bundle = AssetBundle::from_files(vec![
+        vec![("a.txt".to_string(), b"short".to_vec())],
+        vec![
+            ("b.txt".to_string(), b"before".to_vec()),
+            ("c.txt".to_string(), b"unchanged".to_vec()),
+        ],
+    ]);
+    bundle.recalculate_all_hashes();
+    bundle
+        .replace_file("b.txt", b"a much longer replacement".to_vec(), false)
+        .unwrap();
+    assert!(bundle
+        .replace_file("d.txt", b"new".to_vec(), false)
+        .is_err());
+    bundle.replace_file("d.txt", b"new".to_vec(), true).unwrap();
+
+    let info = bundle.get_uncompressed_info(1).unwrap();
+    assert_eq!(
+        info["b.txt"].hash,
+        crate::util::get_buffer_hash(b"a much longer replacement")
+    );
+
+    let temp_dir = TempDir::new();
+    let bundle_path = format!("{}/patched.unity3d", temp_dir.path());
+    bundle
+        .to_file(&bundle_path, &Default::default(), &Default::default(), None)
+        .unwrap();
+    let (_, patched) = AssetBundle::from_file(&bundle_path).unwrap();
+    assert_eq!(patched, bundle);
+    assert_eq!(patched.get_num_files(1).unwrap(), 3);
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_extract_bundle_flat() {
+    use crate::bundle::{AssetBundle, ExtractLayout};
+
+    let bundle = AssetBundle::from_files(vec![
+        vec![("same.txt".to_string(), b"level 0".to_vec())],
+        vec![("same.txt".to_string(), b"level 1".to_vec())],
+    ]);
+    let output_dir = TempDir::new();
+    bundle
+        .extract_files_with_layout(output_dir.path(), ExtractLayout::Flat)
+        .unwrap();
+    let mut names = crate::util::list_filenames_in_directory(output_dir.path()).unwrap();
+    names.sort();
+    assert_eq!(names, vec!["level0__same.txt", "level1__same.txt"]);
+    assert_eq!(
+        std::fs::read(format!("{}/level1__same.txt", output_dir.path())).unwrap(),
+        b"level 1"
+    );
+
+    // single-level bundles aren't prefixed
+    let bundle = AssetBundle::from_files(vec![vec![("only.txt".to_string(), b"only".to_vec())]]);
+    let output_dir = TempDir::new();
+    bundle
+        .extract_files_with_layout(output_dir.path(), ExtractLayout::Flat)
+        .unwrap();
+    assert_eq!(
+        crate::util::list_filenames_in_directory(output_dir.path()).unwrap(),
+        vec!["only.txt"]
+    );
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_extract_bundle_parallel() {
+    use crate::bundle::AssetBundle;
+
+    let bundle = AssetBundle::from_files(vec![
+        (0..100)
+            .map(|i| (format!("file{}.txt", i), vec![i as u8; i * 10]))
+            .collect(),
+        vec![("other.txt".to_string(), b"other".to_vec())],
+    ]);
+    let serial_dir = TempDir::new();
+    bundle.extract_files(serial_dir.path()).unwrap();
+    for threads in [0, 1, 4] {
+        let parallel_dir = TempDir::new();
+        bundle
+            .extract_files_parallel(parallel_dir.path(), threads)
+            .unwrap();
+        for level in ["level0", "level1"] {
+            let serial = format!("{}/{}", serial_dir.path(), level);
+            let parallel = format!("{}/{}", parallel_dir.path(), level);
+            let mut names = crate::util::list_filenames_in_directory(&serial).unwrap();
+            names.sort();
+            let mut parallel_names = crate::util::list_filenames_in_directory(&parallel).unwrap();
+            parallel_names.sort();
+            assert_eq!(names, parallel_names);
+            for name in names {
+                assert_eq!(
+                    std::fs::read(format!("{}/{}", serial, name)).unwrap(),
+                    std::fs::read(format!("{}/{}", parallel, name)).unwrap()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_extract_bundle_rejects_traversal() {
+    use crate::bundle::AssetBundle;
+
+    let root_dir = TempDir::new();
+    let output_dir = format!("{}/out", root_dir.path());
+    for name in [
+        "../evil.txt",
+        "sub/../../evil.txt",
+        "..\\evil.txt",
+        "/tmp/evil.txt",
+        "",
+    ] {
+        let bundle = AssetBundle::from_files(vec![vec![
+            ("good.txt".to_string(), b"good".to_vec()),
+            (name.to_string(), b"evil".to_vec()),
+        ]]);
+        let err = bundle.extract_files(&output_dir).unwrap_err();
+        assert!(err.contains("Unsafe file name"), "{}", err);
+    }
+    assert!(!std::path::Path::new(&format!("{}/evil.txt", root_dir.path())).exists());
+    // nothing is written if any name is unsafe
+    assert!(!std::path::Path::new(&format!("{}/good.txt", output_dir)).exists());
+}
+
 #[cfg(feature = "lzma")]
 #[tokio::test]
 async fn test_repack_bundle() {
@@ -115,9 +2760,119 @@ async fn test_repack_bundle() {
     og_bundle.extract_files(output_dir.path()).unwrap();
 
     let repacked_bundle = AssetBundle::from_directory(output_dir.path()).unwrap();
+    assert_eq!(og_bundle.diff(&repacked_bundle), vec![]);
     assert!(og_bundle == repacked_bundle);
 }
 
+#[cfg(feature = "lzma")]
+#[test]
+fn test_verify_round_trip() {
+    use crate::bundle::{AssetBundle, BundleFileDiff};
+
+    let (_, bundle) =
+        AssetBundle::from_file("example_builds/compressed/good/Map_00_00.unity3d").unwrap();
+    assert_eq!(bundle.verify_round_trip().unwrap(), vec![]);
+
+    // only one of the duplicates can be extracted
+    let bundle = AssetBundle::from_files(vec![vec![
+        ("asset".to_string(), b"first".to_vec()),
+        ("asset".to_string(), b"second".to_vec()),
+    ]]);
+    let diffs = bundle.verify_round_trip().unwrap();
+    assert!(matches!(
+        diffs.as_slice(),
+        [BundleFileDiff::SizeMismatch { name, .. }] if name == "asset"
+    ));
+
+    // files are repacked in name order, so anything else is reported as moved
+    let bundle = AssetBundle::from_files(vec![vec![
+        ("b".to_string(), b"b".to_vec()),
+        ("a".to_string(), b"a".to_vec()),
+        ("c".to_string(), b"c".to_vec()),
+    ]]);
+    let diffs = bundle.verify_round_trip().unwrap();
+    assert_eq!(
+        diffs,
+        vec![
+            BundleFileDiff::Moved {
+                level: 0,
+                name: "b".to_string(),
+                index: 0,
+                other_index: 1,
+            },
+            BundleFileDiff::Moved {
+                level: 0,
+                name: "a".to_string(),
+                index: 1,
+                other_index: 0,
+            },
+        ]
+    );
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_repack_latin1_name() {
+    use std::io::{Read, Write};
+
+    use liblzma::stream::{LzmaOptions, Stream};
+
+    use crate::bundle::{AssetBundle, BundleFileDiff, HeaderVersions, LzmaParams};
+
+    fn decompress_level(bundle: &[u8]) -> Vec<u8> {
+        let header_size = u32::from_be_bytes(bundle[38..42].try_into().unwrap()) as usize;
+        let stream = Stream::new_lzma_decoder(u64::MAX).unwrap();
+        let mut level = Vec::new();
+        liblzma::read::XzDecoder::new_stream(&bundle[header_size..], stream)
+            .read_to_end(&mut level)
+            .unwrap();
+        level
+    }
+
+    // names that aren't valid UTF-8 can't be packed, so patch one into a packed bundle
+    let bundle = AssetBundle::from_files(vec![vec![("cafe.png".to_string(), b"data".to_vec())]]);
+    let mut data = bundle
+        .to_buffer(&LzmaParams::default(), &HeaderVersions::default(), None)
+        .unwrap();
+    let mut level = decompress_level(&data);
+    let name_start = level.windows(8).position(|w| w == b"cafe.png").unwrap();
+    level[name_start + 3] = 0xE9;
+    let stream = Stream::new_lzma_encoder(&LzmaOptions::new_preset(6).unwrap()).unwrap();
+    let mut encoder = liblzma::write::XzEncoder::new_stream(Vec::new(), stream);
+    encoder.write_all(&level).unwrap();
+    let mut compressed = encoder.finish().unwrap();
+    compressed[5..13].copy_from_slice(&(level.len() as u64).to_le_bytes());
+    let header_size = u32::from_be_bytes(data[38..42].try_into().unwrap());
+    let bundle_size = header_size + compressed.len() as u32;
+    data.truncate(header_size as usize);
+    data[34..38].copy_from_slice(&bundle_size.to_be_bytes()); // min streamed bytes
+    data[50..54].copy_from_slice(&(compressed.len() as u32).to_be_bytes()); // compressed end
+    data[58..62].copy_from_slice(&bundle_size.to_be_bytes()); // bundle size
+    data.extend_from_slice(&compressed);
+
+    let dir = TempDir::new();
+    let path = format!("{}/latin1.unity3d", dir.path());
+    std::fs::write(&path, &data).unwrap();
+    let (_, bundle) = AssetBundle::from_file(&path).unwrap();
+
+    // the name is decoded as Latin-1, but written back as it was
+    let repacked = bundle
+        .to_buffer(&LzmaParams::default(), &HeaderVersions::default(), None)
+        .unwrap();
+    let repacked_level = decompress_level(&repacked);
+    assert!(repacked_level.windows(9).any(|w| w == b"caf\xE9.png\0"));
+    assert_eq!(repacked_level, level);
+
+    // extracting it gives it a UTF-8 name, which repacking can't undo
+    assert_eq!(
+        bundle.verify_round_trip().unwrap(),
+        vec![BundleFileDiff::NameMismatch {
+            level: 0,
+            name: "caf\u{e9}.png".to_string(),
+        }]
+    );
+}
+
 #[cfg(feature = "lzma")]
 #[tokio::test]
 async fn test_pack_bundle() {
@@ -130,3 +2885,754 @@ async fn test_pack_bundle() {
     let packed_bundle = AssetBundle::from_directory(unpacked_path).unwrap();
     assert!(og_bundle == packed_bundle);
 }
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_duplicates() {
+    use crate::bundle::AssetBundle;
+
+    let input_dir = TempDir::new();
+    let level0_dir = format!("{}/level0", input_dir.path());
+    std::fs::create_dir(&level0_dir).unwrap();
+    std::fs::write(format!("{}/asset", level0_dir), b"level0").unwrap();
+    std::fs::write(format!("{}/asset", input_dir.path()), b"loose").unwrap();
+
+    assert!(AssetBundle::from_directory(input_dir.path()).is_ok());
+    let err = AssetBundle::from_directory_strict(input_dir.path()).unwrap_err();
+    assert!(err.contains("asset (level 0)"));
+}
+
+#[cfg(feature = "lzma")]
+#[tokio::test]
+async fn test_pack_bundle_dict_size() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+
+    let (_, og_bundle) = AssetBundle::from_file(bundle_path).unwrap();
+    let params = LzmaParams {
+        dict_size: 1 << 26,
+        ..Default::default()
+    };
+    og_bundle
+        .to_file(&output_path, &params, &HeaderVersions::default(), None)
+        .unwrap();
+
+    let (_, repacked_bundle) = AssetBundle::from_file(&output_path).unwrap();
+    assert!(og_bundle == repacked_bundle);
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_header_versions() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    let unpacked_path = "example_builds/uncompressed/good/map_5f00_5f00_2eunity3d";
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+
+    let bundle = AssetBundle::from_directory(unpacked_path).unwrap();
+    let versions = HeaderVersions {
+        player_version: "fusion-2.1.0".to_string(),
+        engine_version: "2.5.5f1".to_string(),
+    };
+    bundle
+        .to_file(&output_path, &LzmaParams::default(), &versions, None)
+        .unwrap();
+
+    let (header, repacked_bundle) = AssetBundle::from_file(&output_path).unwrap();
+    let header = header.to_string();
+    assert!(header.contains("Player version: fusion-2.1.0"));
+    assert!(header.contains("Engine version: 2.5.5f1"));
+    assert!(bundle == repacked_bundle);
+
+    let versions = HeaderVersions {
+        engine_version: "3.0.0".to_string(),
+        ..Default::default()
+    };
+    let bad_output_path = format!("{}/Map_00_00_bad.unity3d", output_dir.path());
+    assert!(bundle
+        .to_file(&bad_output_path, &LzmaParams::default(), &versions, None)
+        .is_err());
+    assert!(!std::path::Path::new(&bad_output_path).exists());
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_multiple_levels() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    let input_dir = TempDir::new();
+    for (level, name, size) in [(0, "a", 50000), (1, "b", 70000), (1, "c", 3000)] {
+        let level_dir = format!("{}/level{}", input_dir.path(), level);
+        std::fs::create_dir_all(&level_dir).unwrap();
+        let data: Vec<u8> = (0..size).map(|i| (i * 7 % 251) as u8).collect();
+        std::fs::write(format!("{}/{}", level_dir, name), data).unwrap();
+    }
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/bundle.unity3d", output_dir.path());
+
+    let bundle = AssetBundle::from_directory(input_dir.path()).unwrap();
+    bundle
+        .to_file(
+            &output_path,
+            &LzmaParams::default(),
+            &HeaderVersions::default(),
+            None,
+        )
+        .unwrap();
+
+    let (_, repacked_bundle) = AssetBundle::from_file(&output_path).unwrap();
+    assert!(bundle == repacked_bundle);
+    assert_eq!(repacked_bundle.get_num_files(1).unwrap(), 2);
+
+    let level_dir = TempDir::new();
+    repacked_bundle.extract_level(1, level_dir.path()).unwrap();
+    let mut names = crate::util::list_filenames_in_directory(level_dir.path()).unwrap();
+    names.sort();
+    assert_eq!(names, vec!["b", "c"]);
+    assert!(repacked_bundle.extract_level(2, level_dir.path()).is_err());
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_parallel_matches_serial() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    // written by the serial writer from before levels were compressed in parallel
+    let serial = std::fs::read("example_builds/fixtures/multi_level.unity3d").unwrap();
+    let bundle = AssetBundle::from_files(vec![
+        vec![
+            (
+                "a.txt".to_string(),
+                (0..50000).map(|i| (i * 7 % 251) as u8).collect(),
+            ),
+            ("b.bin".to_string(), vec![1; 3000]),
+        ],
+        vec![(
+            "c".to_string(),
+            (0..70000).map(|i| (i * 13 % 256) as u8).collect(),
+        )],
+        vec![("d".to_string(), b"level two".to_vec())],
+    ]);
+    for threads in [0, 1, 2, 8] {
+        let parallel = bundle
+            .to_buffer_parallel(
+                &LzmaParams::default(),
+                &HeaderVersions::default(),
+                None,
+                threads,
+            )
+            .unwrap();
+        assert_eq!(parallel, serial, "{} threads", threads);
+    }
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_read_bundle_lenient() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    let data: Vec<u8> = (0..50000).map(|i| (i * 7 % 251) as u8).collect();
+    let bundle = AssetBundle::from_files(vec![
+        vec![("a".to_string(), data.clone())],
+        vec![("b".to_string(), data)],
+    ]);
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/bundle.unity3d", output_dir.path());
+    bundle
+        .to_file(
+            &output_path,
+            &LzmaParams::default(),
+            &HeaderVersions::default(),
+            None,
+        )
+        .unwrap();
+
+    // scramble the compressed data of the first level, past the LZMA header
+    let (header, _) = AssetBundle::from_file(&output_path).unwrap();
+    let level_start = header.get_header_size() as usize;
+    let mut bytes = std::fs::read(&output_path).unwrap();
+    for byte in &mut bytes[level_start + 13..level_start + 64] {
+        *byte = 0xFF;
+    }
+    std::fs::write(&output_path, bytes).unwrap();
+
+    assert!(AssetBundle::from_file(&output_path).is_err());
+    let (_, salvaged, failed_levels) = AssetBundle::from_file_lenient(&output_path).unwrap();
+    assert_eq!(failed_levels.len(), 1);
+    assert_eq!(failed_levels[0].0, 0);
+    assert_eq!(salvaged.get_num_files(0).unwrap(), 0);
+    assert_eq!(salvaged.get_num_files(1).unwrap(), 1);
+    assert_eq!(
+        salvaged.get_uncompressed_info(1).unwrap(),
+        bundle.get_uncompressed_info(1).unwrap()
+    );
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_read_stream_version_3() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    let bundle = AssetBundle::from_files(vec![vec![("a.txt".to_string(), b"hello".to_vec())]]);
+    let v2 = bundle
+        .to_buffer(&LzmaParams::default(), &HeaderVersions::default(), None)
+        .unwrap();
+
+    // with the default versions and one level, the bundle size field ends at byte 62,
+    // followed by 2 bytes of padding. version 3 adds a field there, growing the header by 4 bytes
+    let patch_u32 = |bytes: &mut Vec<u8>, offset: usize, value: u32| {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    };
+    let read_u32 = |bytes: &[u8], offset: usize| {
+        u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    };
+    let mut v3 = v2[..62].to_vec();
+    v3.extend_from_slice(&42u32.to_be_bytes());
+    v3.extend_from_slice(&v2[62..]);
+    patch_u32(&mut v3, 9, 3);
+    for offset in [34, 38, 58] {
+        let value = read_u32(&v3, offset);
+        patch_u32(&mut v3, offset, value + 4);
+    }
+
+    let output_dir = TempDir::new();
+    let v3_path = format!("{}/v3.unity3d", output_dir.path());
+    std::fs::write(&v3_path, &v3).unwrap();
+    let (header, read_bundle) = AssetBundle::from_file(&v3_path).unwrap();
+    assert_eq!(header.get_stream_version(), 3);
+    assert_eq!(header.get_data_header_size(), Some(42));
+    assert_eq!(header.get_bundle_size() as usize, v3.len());
+    assert_eq!(read_bundle, bundle);
+
+    let v2_path = format!("{}/v2.unity3d", output_dir.path());
+    std::fs::write(&v2_path, &v2).unwrap();
+    let (header, _) = AssetBundle::from_file(&v2_path).unwrap();
+    assert_eq!(header.get_data_header_size(), None);
+
+    // unknown versions are still rejected
+    let mut v4 = v2.clone();
+    patch_u32(&mut v4, 9, 4);
+    let v4_path = format!("{}/v4.unity3d", output_dir.path());
+    std::fs::write(&v4_path, &v4).unwrap();
+    assert!(AssetBundle::from_file(&v4_path)
+        .unwrap_err()
+        .contains("Unsupported stream version"));
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_verify_bundle_structure() {
+    use crate::bundle::AssetBundle;
+
+    for bundle_name in [
+        "Map_00_00.unity3d",
+        "Map_01_03.unity3d",
+        "DongResources_00_09.resourceFile",
+    ] {
+        let bundle_path = format!("example_builds/compressed/good/{}", bundle_name);
+        AssetBundle::verify_structure(&bundle_path).unwrap();
+    }
+
+    assert!(
+        AssetBundle::verify_structure("example_builds/compressed/bad/Map_00_00.unity3d").is_err()
+    );
+
+    let temp_dir = TempDir::new();
+    let bundle_path = format!("{}/truncated.unity3d", temp_dir.path());
+    let bytes = std::fs::read("example_builds/compressed/good/Map_00_00.unity3d").unwrap();
+    std::fs::write(&bundle_path, &bytes[..bytes.len() / 2]).unwrap();
+    assert!(AssetBundle::verify_structure(&bundle_path)
+        .unwrap_err()
+        .contains("truncated"));
+    assert!(AssetBundle::verify_structure("example_builds/compressed/good/main.unity3d").is_err());
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_diff_bundles() {
+    use crate::bundle::{AssetBundle, BundleFileDiff};
+
+    let file = |name: &str, data: &[u8]| (name.to_string(), data.to_vec());
+    let first = AssetBundle::from_files(vec![
+        vec![
+            file("same", b"1"),
+            file("resized", b"12"),
+            file("changed", b"ab"),
+        ],
+        vec![file("removed", b"x")],
+    ]);
+    let second = AssetBundle::from_files(vec![
+        vec![
+            file("same", b"1"),
+            file("resized", b"123"),
+            file("changed", b"ac"),
+        ],
+        vec![],
+        vec![file("added", b"y")],
+    ]);
+    assert!(first.diff(&first).is_empty());
+
+    let diffs = first.diff(&second);
+    assert_eq!(diffs.len(), 4);
+    assert_eq!(
+        diffs[0],
+        BundleFileDiff::SizeMismatch {
+            level: 0,
+            name: "resized".to_string(),
+            size: 2,
+            other_size: 3,
+        }
+    );
+    assert!(matches!(&diffs[1], BundleFileDiff::HashMismatch { name, .. } if name == "changed"));
+    assert_eq!(
+        diffs[2],
+        BundleFileDiff::Removed {
+            level: 1,
+            name: "removed".to_string(),
+        }
+    );
+    assert_eq!(
+        diffs[3],
+        BundleFileDiff::Added {
+            level: 2,
+            name: "added".to_string(),
+        }
+    );
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_sorted() {
+    use crate::bundle::AssetBundle;
+
+    let input_dir = TempDir::new();
+    for name in ["b", "c", "a"] {
+        std::fs::write(format!("{}/{}", input_dir.path(), name), name).unwrap();
+    }
+
+    let bundle = AssetBundle::from_directory(input_dir.path()).unwrap();
+    let expected = AssetBundle::from_files(vec![["a", "b", "c"]
+        .iter()
+        .map(|name| (name.to_string(), name.as_bytes().to_vec()))
+        .collect()]);
+    assert!(bundle == expected);
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_layout() {
+    use crate::bundle::{AssetBundle, BUNDLE_LAYOUT_FILE};
+
+    let input_dir = TempDir::new();
+    for name in ["a", "b", "c", "unlisted"] {
+        std::fs::write(format!("{}/{}", input_dir.path(), name), name).unwrap();
+    }
+    std::fs::create_dir(format!("{}/level1", input_dir.path())).unwrap();
+    std::fs::write(format!("{}/level1/d", input_dir.path()), "d").unwrap();
+    let layout = r#"{"levels": [["c", "a", "b"], ["level1/d"]]}"#;
+    std::fs::write(
+        format!("{}/{}", input_dir.path(), BUNDLE_LAYOUT_FILE),
+        layout,
+    )
+    .unwrap();
+
+    let bundle = AssetBundle::from_directory(input_dir.path()).unwrap();
+    let expected = AssetBundle::from_files(vec![
+        vec![
+            ("c".to_string(), b"c".to_vec()),
+            ("a".to_string(), b"a".to_vec()),
+            ("b".to_string(), b"b".to_vec()),
+        ],
+        vec![("d".to_string(), b"d".to_vec())],
+    ]);
+    assert!(bundle == expected);
+
+    std::fs::write(
+        format!("{}/{}", input_dir.path(), BUNDLE_LAYOUT_FILE),
+        r#"{"levels": [["missing"]]}"#,
+    )
+    .unwrap();
+    assert!(AssetBundle::from_directory(input_dir.path()).is_err());
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_pack_bundle_from_files() {
+    use crate::bundle::{AssetBundle, HeaderVersions, LzmaParams};
+
+    let levels = vec![
+        vec![("a".to_string(), vec![1; 5000])],
+        vec![
+            ("b".to_string(), vec![2; 7000]),
+            ("c".to_string(), b"hello".to_vec()),
+        ],
+    ];
+    let bundle = AssetBundle::from_files(levels);
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/bundle.unity3d", output_dir.path());
+    bundle
+        .to_file(
+            &output_path,
+            &LzmaParams::default(),
+            &HeaderVersions::default(),
+            None,
+        )
+        .unwrap();
+
+    let (header, repacked_bundle) = AssetBundle::from_file(&output_path).unwrap();
+    assert!(bundle == repacked_bundle);
+    assert_eq!(header.get_num_levels(), 2);
+    assert_eq!(header.get_level_ends().len(), 2);
+    assert_eq!(header.get_engine_version(), "2.5.4b5");
+    assert_eq!(
+        header.get_bundle_size() as usize,
+        std::fs::metadata(&output_path).unwrap().len() as usize
+    );
+    assert_eq!(repacked_bundle.get_num_files(0).unwrap(), 1);
+    assert_eq!(repacked_bundle.get_num_files(1).unwrap(), 2);
+
+    let buffer = bundle
+        .to_buffer(&LzmaParams::default(), &HeaderVersions::default(), None)
+        .unwrap();
+    assert_eq!(buffer, std::fs::read(&output_path).unwrap());
+
+    let index = repacked_bundle.file_index(true).unwrap();
+    assert_eq!(index.len(), 3);
+    assert_eq!((index["a"], index["b"], index["c"]), (0, 1, 1));
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_bundle_file_index_duplicates() {
+    use crate::bundle::AssetBundle;
+
+    let bundle = AssetBundle::from_files(vec![
+        vec![("a".to_string(), vec![1])],
+        vec![("a".to_string(), vec![2])],
+    ]);
+    assert_eq!(bundle.file_index(false).unwrap()["a"], 0);
+    assert!(bundle.file_index(true).is_err());
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_read_malformed_bundle() {
+    use crate::bundle::AssetBundle;
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let good = std::fs::read(bundle_path).unwrap();
+
+    // header size smaller than the header itself
+    let mut data = good.clone();
+    data[38..42].copy_from_slice(&4u32.to_be_bytes());
+    std::fs::write(&output_path, &data).unwrap();
+    assert!(AssetBundle::from_file(&output_path).is_err());
+
+    // level 0 uncompressed size too small to contain its files
+    let mut data = good.clone();
+    data[54..58].copy_from_slice(&16u32.to_be_bytes());
+    std::fs::write(&output_path, &data).unwrap();
+    assert!(AssetBundle::from_file(&output_path).is_err());
+
+    // header and level counts far larger than the file; nothing is allocated for them
+    let mut data = good.clone();
+    data[38..42].copy_from_slice(&0xFFFF_FFF0u32.to_be_bytes());
+    data[46..50].copy_from_slice(&0x1FFF_FFFEu32.to_be_bytes());
+    std::fs::write(&output_path, &data).unwrap();
+    let err = AssetBundle::from_file(&output_path).unwrap_err();
+    assert!(err.contains("header is"), "{}", err);
+}
+
+#[cfg(feature = "lzma")]
+#[test]
+fn test_read_truncated_bundle() {
+    use crate::bundle::AssetBundle;
+
+    let bundle_path = "example_builds/compressed/good/Map_00_00.unity3d";
+    let output_dir = TempDir::new();
+    let output_path = format!("{}/Map_00_00.unity3d", output_dir.path());
+    let good = std::fs::read(bundle_path).unwrap();
+
+    std::fs::write(&output_path, []).unwrap();
+    let err = AssetBundle::from_file(&output_path).unwrap_err();
+    assert!(err.contains("incomplete"));
+
+    for len in [20, 50, good.len() / 2] {
+        std::fs::write(&output_path, &good[..len]).unwrap();
+        let err = AssetBundle::from_file(&output_path).unwrap_err();
+        assert!(err.contains("truncated"), "{}", err);
+    }
+}
+
+#[test]
+fn test_compression_ratio() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    for (_, bundle_info) in version.get_bundles() {
+        let ratio = bundle_info.compression_ratio().unwrap();
+        assert_eq!(
+            ratio,
+            bundle_info.get_uncompressed_size() as f64 / bundle_info.get_compressed_size() as f64
+        );
+        assert!(ratio > 1.0);
+    }
+    assert_eq!(
+        version.compression_ratio().unwrap(),
+        version.get_uncompressed_assets_size() as f64 / version.get_compressed_assets_size() as f64
+    );
+
+    let bundle_info = crate::BundleInfo::from(crate::FileInfo::default());
+    assert_eq!(bundle_info.compression_ratio(), None);
+}
+
+#[tokio::test]
+async fn test_build_uncompressed() {
+    let asset_root = "example_builds/uncompressed/good/";
+    let version = Version::build_uncompressed(asset_root, "http://example.url/", None, None)
+        .await
+        .unwrap();
+    assert!(version.get_main_file_info().is_none());
+    assert!(version
+        .validate_uncompressed(asset_root, None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(!version
+        .validate_uncompressed("example_builds/uncompressed/bad/", None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // matches the uncompressed info from the compressed bundles, save for the lowercased names
+    let reference = Version::from_manifest_file("example_manifest.json").unwrap();
+    assert_eq!(
+        version.get_uncompressed_assets_size(),
+        reference.get_uncompressed_assets_size()
+    );
+    let bundle_info = version.get_bundle("map_00_00.unity3d").unwrap();
+    let reference_info = reference.get_bundle("Map_00_00.unity3d").unwrap();
+    assert_eq!(
+        bundle_info.get_uncompressed_file_info("BuildPlayer-Map_00_00"),
+        reference_info.get_uncompressed_file_info("BuildPlayer-Map_00_00")
+    );
+
+    // the main file is found under its own name
+    let renamed_root = TempDir::new();
+    crate::util::copy_dir(asset_root, renamed_root.path(), true).unwrap();
+    std::fs::copy(
+        "example_builds/compressed/good/main.unity3d",
+        format!("{}/game.unity3d", renamed_root.path()),
+    )
+    .unwrap();
+    let renamed = Version::build_uncompressed(
+        renamed_root.path(),
+        "http://example.url/",
+        None,
+        Some("game.unity3d"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(renamed.get_main_file_name(), "game.unity3d");
+    assert_eq!(
+        renamed.get_main_file_url().as_deref(),
+        Some("http://example.url/game.unity3d")
+    );
+    assert_eq!(renamed.get_main_file_info(), reference.get_main_file_info());
+
+    assert_eq!(
+        crate::util::url_decode(&crate::util::url_encode("DongResources_00_09.resourceFile")),
+        Some("dongresources_00_09.resourceFile".to_string())
+    );
+    assert_eq!(crate::util::url_decode("bad_zz"), None);
+}
+
+#[tokio::test]
+async fn test_file_failure_has_path() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let bundle_info = version.get_bundle("Map_00_00.unity3d").unwrap();
+    let file_path = "example_builds/compressed/bad/Map_00_00.unity3d";
+    let err = bundle_info
+        .validate_compressed(file_path, None, None, None)
+        .await
+        .unwrap_err();
+    let failure = err.downcast_ref::<crate::FileFailure>().unwrap();
+    assert_eq!(failure.get_path(), file_path);
+    assert!(matches!(
+        failure.get_reason(),
+        crate::FailReason::BadSize { .. }
+    ));
+    assert!(err.to_string().starts_with(file_path));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_policy() {
+    use crate::SymlinkPolicy;
+
+    let source = TempDir::new();
+    let outside = TempDir::new();
+    std::fs::write(format!("{}/real.txt", source.path()), b"real").unwrap();
+    std::fs::write(format!("{}/target.txt", outside.path()), b"target").unwrap();
+    std::fs::create_dir(format!("{}/sub", source.path())).unwrap();
+    std::os::unix::fs::symlink(
+        format!("{}/target.txt", outside.path()),
+        format!("{}/link.txt", source.path()),
+    )
+    .unwrap();
+    // points back at the source, so following it naively never ends
+    std::os::unix::fs::symlink(source.path(), format!("{}/sub/loop", source.path())).unwrap();
+
+    let list = |policy| {
+        crate::util::list_filenames_in_directory_with_policy(source.path(), policy).map(
+            |mut names| {
+                names.sort();
+                names
+            },
+        )
+    };
+    let copy = |dest: &TempDir, policy| {
+        crate::util::copy_dir_with_policy(source.path(), dest.path(), true, policy)
+    };
+
+    assert_eq!(
+        list(SymlinkPolicy::Follow).unwrap(),
+        vec!["link.txt", "real.txt"]
+    );
+    let dest = TempDir::new();
+    copy(&dest, SymlinkPolicy::Follow).unwrap();
+    assert_eq!(
+        std::fs::read(format!("{}/link.txt", dest.path())).unwrap(),
+        b"target"
+    );
+    assert!(!std::fs::exists(format!("{}/sub/loop", dest.path())).unwrap());
+
+    assert_eq!(list(SymlinkPolicy::Skip).unwrap(), vec!["real.txt"]);
+    let dest = TempDir::new();
+    copy(&dest, SymlinkPolicy::Skip).unwrap();
+    assert!(!std::fs::exists(format!("{}/link.txt", dest.path())).unwrap());
+    assert!(std::fs::exists(format!("{}/sub", dest.path())).unwrap());
+
+    let err = list(SymlinkPolicy::Error).unwrap_err();
+    assert!(err.to_string().contains("symlink"));
+    let dest = TempDir::new();
+    assert!(copy(&dest, SymlinkPolicy::Error).is_err());
+}
+
+#[test]
+fn test_export_manifest_stable() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let temp_dir = TempDir::new();
+    let mut exports = Vec::new();
+    for i in 0..2 {
+        // a fresh map each time, so the hash order differs
+        let version: Version =
+            serde_json::from_str(&serde_json::to_string(&version).unwrap()).unwrap();
+        let path = format!("{}/manifest_{}.json", temp_dir.path(), i);
+        version.export_manifest(&path).unwrap();
+        exports.push(std::fs::read_to_string(&path).unwrap());
+    }
+    assert_eq!(exports[0], exports[1]);
+
+    // bundle names appear in sorted order in the raw text
+    let mut names: Vec<&str> = version.get_bundles().map(|(name, _)| name).collect();
+    names.sort();
+    let positions: Vec<usize> = names
+        .iter()
+        .map(|name| exports[0].find(&format!("\"{}\"", name)).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(
+        Version::from_manifest_file(&format!("{}/manifest_0.json", temp_dir.path())).unwrap(),
+        version
+    );
+}
+
+#[tokio::test]
+async fn test_convert_manifest() {
+    let version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let manifest = version.to_uncompressed_manifest();
+    assert_eq!(manifest.get_uuid(), version.get_uuid());
+    let encoded = crate::util::url_encode("Map_00_00.unity3d");
+    assert_eq!(
+        manifest
+            .get_files()
+            .get(&format!("{}/BuildPlayer-Map_00_00", encoded)),
+        version
+            .get_bundle("Map_00_00.unity3d")
+            .unwrap()
+            .get_uncompressed_file_info("BuildPlayer-Map_00_00")
+    );
+
+    let tmp_dir = TempDir::new();
+    let path = format!("{}/uncompressed.json", tmp_dir.path());
+    manifest.export(&path).unwrap();
+    let manifest = crate::UncompressedManifest::from_file(&path).unwrap();
+
+    let converted = Version::from_uncompressed_manifest(&manifest).unwrap();
+    assert_eq!(converted.get_uuid(), version.get_uuid());
+    assert_eq!(
+        converted.get_uncompressed_assets_size(),
+        version.get_uncompressed_assets_size()
+    );
+    assert!(converted
+        .validate_uncompressed("example_builds/uncompressed/good/", None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(converted.to_uncompressed_manifest(), manifest);
+}
+
+#[tokio::test]
+async fn test_temp_file_download_gzip() {
+    let original = std::fs::read("example_manifest.json").unwrap();
+    let body = crate::util::ManifestCompression::Gzip
+        .compress(&original)
+        .unwrap();
+    let server_url =
+        spawn_test_server(move |_| TestResponse::new("200 OK", &["Content-Encoding: gzip"], &body))
+            .await;
+    let url = format!("{}/manifest.json", server_url);
+
+    let temp_file = crate::util::TempFile::download(&url).await.unwrap();
+    assert_eq!(std::fs::read(temp_file.path()).unwrap(), original);
+    let options = crate::OperationOptions::default();
+    assert_eq!(
+        crate::FileInfo::build(&url, &options).await.unwrap(),
+        crate::FileInfo::build("example_manifest.json", &options)
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_validate_main_file() {
+    let mut version = Version::from_manifest_file("example_manifest.json").unwrap();
+    let build_dir = TempDir::new();
+    crate::util::copy_dir("example_builds/compressed/good/", build_dir.path(), false).unwrap();
+    assert!(version
+        .validate_main_file(build_dir.path(), None)
+        .await
+        .unwrap()
+        .is_none());
+
+    std::fs::remove_file(format!("{}/main.unity3d", build_dir.path())).unwrap();
+    assert!(matches!(
+        version
+            .validate_main_file(build_dir.path(), None)
+            .await
+            .unwrap(),
+        Some(crate::FailReason::Missing)
+    ));
+
+    version.main_file_info = None;
+    assert!(version
+        .validate_main_file(build_dir.path(), None)
+        .await
+        .unwrap()
+        .is_none());
+}
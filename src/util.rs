@@ -1,19 +1,455 @@
-use std::{fs::File, io::Write as _, path::Path};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead as _, Read as _, Write as _},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+use base64::prelude::*;
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+    Compression,
+};
 use futures_util::StreamExt;
 use log::*;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::io::AsyncWriteExt as _;
 use uuid::Uuid;
 
-use crate::{Error, ItemProgress, ProgressCallback};
+use crate::{Error, ItemProgress, OperationOptions, ProgressCallback, SymlinkPolicy};
+
+pub(crate) static DOWNLOAD_REQUEST_TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+pub(crate) static DOWNLOAD_TOTAL_TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+pub(crate) static SYMLINK_POLICY: Mutex<SymlinkPolicy> = Mutex::new(SymlinkPolicy::Follow);
+static VALIDATION_IO_PACER: Mutex<Option<Arc<IoPacer>>> = Mutex::new(None);
+
+/// Keeps the overall read speed of everything reading through it under a rate in bytes per second.
+#[derive(Debug)]
+pub(crate) struct IoPacer {
+    bytes_per_sec: u64,
+    next_read: Mutex<Option<Instant>>,
+}
+impl IoPacer {
+    /// Returns `None` for a rate of 0, i.e. full speed.
+    pub(crate) fn new(bytes_per_sec: u64) -> Option<Self> {
+        (bytes_per_sec > 0).then(|| Self {
+            bytes_per_sec,
+            next_read: Mutex::new(None),
+        })
+    }
+
+    /// Blocks until the time budget for `bytes` bytes of reading has been reserved.
+    fn pace(&self, bytes: u64) {
+        // reserve a time slot for this read so concurrent readers share the budget
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let slot_start = {
+            let mut next_read = self.next_read.lock().unwrap();
+            let now = Instant::now();
+            let slot_start = next_read.map_or(now, |next| next.max(now));
+            *next_read = Some(slot_start + cost);
+            slot_start
+        };
+
+        let now = Instant::now();
+        if slot_start > now {
+            std::thread::sleep(slot_start - now);
+        }
+    }
+}
+
+pub(crate) fn set_global_validation_io_rate(bytes_per_sec: u64) {
+    *VALIDATION_IO_PACER.lock().unwrap() = IoPacer::new(bytes_per_sec).map(Arc::new);
+}
+
+/// Returns the pacer for the rate set with `set_validation_io_rate`, if any.
+pub(crate) fn get_global_validation_io_pacer() -> Option<Arc<IoPacer>> {
+    VALIDATION_IO_PACER.lock().unwrap().clone()
+}
 
 pub fn get_file_hash(file_path: &str) -> Result<String, Error> {
+    get_file_hash_paced(file_path, HashAlgorithm::Sha256, None)
+}
+
+/// Same as `get_file_hash`, but with `algorithm` instead of plain SHA-256.
+pub fn get_file_hash_with_algorithm(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+) -> Result<String, Error> {
+    get_file_hash_paced(file_path, algorithm, None)
+}
+
+/// Same as `get_file_hash_with_algorithm`, but keeps reads under the rate of `pacer`, if any.
+/// Pacing blocks the thread, so from async code, call this in `spawn_blocking`.
+pub(crate) fn get_file_hash_paced(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+    pacer: Option<&IoPacer>,
+) -> Result<String, Error> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
     let file = File::open(file_path)?;
+    #[cfg(feature = "parallel-hash")]
+    if algorithm == HashAlgorithm::Sha256Tree && pacer.is_none() {
+        let size = file.metadata()?.len();
+        if size >= PARALLEL_HASH_THRESHOLD {
+            return Ok(get_file_tree_hash_parallel(file_path, size)?);
+        }
+    }
+
     let mut reader = std::io::BufReader::new(file);
-    let mut hasher = Sha256::new();
-    std::io::copy(&mut reader, &mut hasher)?;
-    Ok(format!("{:x}", hasher.finalize()))
+    let mut hasher = Hasher::new(algorithm);
+    let Some(pacer) = pacer else {
+        std::io::copy(&mut reader, &mut hasher)?;
+        return Ok(hasher.finalize());
+    };
+    loop {
+        let copied = std::io::copy(&mut (&mut reader).take(CHUNK_SIZE), &mut hasher)?;
+        pacer.pace(copied);
+        if copied < CHUNK_SIZE {
+            break;
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// Size of the chunks hashed separately by `HashAlgorithm::Sha256Tree`.
+const TREE_HASH_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+/// Files at least this large have their tree hash chunks hashed on several threads at once.
+#[cfg(feature = "parallel-hash")]
+pub const PARALLEL_HASH_THRESHOLD: u64 = 64 * 1024 * 1024;
+const TREE_HASH_PREFIX: &str = "sha256-tree:";
+
+/// How a file's hash is computed. Hashes say which algorithm made them, so a manifest can mix both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// Plain SHA-256, written as 64 hex digits.
+    #[default]
+    Sha256,
+    /// The SHA-256 of the SHA-256 of each 16 MiB chunk of the file, written as `sha256-tree:`
+    /// followed by 64 hex digits. With the `parallel-hash` feature, the chunks of large files are
+    /// hashed on several threads, which is much faster for the biggest files in a build.
+    Sha256Tree,
+}
+impl HashAlgorithm {
+    /// Returns the algorithm that made `hash`.
+    pub fn of(hash: &str) -> Self {
+        match hash.starts_with(TREE_HASH_PREFIX) {
+            true => Self::Sha256Tree,
+            false => Self::Sha256,
+        }
+    }
+}
+
+/// Hashes data as it comes in with either `HashAlgorithm`.
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Sha256Tree {
+        chunk: Sha256,
+        chunk_len: u64,
+        chunk_digests: Sha256,
+        num_chunks: u64,
+    },
+}
+impl Hasher {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha256Tree => Self::Sha256Tree {
+                chunk: Sha256::new(),
+                chunk_len: 0,
+                chunk_digests: Sha256::new(),
+                num_chunks: 0,
+            },
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        let (chunk, chunk_len, chunk_digests, num_chunks) = match self {
+            Self::Sha256(hasher) => return hasher.update(data),
+            Self::Sha256Tree {
+                chunk,
+                chunk_len,
+                chunk_digests,
+                num_chunks,
+            } => (chunk, chunk_len, chunk_digests, num_chunks),
+        };
+        while !data.is_empty() {
+            let take = data.len().min((TREE_HASH_CHUNK_SIZE - *chunk_len) as usize);
+            chunk.update(&data[..take]);
+            *chunk_len += take as u64;
+            data = &data[take..];
+            if *chunk_len == TREE_HASH_CHUNK_SIZE {
+                chunk_digests.update(chunk.finalize_reset());
+                *chunk_len = 0;
+                *num_chunks += 1;
+            }
+        }
+    }
+
+    pub(crate) fn finalize(self) -> String {
+        match self {
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Sha256Tree {
+                chunk,
+                chunk_len,
+                mut chunk_digests,
+                num_chunks,
+            } => {
+                // an empty file is one empty chunk, but a file that fills its last chunk has no empty one after it
+                if chunk_len > 0 || num_chunks == 0 {
+                    chunk_digests.update(chunk.finalize());
+                }
+                format!("{}{:x}", TREE_HASH_PREFIX, chunk_digests.finalize())
+            }
+        }
+    }
+}
+impl std::io::Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Computes the `HashAlgorithm::Sha256Tree` hash of the `size`-byte file at `file_path`,
+/// hashing its chunks on one thread per CPU.
+#[cfg(feature = "parallel-hash")]
+fn get_file_tree_hash_parallel(file_path: &str, size: u64) -> std::io::Result<String> {
+    use std::io::Seek as _;
+
+    let num_chunks = size.div_ceil(TREE_HASH_CHUNK_SIZE).max(1);
+    let next_chunk = AtomicU64::new(0);
+    let hash_chunks = || -> std::io::Result<Vec<(u64, Vec<u8>)>> {
+        let mut file = File::open(file_path)?;
+        let mut digests = Vec::new();
+        loop {
+            let idx = next_chunk.fetch_add(1, Ordering::Relaxed);
+            if idx >= num_chunks {
+                return Ok(digests);
+            }
+            let start = idx * TREE_HASH_CHUNK_SIZE;
+            file.seek(std::io::SeekFrom::Start(start))?;
+            let mut hasher = Sha256::new();
+            let expected = TREE_HASH_CHUNK_SIZE.min(size - start);
+            let mut reader = std::io::BufReader::new((&mut file).take(expected));
+            if std::io::copy(&mut reader, &mut hasher)? != expected {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    format!("{} shrank while it was being hashed", file_path),
+                ));
+            }
+            digests.push((idx, hasher.finalize().to_vec()));
+        }
+    };
+
+    let num_threads = num_cpus::get().min(num_chunks as usize);
+    let mut digests = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads).map(|_| scope.spawn(hash_chunks)).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<std::io::Result<Vec<_>>>()
+    })?
+    .concat();
+    digests.sort_by_key(|(idx, _)| *idx);
+
+    let mut chunk_digests = Sha256::new();
+    for (_, digest) in digests {
+        chunk_digests.update(digest);
+    }
+    Ok(format!(
+        "{}{:x}",
+        TREE_HASH_PREFIX,
+        chunk_digests.finalize()
+    ))
+}
+
+/// Serializes a map with its keys in order, so the output doesn't change from run to run.
+pub(crate) fn serialize_sorted<S, K, V>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    K: Ord + Serialize,
+    V: Serialize,
+{
+    let sorted: std::collections::BTreeMap<&K, &V> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct HashCacheEntry {
+    /// Nanoseconds since the Unix epoch.
+    mtime: u64,
+    size: u64,
+    hash: String,
+}
+
+/// An on-disk cache of file hashes, keyed by the file's path, modification time and size.
+/// Pass one to a single operation with `OperationOptions::with_hash_cache`,
+/// or enable one for the whole process with `set_hash_cache_path`.
+#[derive(Debug)]
+pub struct HashCache {
+    path: String,
+    state: Mutex<HashCacheState>,
+}
+#[derive(Debug)]
+struct HashCacheState {
+    entries: HashMap<String, HashCacheEntry>,
+    dirty: bool,
+}
+impl HashCache {
+    /// Loads the hash cache at `path`, starting an empty one if the file doesn't exist yet.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let entries = if std::fs::exists(path)? {
+            let json = std::fs::read_to_string(path)?;
+            serde_json::from_str(&json)
+                .map_err(|e| format!("Couldn't parse hash cache {}: {}", path, e))?
+        } else {
+            HashMap::new()
+        };
+        debug!("Loaded {} hash cache entries from {}", entries.len(), path);
+        Ok(Self {
+            path: path.to_string(),
+            state: Mutex::new(HashCacheState {
+                entries,
+                dirty: false,
+            }),
+        })
+    }
+
+    /// Same as `get_file_hash`, but reuses the cached hash if the file's modification time and size are unchanged.
+    pub fn get_file_hash(&self, file_path: &str) -> Result<String, Error> {
+        self.get_file_hash_paced(file_path, HashAlgorithm::Sha256, None)
+    }
+
+    /// Same as `get_file_hash`, but with `algorithm`, and files that aren't cached are read under the rate of `pacer`.
+    /// A cached hash made with another algorithm is replaced.
+    pub(crate) fn get_file_hash_paced(
+        &self,
+        file_path: &str,
+        algorithm: HashAlgorithm,
+        pacer: Option<&IoPacer>,
+    ) -> Result<String, Error> {
+        let key = std::fs::canonicalize(file_path)?
+            .to_string_lossy()
+            .to_string();
+        let metadata = std::fs::metadata(file_path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let size = metadata.len();
+
+        if let Some(entry) = self.state.lock().unwrap().entries.get(&key) {
+            if entry.mtime == mtime
+                && entry.size == size
+                && HashAlgorithm::of(&entry.hash) == algorithm
+            {
+                return Ok(entry.hash.clone());
+            }
+        }
+
+        let hash = get_file_hash_paced(file_path, algorithm, pacer)?;
+        let mut state = self.state.lock().unwrap();
+        let entry = HashCacheEntry {
+            mtime,
+            size,
+            hash: hash.clone(),
+        };
+        state.entries.insert(key, entry);
+        state.dirty = true;
+        Ok(hash)
+    }
+
+    /// Writes the cache back to disk if it has changed since it was loaded or last saved.
+    pub fn save(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state.dirty {
+            return Ok(());
+        }
+
+        // write to a temp file first so an interrupted save doesn't corrupt the cache
+        let temp_path = format!("{}.tmp", self.path);
+        std::fs::write(&temp_path, serde_json::to_string(&state.entries)?)?;
+        std::fs::rename(&temp_path, &self.path)?;
+        state.dirty = false;
+        Ok(())
+    }
+}
+
+static HASH_CACHE: Mutex<Option<Arc<HashCache>>> = Mutex::new(None);
+
+/// Enables the on-disk hash cache at `path` for the whole process, loading any existing entries.
+pub(crate) fn load_hash_cache(path: &str) -> Result<(), Error> {
+    *HASH_CACHE.lock().unwrap() = Some(Arc::new(HashCache::load(path)?));
+    Ok(())
+}
+
+/// Returns the hash cache set with `set_hash_cache_path`, if any.
+pub(crate) fn get_global_hash_cache() -> Option<Arc<HashCache>> {
+    HASH_CACHE.lock().unwrap().clone()
+}
+
+/// Same as `get_file_hash`, but consults the hash cache set with `set_hash_cache_path` first if it's enabled.
+/// Cached hashes are only reused if the file's modification time and size are unchanged.
+pub fn get_file_hash_cached(file_path: &str) -> Result<String, Error> {
+    get_file_hash_with_cache(
+        file_path,
+        HashAlgorithm::Sha256,
+        get_global_hash_cache().as_deref(),
+        None,
+    )
+}
+
+/// Same as `get_file_hash_paced`, but consults `cache` first if there is one.
+pub(crate) fn get_file_hash_with_cache(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+    cache: Option<&HashCache>,
+    pacer: Option<&IoPacer>,
+) -> Result<String, Error> {
+    match cache {
+        Some(cache) => cache.get_file_hash_paced(file_path, algorithm, pacer),
+        None => get_file_hash_paced(file_path, algorithm, pacer),
+    }
+}
+
+/// Hashes the file in consecutive chunks of `block_size` bytes. The last block may be shorter.
+pub fn get_file_block_hashes(file_path: &str, block_size: u64) -> Result<Vec<String>, Error> {
+    if block_size == 0 {
+        return Err("Block size must be non-zero".into());
+    }
+
+    let file = File::open(file_path)?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut hashes = Vec::new();
+    let mut buf = Vec::with_capacity(block_size as usize);
+    loop {
+        buf.clear();
+        let read = (&mut reader).take(block_size).read_to_end(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hashes.push(get_buffer_hash(&buf));
+        if (read as u64) < block_size {
+            break;
+        }
+    }
+    Ok(hashes)
 }
 
 pub fn get_buffer_hash(buffer: &[u8]) -> String {
@@ -22,6 +458,76 @@ pub fn get_buffer_hash(buffer: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Compression applied to a manifest file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ManifestCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+impl ManifestCompression {
+    /// Picks the compression from the extension, i.e. `.json.gz` or `.json.zst`.
+    pub(crate) fn from_path(path: &str) -> Self {
+        // ignore any query string in URLs
+        let path = path.split('?').next().unwrap_or(path);
+        if path.ends_with(".gz") {
+            Self::Gzip
+        } else if path.ends_with(".zst") {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+
+    pub(crate) fn from_content_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "zstd" => Some(Self::Zstd),
+            "identity" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn compress(&self, buffer: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::None => Ok(buffer.to_vec()),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(buffer)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::encode_all(buffer, 19)?),
+        }
+    }
+
+    pub(crate) fn decompress(&self, buffer: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::None => Ok(buffer.to_vec()),
+            Self::Gzip => {
+                let mut decompressed = Vec::new();
+                GzDecoder::new(buffer).read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+            Self::Zstd => Ok(zstd::decode_all(buffer)?),
+        }
+    }
+}
+
+/// Deflates the buffer and encodes the result as base64.
+pub fn deflate_base64(buffer: &[u8]) -> Result<String, Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(buffer)?;
+    Ok(BASE64_STANDARD.encode(encoder.finish()?))
+}
+
+/// Decodes a base64 string and inflates the result. Inverse of `deflate_base64`.
+pub fn inflate_base64(encoded: &str) -> Result<Vec<u8>, Error> {
+    let compressed = BASE64_STANDARD.decode(encoded)?;
+    let mut buffer = Vec::new();
+    DeflateDecoder::new(compressed.as_slice()).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
 pub fn get_file_extension(file_path: &str) -> Option<&str> {
     Path::new(file_path)
         .extension()
@@ -42,11 +548,44 @@ pub fn get_file_name_without_parent(file_path: &str) -> &str {
         .unwrap_or(file_path)
 }
 
+/// Returns the symlink policy set with `set_symlink_policy`.
+pub(crate) fn get_symlink_policy() -> SymlinkPolicy {
+    *SYMLINK_POLICY.lock().unwrap()
+}
+
+/// Returns whether `path` should be listed or copied under `policy`,
+/// or an error if it's a symlink and the policy forbids them.
+fn check_symlink(path: &Path, policy: SymlinkPolicy) -> Result<bool, Error> {
+    if !std::fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return Ok(true);
+    }
+    match policy {
+        SymlinkPolicy::Follow => Ok(true),
+        SymlinkPolicy::Skip => {
+            debug!("Skipping symlink {}", path.display());
+            Ok(false)
+        }
+        SymlinkPolicy::Error => Err(format!("{} is a symlink", path.display()).into()),
+    }
+}
+
+/// Lists the files in a directory, handling symlinks under the policy set with `set_symlink_policy`.
 pub fn list_filenames_in_directory(directory_path: &str) -> Result<Vec<String>, Error> {
+    list_filenames_in_directory_with_policy(directory_path, get_symlink_policy())
+}
+
+/// Same as `list_filenames_in_directory`, but with a symlink policy that overrides the global one.
+pub fn list_filenames_in_directory_with_policy(
+    directory_path: &str,
+    symlinks: SymlinkPolicy,
+) -> Result<Vec<String>, Error> {
     let mut filenames = Vec::new();
     for entry in std::fs::read_dir(directory_path)? {
         let entry = entry?;
         let path = entry.path();
+        if !check_symlink(&path, symlinks)? {
+            continue;
+        }
         if path.is_file() {
             let name = path.file_name().unwrap().to_str().unwrap();
             filenames.push(name.to_string());
@@ -55,26 +594,125 @@ pub fn list_filenames_in_directory(directory_path: &str) -> Result<Vec<String>,
     Ok(filenames)
 }
 
+pub(crate) static TEMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Returns the directory `TempFile` and `TempDir` are created in:
+/// the one set with `set_temp_dir`, or the system temp directory.
+pub fn get_temp_dir() -> PathBuf {
+    TEMP_DIR.get().cloned().unwrap_or_else(std::env::temp_dir)
+}
+
+pub(crate) static BUNDLE_STORE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Returns the bundle store entry name for `hash`, if it looks like a SHA-256 or tree hash.
+fn bundle_store_key(hash: &str) -> Option<String> {
+    let (prefix, hex) = match hash.strip_prefix(TREE_HASH_PREFIX) {
+        Some(hex) => ("tree-", hex),
+        None => ("", hash),
+    };
+    (hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| format!("{}{}", prefix, hex))
+}
+
+/// Copies `from` to `file_path` through a temporary file, so `file_path` is never left half-written.
+pub(crate) fn copy_to_file(from: &str, file_path: &str) -> Result<(), Error> {
+    let part_path = format!("{}.part", file_path);
+    if let Err(e) = std::fs::copy(from, &part_path) {
+        let _ = std::fs::remove_file(&part_path);
+        return Err(e.into());
+    }
+    std::fs::rename(&part_path, file_path)?;
+    Ok(())
+}
+
+/// Copies the bundle with the given hash from the bundle store at `store` to `file_path`, if the
+/// store has it. Returns whether the bundle was found.
+pub(crate) fn fetch_from_bundle_store(
+    store: &Path,
+    hash: &str,
+    file_path: &str,
+) -> Result<bool, Error> {
+    let Some(key) = bundle_store_key(hash) else {
+        return Err(format!("Invalid bundle hash {}", hash).into());
+    };
+    let stored_path = store.join(key);
+    if !stored_path.exists() {
+        return Ok(false);
+    }
+
+    copy_to_file(&stored_path.to_string_lossy(), file_path)?;
+    Ok(true)
+}
+
+/// Copies the bundle at `file_path` into the bundle store at `store` under `hash`, creating the store if needed.
+/// Any existing entry is only replaced if `replace` is set.
+pub(crate) fn add_to_bundle_store(
+    store: &Path,
+    file_path: &str,
+    hash: &str,
+    replace: bool,
+) -> Result<(), Error> {
+    let Some(key) = bundle_store_key(hash) else {
+        return Err(format!("Invalid bundle hash {}", hash).into());
+    };
+    let stored_path = store.join(key);
+    if !replace && stored_path.exists() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(store)?;
+    copy_to_file(file_path, &stored_path.to_string_lossy())?;
+    Ok(())
+}
+
 /// RAII struct for temporary files
 pub struct TempFile {
     path: String,
 }
 impl TempFile {
+    /// Downloads the file at `url` to a new temporary file. `file://` URLs are copied.
+    /// If the server applied a `Content-Encoding`, the file is stored decoded.
     pub async fn download(url: &str) -> Result<Self, Error> {
-        let permit = if let Some(permits) = crate::DOWNLOAD_PERMITS.get() {
-            Some(permits.acquire().await.unwrap())
-        } else {
-            None
-        };
+        Self::download_in(url, &get_temp_dir()).await
+    }
 
-        let response = reqwest::get(url).await?;
+    /// Same as `download`, but creates the temporary file in `dir` instead of the temp directory.
+    pub async fn download_in(url: &str, dir: &Path) -> Result<Self, Error> {
+        Self::download_with_options(url, dir, &OperationOptions::default()).await
+    }
+
+    /// Same as `download_in`, with the download limit of `options`.
+    pub(crate) async fn download_with_options(
+        url: &str,
+        dir: &Path,
+        options: &OperationOptions,
+    ) -> Result<Self, Error> {
         let filename = Uuid::new_v4().to_string();
-        let path = std::env::temp_dir().join(filename);
+        let path = dir.join(filename);
+        if let Some(source_path) = file_uri_to_path(url) {
+            tokio::fs::copy(&source_path, &path).await?;
+            return Ok(Self {
+                path: path.to_string_lossy().to_string(),
+            });
+        }
+
+        let permit = options.acquire_download().await;
+
+        let response = reqwest::get(url).await?;
+        let compression = match response.headers().get(reqwest::header::CONTENT_ENCODING) {
+            Some(encoding) => {
+                let encoding = encoding.to_str().unwrap_or_default();
+                ManifestCompression::from_content_encoding(encoding).ok_or_else(|| {
+                    format!("Unsupported content encoding for {}: {}", url, encoding)
+                })?
+            }
+            None => ManifestCompression::None,
+        };
         let mut file = File::create(&path)?;
         let bytes = response.bytes().await?;
         drop(permit);
 
-        file.write_all(&bytes)?;
+        file.write_all(&compression.decompress(&bytes)?)?;
         Ok(Self {
             path: path.to_string_lossy().to_string(),
         })
@@ -101,8 +739,13 @@ impl Default for TempDir {
 }
 impl TempDir {
     pub fn new() -> Self {
+        Self::new_in(&get_temp_dir())
+    }
+
+    /// Creates the temporary directory in `parent` instead of the temp directory.
+    pub fn new_in(parent: &Path) -> Self {
         let dir_name = Uuid::new_v4().to_string();
-        let path = std::env::temp_dir().join(dir_name);
+        let path = parent.join(dir_name);
         std::fs::create_dir(&path).unwrap();
         Self {
             path: path.to_string_lossy().to_string(),
@@ -135,17 +778,467 @@ pub fn url_encode(input: &str) -> String {
     output
 }
 
+/// Reverses `url_encode`. Since encoding lowercases everything up to the first escaped character,
+/// that part of the original name can't be recovered and is returned lowercase.
+/// Returns `None` if `input` isn't a valid encoded name.
+pub fn url_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut chars = input.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'_' {
+            let hex = [chars.next()?, chars.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else if byte.is_ascii_alphanumeric() {
+            bytes.push(byte);
+        } else {
+            return None;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
 pub async fn download_to_file(
     associated_uuid: Option<Uuid>,
     url: &str,
     file_path: &str,
     callback: Option<ProgressCallback>,
 ) -> Result<(), Error> {
-    info!("Downloading {} to {}", url, file_path);
+    download_to_file_internal(
+        associated_uuid,
+        url,
+        file_path,
+        callback,
+        false,
+        HashAlgorithm::Sha256,
+        &OperationOptions::default(),
+        (1, 1),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Like `download_to_file`, but returns the SHA-256 hash of the downloaded file.
+/// The hash is computed as the file is written, so it doesn't need to be read back from disk.
+pub async fn download_to_file_hashed(
+    associated_uuid: Option<Uuid>,
+    url: &str,
+    file_path: &str,
+    callback: Option<ProgressCallback>,
+) -> Result<String, Error> {
+    let hash = download_to_file_internal(
+        associated_uuid,
+        url,
+        file_path,
+        callback,
+        false,
+        HashAlgorithm::Sha256,
+        &OperationOptions::default(),
+        (1, 1),
+    )
+    .await?;
+    Ok(hash.expect("only a conditional download can be skipped"))
+}
+
+/// Like `download_to_file`, but sends a conditional request keyed off the local file's
+/// modification time and leaves the file untouched if the server reports it hasn't changed.
+/// Returns whether the file was downloaded. `file://` URLs are always copied.
+pub async fn download_to_file_if_modified(
+    associated_uuid: Option<Uuid>,
+    url: &str,
+    file_path: &str,
+    callback: Option<ProgressCallback>,
+) -> Result<bool, Error> {
+    let hash = download_to_file_internal(
+        associated_uuid,
+        url,
+        file_path,
+        callback,
+        true,
+        HashAlgorithm::Sha256,
+        &OperationOptions::default(),
+        (1, 1),
+    )
+    .await?;
+    Ok(hash.is_some())
+}
+
+/// Returns the size of the file at `url` without downloading it, using a HEAD request for HTTP URLs.
+/// Returns `None` if the server doesn't report a size, or only the size after applying a
+/// `Content-Encoding`. `file://` URLs are read from disk.
+pub async fn remote_file_size(url: &str) -> Result<Option<u64>, Error> {
+    if let Some(path) = file_uri_to_path(url) {
+        return Ok(Some(std::fs::metadata(path)?.len()));
+    }
+
+    let response = reqwest::Client::new()
+        .head(url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let encoded = response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .is_some_and(|encoding| encoding != "identity");
+    if encoded {
+        return Ok(None);
+    }
+    // `content_length` reflects the (empty) body for HEAD responses, so read the header instead
+    let size = response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse().ok());
+    Ok(size)
+}
+
+static MIRROR_STATS: Mutex<Option<HashMap<String, MirrorStats>>> = Mutex::new(None);
+
+/// Weight of the newest download in a mirror's rolling throughput.
+const MIRROR_THROUGHPUT_SMOOTHING: f64 = 0.3;
+
+/// Rolling download stats for a mirror, i.e. the origin (scheme, host and port) files were downloaded from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorStats {
+    pub origin: String,
+    /// Exponentially weighted average of recent download speeds, in bytes per second.
+    pub bytes_per_sec: f64,
+    pub downloads: u64,
+    pub failures: u64,
+    /// Failures since the last successful download.
+    pub consecutive_failures: u64,
+}
+impl MirrorStats {
+    fn new(origin: String) -> Self {
+        Self {
+            origin,
+            bytes_per_sec: 0.0,
+            downloads: 0,
+            failures: 0,
+            consecutive_failures: 0,
+        }
+    }
+}
+
+fn get_origin(url: &str) -> Option<String> {
+    Some(
+        reqwest::Url::parse(url)
+            .ok()?
+            .origin()
+            .ascii_serialization(),
+    )
+}
+
+fn update_mirror_stats(url: &str, update: impl FnOnce(&mut MirrorStats)) {
+    let Some(origin) = get_origin(url) else {
+        return;
+    };
+    let mut stats = MIRROR_STATS.lock().unwrap();
+    let stats = stats
+        .get_or_insert_with(HashMap::new)
+        .entry(origin.clone())
+        .or_insert_with(|| MirrorStats::new(origin));
+    update(stats);
+}
+
+pub(crate) fn record_mirror_download(url: &str, bytes: u64, duration: Duration) {
+    let bytes_per_sec = bytes as f64 / duration.as_secs_f64().max(0.001);
+    update_mirror_stats(url, |stats| {
+        stats.bytes_per_sec = if stats.downloads == 0 {
+            bytes_per_sec
+        } else {
+            MIRROR_THROUGHPUT_SMOOTHING * bytes_per_sec
+                + (1.0 - MIRROR_THROUGHPUT_SMOOTHING) * stats.bytes_per_sec
+        };
+        stats.downloads += 1;
+        stats.consecutive_failures = 0;
+    });
+}
+
+pub(crate) fn record_mirror_failure(url: &str) {
+    update_mirror_stats(url, |stats| {
+        stats.failures += 1;
+        stats.consecutive_failures += 1;
+    });
+}
+
+/// Returns the stats of every mirror downloaded from so far, best first.
+/// Mirrors that are currently failing rank below working ones; the rest are ranked by throughput.
+pub fn get_mirror_rankings() -> Vec<MirrorStats> {
+    let stats = MIRROR_STATS.lock().unwrap();
+    let mut rankings: Vec<MirrorStats> = stats.iter().flat_map(|s| s.values().cloned()).collect();
+    rankings.sort_by(|a, b| {
+        a.consecutive_failures
+            .cmp(&b.consecutive_failures)
+            .then(b.bytes_per_sec.total_cmp(&a.bytes_per_sec))
+    });
+    rankings
+}
+
+/// Forgets the stats of every mirror, e.g. after a network change made them meaningless.
+pub fn reset_mirror_stats() {
+    *MIRROR_STATS.lock().unwrap() = None;
+}
+
+/// Picks the mirror to download from out of `candidates`, ranked like `get_mirror_rankings`.
+/// Mirrors that haven't been downloaded from yet rank above working ones, so each of them gets measured.
+/// Ties go to the earliest candidate.
+pub(crate) fn select_mirror<'a>(candidates: &[&'a str]) -> Option<&'a str> {
+    let stats = MIRROR_STATS.lock().unwrap();
+    let rank = |candidate: &str| {
+        let stats = get_origin(candidate).and_then(|origin| stats.as_ref()?.get(&origin).cloned());
+        match stats {
+            Some(stats) => (stats.consecutive_failures, stats.bytes_per_sec),
+            None => (0, f64::INFINITY),
+        }
+    };
+    candidates
+        .iter()
+        .map(|candidate| (*candidate, rank(candidate)))
+        .min_by(|(_, a), (_, b)| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)))
+        .map(|(candidate, _)| candidate)
+}
+
+/// Returns the size of the file at `url` and, if `hash` is given, its hash with that algorithm,
+/// without saving it anywhere. When only the size is needed, just the first byte of an HTTP file is requested.
+pub(crate) async fn fetch_remote_file_info(
+    url: &str,
+    hash: Option<HashAlgorithm>,
+    options: &OperationOptions,
+) -> Result<(u64, Option<String>), Error> {
+    if let Some(path) = file_uri_to_path(url) {
+        let size = std::fs::metadata(&path)?.len();
+        let hash = match hash {
+            Some(algorithm) => Some(get_file_hash_with_algorithm(&path, algorithm)?),
+            None => None,
+        };
+        return Ok((size, hash));
+    }
+
+    let _permit = options.acquire_download().await;
+    let client = options.get_http_client()?;
+    let Some(algorithm) = hash else {
+        let response = client
+            .get(url)
+            .header(reqwest::header::RANGE, "bytes=0-0")
+            .send()
+            .await?
+            .error_for_status()?;
+        // partial responses report the full size after the slash, e.g. `bytes 0-0/1234`.
+        // servers that ignore the range send the whole file, so its length is the size
+        let size = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|range| range.to_str().ok())
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|size| size.parse().ok())
+        } else {
+            response.content_length()
+        };
+        let size = size.ok_or(format!("{} didn't report a size", url))?;
+        return Ok((size, None));
+    };
 
+    let response = client.get(url).send().await?.error_for_status()?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut size = 0;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+    }
+    Ok((size, Some(hasher.finalize())))
+}
+
+/// Compares a stream of reference bytes against a local file as they come in.
+struct MismatchFinder {
+    reader: std::io::BufReader<File>,
+    hasher: Hasher,
+    offset: u64,
+    first_mismatch: Option<u64>,
+}
+impl MismatchFinder {
+    fn update(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.hasher.update(chunk);
+        if self.first_mismatch.is_none() {
+            let mut local = Vec::with_capacity(chunk.len());
+            (&mut self.reader)
+                .take(chunk.len() as u64)
+                .read_to_end(&mut local)?;
+            let differs_at = chunk
+                .iter()
+                .zip(&local)
+                .position(|(a, b)| a != b)
+                .or((local.len() < chunk.len()).then_some(local.len()));
+            self.first_mismatch = differs_at.map(|i| self.offset + i as u64);
+        }
+        self.offset += chunk.len() as u64;
+        Ok(())
+    }
+}
+
+/// Streams `reference` (a local path, `file://` URI or HTTP URL) alongside the local file at `file_path`.
+/// Returns the offset of the first byte that differs, if any, along with the `algorithm` hash of the reference.
+pub(crate) async fn find_first_mismatch(
+    file_path: &str,
+    reference: &str,
+    algorithm: HashAlgorithm,
+) -> Result<(Option<u64>, String), Error> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    let mut finder = MismatchFinder {
+        reader: std::io::BufReader::new(File::open(file_path)?),
+        hasher: Hasher::new(algorithm),
+        offset: 0,
+        first_mismatch: None,
+    };
+    if is_http_url(reference) {
+        let response = reqwest::get(reference).await?.error_for_status()?;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            finder.update(&chunk?)?;
+        }
+    } else {
+        let path = file_uri_to_path(reference).unwrap_or(reference.to_string());
+        let mut reader = File::open(path)?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            finder.update(&buf[..read])?;
+        }
+    }
+
+    // the local file is longer than the reference
+    if finder.first_mismatch.is_none() && !finder.reader.fill_buf()?.is_empty() {
+        finder.first_mismatch = Some(finder.offset);
+    }
+    Ok((finder.first_mismatch, finder.hasher.finalize()))
+}
+
+/// Parses the output of `sha256sum` into file names and lowercase hashes.
+/// Blank lines and lines starting with `#` are skipped.
+pub(crate) fn parse_sha256sums(text: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut sums = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // the hash and name are separated by a space and a mode character: ' ' for text, '*' for binary
+        let parsed = line.split_once(' ').and_then(|(hash, rest)| {
+            let name = rest.strip_prefix([' ', '*'])?;
+            let valid_hash = hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit());
+            valid_hash.then_some((hash, name.strip_prefix("./").unwrap_or(name)))
+        });
+        let Some((hash, name)) = parsed else {
+            return Err(format!("Bad checksum line {}: {}", i + 1, line).into());
+        };
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(format!("Bad file name on checksum line {}: {}", i + 1, name).into());
+        }
+        sums.push((name.to_string(), hash.to_ascii_lowercase()));
+    }
+    Ok(sums)
+}
+
+/// A download was refused with HTTP 429. The mirror asked us to wait `retry_after` before trying again.
+#[derive(Debug)]
+pub(crate) struct RateLimited {
+    pub(crate) retry_after: Duration,
+}
+impl RateLimited {
+    /// Used when the mirror doesn't send a `Retry-After` header.
+    const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+    /// Mirrors asking for longer than this are treated as having asked for this long.
+    const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+}
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Rate limited; retry after {:.1}s",
+            self.retry_after.as_secs_f64()
+        )
+    }
+}
+impl std::error::Error for RateLimited {}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Returns the `algorithm` hash of the downloaded file, or `None` if a conditional download was skipped.
+/// `attempt` is the attempt number and the maximum number of attempts, for progress reports.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn download_to_file_internal(
+    associated_uuid: Option<Uuid>,
+    url: &str,
+    file_path: &str,
+    callback: Option<ProgressCallback>,
+    conditional: bool,
+    algorithm: HashAlgorithm,
+    options: &OperationOptions,
+    attempt: (usize, usize),
+) -> Result<Option<String>, Error> {
+    // download under a temporary name so a failed download never leaves a truncated file behind
+    // and nothing else can observe a half-written one
+    let part_path = format!("{}.part", file_path);
+    match download_to_part_file(
+        associated_uuid,
+        url,
+        file_path,
+        &part_path,
+        callback,
+        conditional,
+        algorithm,
+        options,
+        attempt,
+    )
+    .await
+    {
+        Ok(Some(hash)) => {
+            std::fs::rename(&part_path, file_path)?;
+            Ok(Some(hash))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => {
+            let _ = std::fs::remove_file(&part_path);
+            if file_uri_to_path(url).is_none() {
+                record_mirror_failure(url);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_to_part_file(
+    associated_uuid: Option<Uuid>,
+    url: &str,
+    file_path: &str,
+    part_path: &str,
+    callback: Option<ProgressCallback>,
+    conditional: bool,
+    algorithm: HashAlgorithm,
+    options: &OperationOptions,
+    (attempt, max_attempts): (usize, usize),
+) -> Result<Option<String>, Error> {
     let uuid = associated_uuid.unwrap_or(Uuid::nil());
+    options.wait_if_paused().await;
+    info!("[{}] Downloading {} to {}", uuid, url, file_path);
+
     let file_name = get_file_name_without_parent(file_path);
-    let mut file = tokio::fs::File::create(file_path).await?;
+    let mut hasher = Hasher::new(algorithm);
 
     if let Some(ref callback) = callback {
         callback(
@@ -154,14 +1247,15 @@ pub async fn download_to_file(
             ItemProgress::Downloading {
                 bytes_downloaded: 0,
                 total_bytes: 0,
+                attempt,
+                max_attempts,
             },
         );
     }
 
     // If the url is a file path, copy the file instead of downloading it
-    if url.starts_with("file:///") {
-        let path = url.trim_start_matches("file:///");
-        let size = std::fs::metadata(path)?.len();
+    if let Some(path) = file_uri_to_path(url) {
+        let size = std::fs::metadata(&path)?.len();
         if let Some(ref callback) = callback {
             callback(
                 &uuid,
@@ -169,11 +1263,16 @@ pub async fn download_to_file(
                 ItemProgress::Downloading {
                     bytes_downloaded: 0,
                     total_bytes: size,
+                    attempt,
+                    max_attempts,
                 },
             );
         }
-        let reader = tokio::fs::read(path).await?;
+        let reader = tokio::fs::read(&path).await?;
+        let mut file = tokio::fs::File::create(part_path).await?;
         file.write_all(&reader).await?;
+        file.flush().await?;
+        hasher.update(&reader);
         if let Some(ref callback) = callback {
             callback(
                 &uuid,
@@ -181,17 +1280,43 @@ pub async fn download_to_file(
                 ItemProgress::Downloading {
                     bytes_downloaded: size,
                     total_bytes: size,
+                    attempt,
+                    max_attempts,
                 },
             );
         }
     } else {
-        let _permit = if let Some(permits) = crate::DOWNLOAD_PERMITS.get() {
-            Some(permits.acquire().await.unwrap())
-        } else {
-            None
-        };
+        let _permit = options.acquire_download().await;
 
-        let response = reqwest::get(url).await?;
+        let mut timer = DownloadTimer::new(options);
+        let mut request = options.get_http_client()?.get(url);
+        if conditional {
+            if let Ok(modified) = std::fs::metadata(file_path).and_then(|m| m.modified()) {
+                request = request.header(
+                    reqwest::header::IF_MODIFIED_SINCE,
+                    httpdate::fmt_http_date(modified),
+                );
+            }
+        }
+
+        let response = timer.run(url, request.send()).await??;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            info!("[{}] {} not modified; keeping local copy", uuid, file_name);
+            return Ok(None);
+        }
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after)
+                .unwrap_or(RateLimited::DEFAULT_RETRY_AFTER)
+                .min(RateLimited::MAX_RETRY_AFTER);
+            return Err(RateLimited { retry_after }.into());
+        }
+        let response = response.error_for_status()?;
+
+        let mut file = tokio::fs::File::create(part_path).await?;
         let total_size = response.content_length().unwrap_or(0);
         if let Some(ref callback) = callback {
             callback(
@@ -200,26 +1325,83 @@ pub async fn download_to_file(
                 ItemProgress::Downloading {
                     bytes_downloaded: 0,
                     total_bytes: total_size,
+                    attempt,
+                    max_attempts,
                 },
             );
         }
 
+        let start = Instant::now();
         let mut downloaded_size = 0;
         let mut stream = response.bytes_stream();
-        while let Some(chunk) = stream.next().await {
+        let mut paused = Duration::ZERO;
+        loop {
+            let paused_now = options.wait_if_paused().await;
+            paused += paused_now;
+            timer.extend(paused_now);
+            let Some(chunk) = timer.run(url, stream.next()).await? else {
+                break;
+            };
             let chunk = chunk?;
             file.write_all(&chunk).await?;
+            hasher.update(&chunk);
             downloaded_size += chunk.len() as u64;
             let progress = ItemProgress::Downloading {
                 bytes_downloaded: downloaded_size,
                 total_bytes: total_size,
+                attempt,
+                max_attempts,
             };
             if let Some(ref callback) = callback {
                 callback(&uuid, file_name, progress);
             }
         }
+        file.flush().await?;
+        // time spent paused says nothing about the mirror
+        record_mirror_download(url, downloaded_size, start.elapsed() - paused);
+    }
+    Ok(Some(hasher.finalize()))
+}
+
+/// Returns the download timeouts set with `set_download_timeout`.
+pub(crate) fn get_global_download_timeouts() -> (Option<Duration>, Option<Duration>) {
+    let get_timeout = |ms: &AtomicU64| {
+        Some(Duration::from_millis(ms.load(Ordering::Relaxed))).filter(|t| !t.is_zero())
+    };
+    (
+        get_timeout(&DOWNLOAD_REQUEST_TIMEOUT_MS),
+        get_timeout(&DOWNLOAD_TOTAL_TIMEOUT_MS),
+    )
+}
+
+/// Tracks the total download timeout of an operation for a single download attempt.
+/// The per-request timeout is set on the operation's HTTP client instead.
+struct DownloadTimer {
+    deadline: Option<Instant>,
+}
+impl DownloadTimer {
+    fn new(options: &OperationOptions) -> Self {
+        let (_, total_timeout) = options.get_download_timeouts();
+        Self {
+            deadline: total_timeout.map(|t| Instant::now() + t),
+        }
+    }
+
+    /// Pushes the deadline back, e.g. to not count time spent paused.
+    fn extend(&mut self, by: Duration) {
+        self.deadline = self.deadline.map(|deadline| deadline + by);
+    }
+
+    /// Awaits `future`, failing if it runs past the deadline for the whole download.
+    async fn run<F: std::future::Future>(&self, url: &str, future: F) -> Result<F::Output, Error> {
+        let Some(deadline) = self.deadline else {
+            return Ok(future.await);
+        };
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| format!("Timed out downloading {}", url).into())
     }
-    Ok(())
 }
 
 pub fn create_dir_if_needed(path: &str) -> Result<(), Error> {
@@ -229,7 +1411,19 @@ pub fn create_dir_if_needed(path: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// Copies the files in `from` into `to`, descending into subdirectories if `recursive` is set.
+/// Symlinks inside `from` are handled according to the symlink policy.
 pub fn copy_dir(from: &str, to: &str, recursive: bool) -> Result<(), Error> {
+    copy_dir_with_policy(from, to, recursive, get_symlink_policy())
+}
+
+/// Same as `copy_dir`, but with a symlink policy that overrides the global one.
+pub fn copy_dir_with_policy(
+    from: &str,
+    to: &str,
+    recursive: bool,
+    symlinks: SymlinkPolicy,
+) -> Result<(), Error> {
     let from = Path::new(from);
     let to = Path::new(to);
 
@@ -238,24 +1432,62 @@ pub fn copy_dir(from: &str, to: &str, recursive: bool) -> Result<(), Error> {
     }
 
     if from.is_dir() {
-        for entry in std::fs::read_dir(from)? {
-            let entry = entry?;
-            let path = entry.path();
-            let new_path = to.join(path.file_name().unwrap());
-            if path.is_dir() {
-                if recursive {
-                    copy_dir(&path.to_string_lossy(), &new_path.to_string_lossy(), true)?;
-                }
-            } else {
-                std::fs::copy(&path, &new_path)?;
-            }
-        }
+        copy_dir_internal(from, to, recursive, symlinks, &mut Vec::new())
     } else {
         std::fs::copy(from, to)?;
+        Ok(())
+    }
+}
+
+/// `ancestors` holds the canonical paths of the directories being copied, to catch symlink loops.
+fn copy_dir_internal(
+    from: &Path,
+    to: &Path,
+    recursive: bool,
+    symlinks: SymlinkPolicy,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    ancestors.push(from.canonicalize()?);
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !check_symlink(&path, symlinks)? {
+            continue;
+        }
+        let new_path = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            if !recursive {
+                continue;
+            }
+            if ancestors.contains(&path.canonicalize()?) {
+                warn!("Skipping {}: symlink loop", path.display());
+                continue;
+            }
+            std::fs::create_dir_all(&new_path)?;
+            copy_dir_internal(&path, &new_path, true, symlinks, ancestors)?;
+        } else {
+            std::fs::copy(&path, &new_path)?;
+        }
     }
+    ancestors.pop();
     Ok(())
 }
 
+/// Whether `url` is an HTTP(S) URL, as opposed to e.g. a local path that happens to start with `http`.
+pub(crate) fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Returns the local path a `file://` URI points to, or `None` if `uri` isn't a `file://` URI.
+/// Inverse of `file_path_to_uri`.
+pub fn file_uri_to_path(uri: &str) -> Option<String> {
+    let url = reqwest::Url::parse(uri)
+        .ok()
+        .filter(|url| url.scheme() == "file")?;
+    let path = url.to_file_path().ok()?;
+    Some(path.to_string_lossy().to_string())
+}
+
 pub fn file_path_to_uri(file_path: &str) -> String {
     let path = file_path.to_string();
     // Replace backslashes with forward slashes
@@ -266,9 +1498,39 @@ pub fn file_path_to_uri(file_path: &str) -> String {
     format!("file:///{}", path)
 }
 
-pub fn bytes_to_human_readable(bytes: u32) -> String {
+/// Formats the time as an RFC 3339 timestamp in UTC with second precision, e.g. `2024-11-02T18:30:00Z`.
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // days since the epoch to a proleptic Gregorian date (Howard Hinnant's civil_from_days)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+pub fn bytes_to_human_readable(bytes: impl Into<u64>) -> String {
     let units = ["B", "KB", "MB", "GB"];
-    let mut bytes = bytes as f64;
+    let mut bytes = bytes.into() as f64;
     let mut unit = 0;
     while bytes >= 1024.0 && unit < units.len() - 1 {
         bytes /= 1024.0;